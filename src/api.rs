@@ -1,83 +1,364 @@
-use std::{error::Error, str::Utf8Error, string::FromUtf8Error};
+use std::{collections::HashMap, error::Error, fmt::{self, Display}, str::{FromStr, Utf8Error}, string::FromUtf8Error, sync::Arc, thread, time::Duration};
 
+use parking_lot::Mutex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned};
 
-use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use serde_repr::Deserialize_repr;
+use crate::{cache::MetadataCache, http::{HttpClient, HttpResponse, MinreqClient}, output, proxy::ProxyConfig, ratelimit::RateLimiter, search::{self, SearchQuery, SearchResults}};
+
+// CF adds values to these without warning, so anything unrecognised becomes Unknown instead of failing the whole response.
+// each variant also gets the name it's shown as to users
+macro_rules! api_enum {
+	(pub enum $name:ident { $($variant:ident = $value:literal => $label:literal),+ $(,)? }) => {
+		#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+		pub enum $name {
+			$($variant,)+
+			Unknown(u8)
+		}
+
+		impl From<u8> for $name {
+			fn from(value: u8) -> Self {
+				match value {
+					$($value => Self::$variant,)+
+					other => Self::Unknown(other)
+				}
+			}
+		}
+
+		impl From<$name> for u8 {
+			fn from(value: $name) -> Self {
+				match value {
+					$($name::$variant => $value,)+
+					$name::Unknown(other) => other
+				}
+			}
+		}
+
+		impl Display for $name {
+			fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+				match self {
+					$($name::$variant => f.write_str($label),)+
+					$name::Unknown(other) => write!(f, "Unknown ({})", other)
+				}
+			}
+		}
+
+		impl Serialize for $name {
+			fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+				serializer.serialize_u8((*self).into())
+			}
+		}
+
+		impl<'de> Deserialize<'de> for $name {
+			fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				u8::deserialize(deserializer).map(Self::from)
+			}
+		}
+	};
+}
 
 pub const CF_BASE_URL: &str = "https://api.curseforge.com/v1/";
 
+// for failures is_retryable() says are worth another go. the delay doubles each time
+pub(crate) const API_ATTEMPTS: u32 = 3;
+pub(crate) const API_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub(crate) const CACHE_MODS: &str = "mods";
+pub(crate) const CACHE_FILES: &str = "files";
+
+pub const GAME_MINECRAFT: i32 = 432;
+
+pub(crate) const CDN_BASE_URL: &str = "https://edge.forgecdn.net/files";
+
+pub const CLASS_MODS: i32 = 6;
+pub const CLASS_RESOURCE_PACKS: i32 = 12;
+pub const CLASS_WORLDS: i32 = 17;
+pub const CLASS_MODPACKS: i32 = 4471;
+pub const CLASS_CUSTOMIZATION: i32 = 4546;
+pub const CLASS_SHADERS: i32 = 6552;
+pub const CLASS_DATA_PACKS: i32 = 6945;
+
 #[derive(Clone)]
 pub struct Curseforge {
-	key: String
+	key: String,
+	// always ends in a slash, request paths are appended straight on
+	base_url: String,
+	proxy: ProxyConfig,
+	cache: Option<MetadataCache>,
+	// shared by every clone, so worker threads all draw from the same budget
+	rate_limit: Option<Arc<RateLimiter>>,
+	// API requests only, downloads go through `proxy` whatever this is
+	http: Arc<dyn HttpClient>,
+	// slugs already looked up by get_mod_by_slug, shared by clones like rate_limit. only kept for this run
+	slugs: Arc<Mutex<HashMap<String, i32>>>
+}
+
+// by hand so the key can't leak through a {:?}
+impl fmt::Debug for Curseforge {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("Curseforge")
+			.field("key", &"***")
+			.field("base_url", &self.base_url)
+			.field("proxy", &self.proxy)
+			.field("cache", &self.cache)
+			.finish_non_exhaustive()
+	}
 }
 
 impl Curseforge {
 	pub fn new(key: String) -> Self {
+		output::add_secret(&key);
 		Self {
-			key
+			key,
+			base_url: CF_BASE_URL.into(),
+			proxy: ProxyConfig::default(),
+			cache: None,
+			rate_limit: None,
+			http: Arc::new(MinreqClient::default()),
+			slugs: Arc::default()
 		}
 	}
 
+	pub fn with_cache(mut self, cache: Option<MetadataCache>) -> Self {
+		self.cache = cache;
+		self
+	}
+
+	// in requests per second. cache hits don't count
+	pub fn with_rate_limit(mut self, per_second: Option<u64>) -> Self {
+		self.rate_limit = per_second.map(|r| Arc::new(RateLimiter::new(r)));
+		self
+	}
+
+	// for a caching proxy, a mock server, or anything else that speaks the same API
+	pub fn with_base_url(mut self, base_url: &str) -> Self {
+		self.base_url = format!("{}/", base_url.trim_end_matches('/'));
+		self
+	}
+
+	// replaces the MinreqClient for API requests. the proxy setting is then up to the client,
+	// though downloads still go through proxy()
+	pub fn with_http_client(mut self, http: Arc<dyn HttpClient>) -> Self {
+		self.http = http;
+		self
+	}
+
+	// also swaps in a MinreqClient using it, so call with_http_client after this, not before
+	pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+		self.http = Arc::new(MinreqClient::new(proxy.clone()));
+		self.proxy = proxy;
+		self
+	}
+
+	pub fn proxy(&self) -> &ProxyConfig {
+		&self.proxy
+	}
+
 	pub fn get_mod(&self, project_id: i32) -> Result<Mod, ApiError> {
+		if let Some(m) = self.cache.as_ref().and_then(|c| c.get(CACHE_MODS, project_id)) {
+			return Ok(m);
+		}
 		let query_url = format!("mods/{}", project_id);
 		let result: DataResponse<Mod> = self.api_get(&query_url)?;
+		if let Some(c) = &self.cache {
+			c.put(CACHE_MODS, project_id, &result.data);
+		}
 		Ok(result.data)
 	}
 
 	pub fn get_mods(&self, mod_ids: &[i32]) -> Result<Vec<Mod>, ApiError> {
-		let body = GetModsBody{mod_ids};
-		let result: DataResponse<Vec<Mod>> = self.api_post("mods", &body)?;
-		Ok(result.data)
+		self.batch_cached(CACHE_MODS, mod_ids, |m: &Mod| m.id, |mod_ids| {
+			let body = GetModsBody{mod_ids};
+			let result: DataResponse<Vec<Mod>> = self.api_post("mods", &body)?;
+			Ok(result.data)
+		})
 	}
 
 	pub fn get_mod_file(&self, project_id: i32, file_id: i32) -> Result<File, ApiError> {
+		if let Some(f) = self.cache.as_ref().and_then(|c| c.get(CACHE_FILES, file_id)) {
+			return Ok(f);
+		}
 		let query_url = format!("mods/{}/files/{}", project_id, file_id);
 		let result: DataResponse<File> = self.api_get(&query_url)?;
+		if let Some(c) = &self.cache {
+			c.put(CACHE_FILES, file_id, &result.data);
+		}
 		Ok(result.data)
 	}
 
 	pub fn get_files(&self, file_ids: &[i32]) -> Result<Vec<File>, ApiError> {
-		let body = GetFilesBody{file_ids};
-		let result: DataResponse<Vec<File>> = self.api_post("mods/files", &body)?;
+		self.batch_cached(CACHE_FILES, file_ids, |f: &File| f.id, |file_ids| {
+			let body = GetFilesBody{file_ids};
+			let result: DataResponse<Vec<File>> = self.api_post("mods/files", &body)?;
+			Ok(result.data)
+		})
+	}
+
+	// serves what it can from the cache and only asks the API for the rest
+	fn batch_cached<T, K, F>(&self, kind: &str, ids: &[i32], key: K, fetch: F) -> Result<Vec<T>, ApiError> where
+			T: Serialize + DeserializeOwned,
+			K: Fn(&T) -> i32,
+			F: FnOnce(&[i32]) -> Result<Vec<T>, ApiError> {
+		if ids.is_empty() {
+			return Ok(Vec::new());
+		}
+		let cache = match &self.cache {
+			Some(c) => c,
+			None => return fetch(ids)
+		};
+		let mut found = Vec::new();
+		let mut missing = Vec::new();
+		for &id in ids {
+			match cache.get(kind, id) {
+				Some(t) => found.push(t),
+				None => missing.push(id)
+			}
+		}
+		if !missing.is_empty() {
+			let fetched = fetch(&missing)?;
+			for t in &fetched {
+				cache.put(kind, key(t), t);
+			}
+			found.extend(fetched);
+		}
+		Ok(found)
+	}
+
+	pub fn get_mod_files(&self, project_id: i32, game_version: Option<&str>, loader: Option<ModLoaderType>) -> Result<Vec<File>, ApiError> {
+		let mut query_url = format!("mods/{}/files?pageSize=50", project_id);
+		if let Some(v) = game_version {
+			query_url.push_str(&format!("&gameVersion={}", v));
+		}
+		if let Some(l) = loader {
+			query_url.push_str(&format!("&modLoaderType={}", u8::from(l)));
+		}
+		let result: DataResponse<Vec<File>> = self.api_get(&query_url)?;
+		Ok(result.data)
+	}
+
+	pub fn search_mods(&self, class_id: Option<i32>, slug: &str) -> Result<Vec<Mod>, ApiError> {
+		let mut query_url = format!("mods/search?gameId={}&slug={}", GAME_MINECRAFT, search::encode(slug));
+		if let Some(c) = class_id {
+			query_url.push_str(&format!("&classId={}", c));
+		}
+		let result: DataResponse<Vec<Mod>> = self.api_get(&query_url)?;
 		Ok(result.data)
 	}
 
+	// the one project with exactly this slug, of any class. CF's slug filter also matches on prefixes, so the
+	// results are checked for an exact match, and a slug used by more than one class is an error
+	pub fn get_mod_by_slug(&self, slug: &str) -> Result<Mod, ApiError> {
+		let known = self.slugs.lock().get(slug).copied();
+		if let Some(id) = known {
+			return self.get_mod(id);
+		}
+		let mut matches: Vec<Mod> = self.search_mods(None, slug)?.into_iter().filter(|m| m.slug == slug).collect();
+		match matches.len() {
+			0 => Err(ApiError::SlugNotFound(slug.to_string())),
+			1 => {
+				let m = matches.remove(0);
+				self.slugs.lock().insert(slug.to_string(), m.id);
+				Ok(m)
+			},
+			_ => Err(ApiError::AmbiguousSlug(slug.to_string(), matches.iter().map(|m| m.id).collect()))
+		}
+	}
+
+	// one page of results. search_all is usually easier
+	pub fn search_page(&self, query: &SearchQuery, index: u32, page_size: u32) -> Result<(Vec<Mod>, Pagination), ApiError> {
+		let query_url = format!("mods/search?gameId={}&index={}&pageSize={}{}", GAME_MINECRAFT, index, page_size, query.params());
+		let result: PagedResponse<Vec<Mod>> = self.api_get(&query_url)?;
+		Ok((result.data, result.pagination))
+	}
+
+	// every result, fetched lazily a page at a time, stopping after `limit` mods if given
+	pub fn search_all(&self, query: SearchQuery, limit: Option<usize>) -> SearchResults<'_> {
+		SearchResults::new(self, query, limit)
+	}
+
 	pub fn get_download_url(&self, project_id: i32, file_id: i32) -> Result<String, ApiError> {
 		let query_url = format!("mods/{}/files/{}/download-url", project_id, file_id);
 		let result: DataResponse<String> = self.api_get(&query_url)?;
 		Ok(result.data)
 	}
 
+	// the project page's full description, HTML like the changelogs. Mod::summary is the one-line version
+	pub fn get_mod_description(&self, project_id: i32) -> Result<String, ApiError> {
+		let query_url = format!("mods/{}/description", project_id);
+		let result: DataResponse<String> = self.api_get(&query_url)?;
+		Ok(result.data)
+	}
+
+	// HTML, as the author wrote it on the CF site
+	pub fn get_file_changelog(&self, project_id: i32, file_id: i32) -> Result<String, ApiError> {
+		let query_url = format!("mods/{}/files/{}/changelog", project_id, file_id);
+		let result: DataResponse<String> = self.api_get(&query_url)?;
+		Ok(result.data)
+	}
+
+	// the cheapest request that needs a valid key, never cached. a bad key comes back as ForbiddenError
+	pub fn check_key(&self) -> Result<(), ApiError> {
+		let query_url = format!("games/{}", GAME_MINECRAFT);
+		let _: DataResponse<serde_json::Value> = self.api_get(&query_url)?;
+		Ok(())
+	}
+
+	// the api hides the url of files whose author disabled third-party downloads, but the CDN still serves them
+	// at a path built from the file id: 3752735 -> 3752/735
+	pub fn cdn_url(file_id: i32, file_name: &str) -> String {
+		format!("{}/{}/{}/{}", CDN_BASE_URL, file_id / 1000, file_id % 1000, file_name)
+	}
+
+	fn throttle(&self) {
+		if let Some(limiter) = &self.rate_limit {
+			limiter.acquire(1);
+		}
+	}
+
 	fn api_get<T: DeserializeOwned>(&self, suburl: &str) -> Result<T, ApiError> {
-		let query_url = format!("{}{}", CF_BASE_URL, suburl);
-		let response = minreq::get(&query_url)
-			.with_header("x-api-key", &self.key)
-			.send()
-			.ctx_error(&query_url)?;
-		response.status_code.ctx_error(&query_url)?;
-		let vec = response.into_bytes();
-		let response = String::from_utf8(vec).ctx_error(&query_url)?;
-		serde_json::from_str(&response).ctx_error((&query_url, &response))
+		let query_url = format!("{}{}", self.base_url, suburl);
+		let headers = [("x-api-key", self.key.as_str())];
+		self.with_retries(|| {
+			self.throttle();
+			log::debug!("GET {}", query_url);
+			parse_response(self.http.get(&query_url, &headers)?, &query_url)
+		})
 	}
 
 	fn api_post<T: DeserializeOwned, U: Serialize>(&self, suburl: &str, body: &U) -> Result<T, ApiError> {
-		let query_url = format!("{}{}", CF_BASE_URL, suburl);
+		let query_url = format!("{}{}", self.base_url, suburl);
 		let query_body = serde_json::to_string(body).ctx_error((&query_url, "N/A"))?;
-		let response = minreq::post(&query_url)
-			.with_header("x-api-key", &self.key)
-			.with_header("Content-Type", "application/json")
-			.with_header("Accept", "application/json")
-			.with_body(query_body.as_bytes())
-			.send()
-			.ctx_error(&query_url)?;
-		response.status_code.ctx_error(&query_url)?;
-		let vec = response.into_bytes();
-		let response = String::from_utf8(vec).ctx_error(&query_url)?;
-		serde_json::from_str(&response).ctx_error((&query_url, &response))
+		let headers = [("x-api-key", self.key.as_str()), ("Content-Type", "application/json"), ("Accept", "application/json")];
+		self.with_retries(|| {
+			self.throttle();
+			log::debug!("POST {} {}", query_url, query_body);
+			parse_response(self.http.post(&query_url, &headers, query_body.as_bytes())?, &query_url)
+		})
+	}
+
+	fn with_retries<T>(&self, mut attempt: impl FnMut() -> Result<T, ApiError>) -> Result<T, ApiError> {
+		let mut delay = API_RETRY_DELAY;
+		for _ in 1..API_ATTEMPTS {
+			match attempt() {
+				Err(e) if e.is_retryable() => {
+					output::warn(format!("{}, retrying in {}s", e, delay.as_secs()));
+					thread::sleep(delay);
+					delay *= 2;
+				},
+				result => return result
+			}
+		}
+		attempt()
 	}
 }
 
-#[derive(Deserialize, Clone, Debug)]
+pub(crate) fn parse_response<T: DeserializeOwned>(response: HttpResponse, query_url: &str) -> Result<T, ApiError> {
+	log::debug!("{} responded {}", query_url, response.status);
+	response.status.ctx_error(query_url)?;
+	let response = String::from_utf8(response.body).ctx_error(query_url)?;
+	serde_json::from_str(&response).ctx_error((query_url, response.as_str()))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct File {
 	pub id: i32,
 	#[serde(rename = "gameId")]
@@ -102,7 +383,7 @@ pub struct File {
 	#[serde(rename = "downloadCount")]
 	pub download_count: i64,
 	#[serde(rename = "downloadUrl")]
-	pub download_url: String,
+	pub download_url: Option<String>, // null when the author has opted out of third-party downloads
 	#[serde(rename = "gameVersions")]
 	pub game_versions: Vec<String>,
 	#[serde(rename = "sortableGameVersions")]
@@ -123,7 +404,15 @@ pub struct File {
 	pub modules: Vec<FileModule>
 }
 
-#[derive(Clone, Deserialize, Debug)]
+impl File {
+	// CF's gameVersion filter also lets through files that only list a nearby version, so results are checked
+	// against the versions the file itself lists
+	pub fn lists_game_version(&self, version: &str) -> bool {
+		self.game_versions.iter().any(|v| v == version) || self.sortable_game_versions.iter().any(|v| v.game_version == version)
+	}
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Mod {
 	pub id: i32,
 	#[serde(rename = "gameId")]
@@ -167,7 +456,7 @@ pub struct Mod {
 	pub thumbs_up_count: Option<i32>
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Category {
 	pub id: i32,
 	#[serde(rename = "gameId")]
@@ -189,7 +478,7 @@ pub struct Category {
 	pub display_index: Option<i32>
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FileIndex {
 	#[serde(rename = "gameVersion")]
 	pub game_version: String,
@@ -204,7 +493,7 @@ pub struct FileIndex {
 	pub mod_loader: Option<ModLoaderType>
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ModAsset {
 	pub id: i32,
 	#[serde(rename = "modId")]
@@ -216,14 +505,14 @@ pub struct ModAsset {
 	pub url: String
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ModAuthor {
 	pub id: i32,
 	pub name: String,
 	pub url: String
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ModLinks {
 	#[serde(rename = "websiteUrl")]
 	pub website_url: Option<String>,
@@ -235,13 +524,13 @@ pub struct ModLinks {
 	pub source_url: Option<String>
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FileModule {
 	pub name: String,
 	pub fingerprint: i64
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FileDependency {
 	#[serde(rename = "modId")]
 	pub mod_id: i32,
@@ -249,7 +538,7 @@ pub struct FileDependency {
 	pub relation_type: FileRelationType
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct SortableGameVersion {
 	#[serde(rename = "gameVersionName")]
 	pub game_version_name: String,
@@ -263,100 +552,166 @@ pub struct SortableGameVersion {
 	pub game_version_type_id: Option<i32>
 }
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct FileHash {
 	pub value: String,
 	pub algo: HashAlgo
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr)]
-#[repr(u8)]
-pub enum ModLoaderType {
-	Any = 0,
-	Forge = 1,
-	Cauldron = 2,
-	LiteLoader = 3,
-	Fabric = 4
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr)]
-#[repr(u8)]
-pub enum ModStatus {
-	New = 1,
-	ChangesRequired = 2,
-	UnderSoftReview = 3,
-	Approved = 4,
-	Rejected = 5,
-	ChangesMade = 6,
-	Inactive = 7,
-	Abandoned = 8,
-	Deleted = 9,
-	UnderReview = 10
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr)]
-#[repr(u8)]
-pub enum FileRelationType {
-	EmbeddedLibrary = 1,
-	OptionalDependency = 2,
-	RequiredDependency = 3,
-	Tool = 4,
-	Incompatible = 5,
-	Include = 6
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr)]
-#[repr(u8)]
-pub enum FileReleaseType {
-	Release = 1,
-	Beta = 2,
-	Alpha = 3
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr)]
-#[repr(u8)]
-pub enum FileStatus {
-	Processing = 1,
-	ChangesRequired = 2,
-	UnderReview = 3,
-	Approved = 4,
-	Rejected = 5,
-	MalwareDetected = 6,
-	Deleted = 7,
-	Archived = 8,
-	Testing = 9,
-	Released = 10,
-	ReadyForReview = 11,
-	Deprecated = 12,
-	Baking = 13,
-	AwaitingPublishing = 14,
-	FailedPublishing = 15
-}
-
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize_repr)]
-#[repr(u8)]
-pub enum HashAlgo {
-	Sha1 = 1,
-	Md5 = 2
+api_enum! {
+	pub enum ModLoaderType {
+		Any = 0 => "Any",
+		Forge = 1 => "Forge",
+		Cauldron = 2 => "Cauldron",
+		LiteLoader = 3 => "LiteLoader",
+		Fabric = 4 => "Fabric",
+		Quilt = 5 => "Quilt",
+		NeoForge = 6 => "NeoForge"
+	}
+}
+
+api_enum! {
+	pub enum ModStatus {
+		New = 1 => "New",
+		ChangesRequired = 2 => "Changes required",
+		UnderSoftReview = 3 => "Under soft review",
+		Approved = 4 => "Approved",
+		Rejected = 5 => "Rejected",
+		ChangesMade = 6 => "Changes made",
+		Inactive = 7 => "Inactive",
+		Abandoned = 8 => "Abandoned",
+		Deleted = 9 => "Deleted",
+		UnderReview = 10 => "Under review"
+	}
+}
+
+api_enum! {
+	pub enum FileRelationType {
+		EmbeddedLibrary = 1 => "Embedded library",
+		OptionalDependency = 2 => "Optional dependency",
+		RequiredDependency = 3 => "Required dependency",
+		Tool = 4 => "Tool",
+		Incompatible = 5 => "Incompatible",
+		Include = 6 => "Include"
+	}
+}
+
+api_enum! {
+	pub enum FileReleaseType {
+		Release = 1 => "Release",
+		Beta = 2 => "Beta",
+		Alpha = 3 => "Alpha"
+	}
+}
+
+api_enum! {
+	pub enum FileStatus {
+		Processing = 1 => "Processing",
+		ChangesRequired = 2 => "Changes required",
+		UnderReview = 3 => "Under review",
+		Approved = 4 => "Approved",
+		Rejected = 5 => "Rejected",
+		MalwareDetected = 6 => "Malware detected",
+		Deleted = 7 => "Deleted",
+		Archived = 8 => "Archived",
+		Testing = 9 => "Testing",
+		Released = 10 => "Released",
+		ReadyForReview = 11 => "Ready for review",
+		Deprecated = 12 => "Deprecated",
+		Baking = 13 => "Baking",
+		AwaitingPublishing = 14 => "Awaiting publishing",
+		FailedPublishing = 15 => "Failed publishing"
+	}
+}
+
+impl FileStatus {
+	pub fn is_released(self) -> bool {
+		matches!(self, Self::Approved | Self::Released)
+	}
+}
+
+impl FromStr for FileReleaseType {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"release" => Self::Release,
+			"beta" => Self::Beta,
+			"alpha" => Self::Alpha,
+			_ => return Err(format!("unknown release type {}, expected release, beta or alpha", s))
+		})
+	}
+}
+
+// kebab-case on the command line, e.g. `malware-detected`
+impl FromStr for FileStatus {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Ok(match s {
+			"processing" => Self::Processing,
+			"changes-required" => Self::ChangesRequired,
+			"under-review" => Self::UnderReview,
+			"approved" => Self::Approved,
+			"rejected" => Self::Rejected,
+			"malware-detected" => Self::MalwareDetected,
+			"deleted" => Self::Deleted,
+			"archived" => Self::Archived,
+			"testing" => Self::Testing,
+			"released" => Self::Released,
+			"ready-for-review" => Self::ReadyForReview,
+			"deprecated" => Self::Deprecated,
+			"baking" => Self::Baking,
+			"awaiting-publishing" => Self::AwaitingPublishing,
+			"failed-publishing" => Self::FailedPublishing,
+			_ => return Err(format!("unknown file status {}", s))
+		})
+	}
+}
+
+api_enum! {
+	pub enum HashAlgo {
+		Sha1 = 1 => "SHA-1",
+		Md5 = 2 => "MD5"
+	}
 }
 
 #[derive(Deserialize, Clone, Copy)]
-struct DataResponse<T> {
-	data: T
+pub(crate) struct DataResponse<T> {
+	pub(crate) data: T
+}
+
+#[derive(Deserialize)]
+pub(crate) struct PagedResponse<T> {
+	pub(crate) data: T,
+	pub(crate) pagination: Pagination
+}
+
+// where a page of search results sits in the whole result set
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct Pagination {
+	pub index: u32,
+	#[serde(rename = "pageSize")]
+	pub page_size: u32,
+	#[serde(rename = "resultCount")]
+	pub result_count: u32,
+	#[serde(rename = "totalCount")]
+	pub total_count: u64
 }
 
 #[derive(Serialize, Clone, Copy)]
-struct GetModsBody<'a> {
+pub(crate) struct GetModsBody<'a> {
 	#[serde(rename = "modIds")]
-	mod_ids: &'a [i32]
+	pub(crate) mod_ids: &'a [i32]
 }
 
 #[derive(Serialize, Clone, Copy)]
-struct GetFilesBody<'a> {
+pub(crate) struct GetFilesBody<'a> {
 	#[serde(rename = "fileIds")]
-	file_ids: &'a [i32]
+	pub(crate) file_ids: &'a [i32]
 }
 
+#[derive(Debug)]
 pub enum ApiError {
 	HTTPError(minreq::Error, String),
 	ResponseParseError(serde_json::Error, String, String),
@@ -365,7 +720,30 @@ pub enum ApiError {
 	ForbiddenError(String),
 	NotFoundError(String),
 	ServerError(String),
-	OtherError(String, Box<dyn Error>)
+	// a custom HttpClient couldn't get a response at all, like HTTPError for minreq
+	TransportError(String, Box<dyn Error + Send + Sync>),
+	OtherError(String, Box<dyn Error + Send + Sync>),
+	SlugNotFound(String),
+	// the slug, and the project ids using it
+	AmbiguousSlug(String, Vec<i32>)
+}
+
+impl ApiError {
+	// whether the same request might work if sent again: network trouble, CF having a bad moment, or
+	// being told to slow down. anything CF actually answered (bad key, missing project, garbage json) won't change
+	pub fn is_retryable(&self) -> bool {
+		match self {
+			Self::HTTPError(..) | Self::TransportError(..) | Self::ServerError(_) => true,
+			Self::BadHTTPResponse(_, code) => *code == 429 || (500..600).contains(code),
+			Self::ForbiddenError(_) | Self::NotFoundError(_) | Self::ResponseParseError(..) | Self::MalformedResponse(..) | Self::OtherError(..)
+				| Self::SlugNotFound(_) | Self::AmbiguousSlug(..) => false
+		}
+	}
+}
+
+// a non-2xx status from outside the API, like a file download, as the same errors the API calls give
+pub(crate) fn check_status(status: i32, url: &str) -> Result<i32, ApiError> {
+	status.ctx_error(url)
 }
 
 impl<T, U, V: ErrorContextualize<U>> ErrorContextualize<U> for Result<T, V> {
@@ -415,7 +793,7 @@ impl ErrorContextualize<(&str, &str)> for serde_json::Error {
 	}
 }
 
-impl ErrorContextualize<&str> for Box<dyn Error> {
+impl ErrorContextualize<&str> for Box<dyn Error + Send + Sync> {
 	type Contextualized = ApiError;
 	fn ctx_error(self, ctx: &str) -> Self::Contextualized {
 		ApiError::OtherError(ctx.to_string(), self)
@@ -436,37 +814,92 @@ impl ErrorContextualize<&str> for FromUtf8Error {
 	}
 }
 
-impl ToString for ApiError {
-	fn to_string(&self) -> String {
+impl Display for ApiError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 		match self {
-			Self::HTTPError(error, url) => format!("Error contacting {}: {}", url, error),
-			Self::ResponseParseError(error, url, response) => format!("Received invalid API data from {}: {}\nResponse:{}", url, error, response),
-			Self::ForbiddenError(url) => format!("URL {} returned 403 Forbidden", url),
-			Self::NotFoundError(url) => format!("URL {} returned 404 Not Found", url),
-			Self::ServerError(url) => format!("URL {} returned 500 Server Error", url),
-			Self::OtherError(url, err) => format!("Error requesting API at {}: {}", url, err),
-			Self::BadHTTPResponse(url, code ) => format!("URL {} returned HTTP error {}", url, code),
-			Self::MalformedResponse(error, url) => format!("URL {} responded with malformed UTF-8: {}", url, error)
+			Self::HTTPError(error, url) => write!(f, "Error contacting {}: {}", url, error),
+			Self::ResponseParseError(error, url, response) => write!(f, "Received invalid API data from {}: {}\nResponse:{}", url, error, response),
+			Self::ForbiddenError(url) => write!(f, "URL {} returned 403 Forbidden", url),
+			Self::NotFoundError(url) => write!(f, "URL {} returned 404 Not Found", url),
+			Self::ServerError(url) => write!(f, "URL {} returned 500 Server Error", url),
+			Self::TransportError(url, err) => write!(f, "Error contacting {}: {}", url, err),
+			Self::OtherError(url, err) => write!(f, "Error requesting API at {}: {}", url, err),
+			Self::BadHTTPResponse(url, code ) => write!(f, "URL {} returned HTTP error {}", url, code),
+			Self::MalformedResponse(error, url) => write!(f, "URL {} responded with malformed UTF-8: {}", url, error),
+			Self::SlugNotFound(slug) => write!(f, "No project with slug {}", slug),
+			Self::AmbiguousSlug(slug, ids) => write!(f, "Slug {} is used by more than one project ({}), use the project ID instead",
+				slug, ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", "))
 		}
 	}
 }
 
-pub trait ErrorStringify {
-	type Stringified;
-	fn stringify_error(self, prefix: &str) -> Self::Stringified;
-}
-
-impl<T, S: ToString> ErrorStringify for Result<T, S> {
-	type Stringified = Result<T, String>;
-	fn stringify_error(self, prefix: &str) -> Self::Stringified {
-		match self {
-			Ok(v) => Ok(v),
-			Err(e) => Err(format!("{}: {}", prefix, e.to_string()))
-		}
-	}
-}
+impl Error for ApiError {}
 
 trait ErrorContextualize<T> {
 	type Contextualized;
 	fn ctx_error(self, ctx: T) -> Self::Contextualized;
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn unknown_enum_values_are_kept() {
+		assert_eq!(serde_json::from_str::<FileStatus>("99").unwrap(), FileStatus::Unknown(99));
+		assert_eq!(serde_json::from_str::<ModLoaderType>("255").unwrap(), ModLoaderType::Unknown(255));
+		assert_eq!(serde_json::from_str::<FileReleaseType>("2").unwrap(), FileReleaseType::Beta);
+		// and written back out as the same number
+		assert_eq!(serde_json::to_string(&FileRelationType::Unknown(42)).unwrap(), "42");
+		assert!(serde_json::from_str::<FileStatus>("256").is_err());
+	}
+
+	#[test]
+	fn enum_display() {
+		assert_eq!(FileStatus::MalwareDetected.to_string(), "Malware detected");
+		assert_eq!(ModLoaderType::NeoForge.to_string(), "NeoForge");
+		assert_eq!(FileStatus::Unknown(99).to_string(), "Unknown (99)");
+		assert_eq!(HashAlgo::Unknown(7).to_string(), "Unknown (7)");
+	}
+
+	// a file whose author opted out of third-party downloads, as the API returns it minus the optional fields
+	const OPTED_OUT_FILE: &str = r#"{
+		"id": 3752735, "gameId": 432, "modId": 238222, "isAvailable": true,
+		"displayName": "jei-1.18.2-9.7.0.195.jar", "fileName": "jei-1.18.2-9.7.0.195.jar",
+		"releaseType": 1, "fileStatus": 4,
+		"hashes": [{"value": "1d4b4e8e6d3f4f0a5e1b6c2a9d7e3f8b0c1d2e3f", "algo": 1}, {"value": "0f1e2d3c4b5a69788796a5b4c3d2e1f0", "algo": 2}],
+		"fileDate": "2022-04-10T12:00:00.000Z", "fileLength": 913421, "downloadCount": 100,
+		"downloadUrl": null,
+		"gameVersions": ["1.18.2", "Forge"],
+		"sortableGameVersions": [{"gameVersionName": "1.18.2", "gameVersionPadded": "0000000001.0000000018.0000000002",
+			"gameVersion": "1.18.2", "gameVersionReleaseDate": "2022-02-28T14:20:39.413Z", "gameVersionTypeId": 73250}],
+		"dependencies": [], "alternateFileId": 0, "isServerPack": false, "fileFingerprint": 3089143260,
+		"modules": [{"name": "META-INF", "fingerprint": 4071014356}]
+	}"#;
+
+	#[test]
+	fn null_download_url_falls_back_to_cdn() {
+		let file: File = serde_json::from_str(OPTED_OUT_FILE).unwrap();
+		assert_eq!(file.download_url, None);
+		assert_eq!(Curseforge::cdn_url(file.id, &file.file_name), "https://edge.forgecdn.net/files/3752/735/jei-1.18.2-9.7.0.195.jar");
+		// the thousands split doesn't pad, 4611035 lives at 4611/35
+		assert_eq!(Curseforge::cdn_url(4611035, "a.jar"), "https://edge.forgecdn.net/files/4611/35/a.jar");
+	}
+
+	#[test]
+	fn retryable_errors() {
+		let url = || "https://api.curseforge.com/v1/mods/1".to_string();
+		assert!(ApiError::HTTPError(minreq::Error::AddressNotFound, url()).is_retryable());
+		assert!(ApiError::TransportError(url(), "connection reset".into()).is_retryable());
+		assert!(ApiError::ServerError(url()).is_retryable());
+		assert!(ApiError::BadHTTPResponse(url(), 429).is_retryable());
+		assert!(ApiError::BadHTTPResponse(url(), 502).is_retryable());
+		assert!(!ApiError::BadHTTPResponse(url(), 400).is_retryable());
+		assert!(!ApiError::ForbiddenError(url()).is_retryable());
+		assert!(!ApiError::NotFoundError(url()).is_retryable());
+		assert!(!ApiError::SlugNotFound("jei".into()).is_retryable());
+		assert!(!ApiError::AmbiguousSlug("dup".into(), vec![1, 2]).is_retryable());
+		let garbage = serde_json::from_str::<serde_json::Value>("{").unwrap_err();
+		assert!(!ApiError::ResponseParseError(garbage, url(), "{".into()).is_retryable());
+	}
+}
@@ -0,0 +1,222 @@
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}, sync::Arc};
+
+use futures_util::{StreamExt, future};
+use parking_lot::Mutex;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::{fs, io::AsyncWriteExt, sync::Semaphore};
+
+use crate::{api::{self, ApiError, Curseforge, DataResponse, File, GetFilesBody, GetModsBody, HashAlgo, Mod, ModLoaderType, Pagination, PagedResponse, API_ATTEMPTS, API_RETRY_DELAY, CF_BASE_URL, GAME_MINECRAFT}, error::{AppError, ErrorContext}, hash, http::HttpResponse, install, output, search::{self, SearchQuery}};
+
+// Curseforge for code that's already async, on reqwest instead of minreq. it takes the same requests and hands
+// back the same types, but has no metadata cache or rate limit, and needs a tokio runtime with the timer enabled
+#[derive(Clone)]
+pub struct AsyncCurseforge {
+	key: String,
+	// always ends in a slash, request paths are appended straight on
+	base_url: String,
+	client: reqwest::Client,
+	slugs: Arc<Mutex<HashMap<String, i32>>>
+}
+
+impl fmt::Debug for AsyncCurseforge {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("AsyncCurseforge")
+			.field("key", &"***")
+			.field("base_url", &self.base_url)
+			.finish_non_exhaustive()
+	}
+}
+
+impl AsyncCurseforge {
+	pub fn new(key: String) -> Self {
+		output::add_secret(&key);
+		Self {
+			key,
+			base_url: CF_BASE_URL.into(),
+			client: reqwest::Client::new(),
+			slugs: Arc::default()
+		}
+	}
+
+	pub fn with_base_url(mut self, base_url: &str) -> Self {
+		self.base_url = format!("{}/", base_url.trim_end_matches('/'));
+		self
+	}
+
+	// for proxies, timeouts and anything else reqwest can be configured with. the default client
+	// already follows HTTP_PROXY/HTTPS_PROXY
+	pub fn with_client(mut self, client: reqwest::Client) -> Self {
+		self.client = client;
+		self
+	}
+
+	pub async fn get_mod(&self, project_id: i32) -> Result<Mod, ApiError> {
+		let result: DataResponse<Mod> = self.api_get(&format!("mods/{}", project_id)).await?;
+		Ok(result.data)
+	}
+
+	pub async fn get_mods(&self, mod_ids: &[i32]) -> Result<Vec<Mod>, ApiError> {
+		if mod_ids.is_empty() {
+			return Ok(Vec::new());
+		}
+		let result: DataResponse<Vec<Mod>> = self.api_post("mods", &GetModsBody{mod_ids}).await?;
+		Ok(result.data)
+	}
+
+	pub async fn get_mod_file(&self, project_id: i32, file_id: i32) -> Result<File, ApiError> {
+		let result: DataResponse<File> = self.api_get(&format!("mods/{}/files/{}", project_id, file_id)).await?;
+		Ok(result.data)
+	}
+
+	pub async fn get_files(&self, file_ids: &[i32]) -> Result<Vec<File>, ApiError> {
+		if file_ids.is_empty() {
+			return Ok(Vec::new());
+		}
+		let result: DataResponse<Vec<File>> = self.api_post("mods/files", &GetFilesBody{file_ids}).await?;
+		Ok(result.data)
+	}
+
+	pub async fn get_mod_files(&self, project_id: i32, game_version: Option<&str>, loader: Option<ModLoaderType>) -> Result<Vec<File>, ApiError> {
+		let mut query_url = format!("mods/{}/files?pageSize=50", project_id);
+		if let Some(v) = game_version {
+			query_url.push_str(&format!("&gameVersion={}", v));
+		}
+		if let Some(l) = loader {
+			query_url.push_str(&format!("&modLoaderType={}", u8::from(l)));
+		}
+		let result: DataResponse<Vec<File>> = self.api_get(&query_url).await?;
+		Ok(result.data)
+	}
+
+	pub async fn search_mods(&self, class_id: Option<i32>, slug: &str) -> Result<Vec<Mod>, ApiError> {
+		let mut query_url = format!("mods/search?gameId={}&slug={}", GAME_MINECRAFT, search::encode(slug));
+		if let Some(c) = class_id {
+			query_url.push_str(&format!("&classId={}", c));
+		}
+		let result: DataResponse<Vec<Mod>> = self.api_get(&query_url).await?;
+		Ok(result.data)
+	}
+
+	// same rules as Curseforge::get_mod_by_slug
+	pub async fn get_mod_by_slug(&self, slug: &str) -> Result<Mod, ApiError> {
+		let known = self.slugs.lock().get(slug).copied();
+		if let Some(id) = known {
+			return self.get_mod(id).await;
+		}
+		let mut matches: Vec<Mod> = self.search_mods(None, slug).await?.into_iter().filter(|m| m.slug == slug).collect();
+		match matches.len() {
+			0 => Err(ApiError::SlugNotFound(slug.to_string())),
+			1 => {
+				let m = matches.remove(0);
+				self.slugs.lock().insert(slug.to_string(), m.id);
+				Ok(m)
+			},
+			_ => Err(ApiError::AmbiguousSlug(slug.to_string(), matches.iter().map(|m| m.id).collect()))
+		}
+	}
+
+	pub async fn search_page(&self, query: &SearchQuery, index: u32, page_size: u32) -> Result<(Vec<Mod>, Pagination), ApiError> {
+		let query_url = format!("mods/search?gameId={}&index={}&pageSize={}{}", GAME_MINECRAFT, index, page_size, query.params());
+		let result: PagedResponse<Vec<Mod>> = self.api_get(&query_url).await?;
+		Ok((result.data, result.pagination))
+	}
+
+	pub async fn get_download_url(&self, project_id: i32, file_id: i32) -> Result<String, ApiError> {
+		let result: DataResponse<String> = self.api_get(&format!("mods/{}/files/{}/download-url", project_id, file_id)).await?;
+		Ok(result.data)
+	}
+
+	pub async fn get_mod_description(&self, project_id: i32) -> Result<String, ApiError> {
+		let result: DataResponse<String> = self.api_get(&format!("mods/{}/description", project_id)).await?;
+		Ok(result.data)
+	}
+
+	pub async fn get_file_changelog(&self, project_id: i32, file_id: i32) -> Result<String, ApiError> {
+		let result: DataResponse<String> = self.api_get(&format!("mods/{}/files/{}/changelog", project_id, file_id)).await?;
+		Ok(result.data)
+	}
+
+	pub async fn check_key(&self) -> Result<(), ApiError> {
+		let _: DataResponse<serde_json::Value> = self.api_get(&format!("games/{}", GAME_MINECRAFT)).await?;
+		Ok(())
+	}
+
+	// downloads every file into `dir`, at most `concurrency` at once. the results are in the same order as `files`
+	pub async fn download_files(&self, files: &[File], dir: &Path, concurrency: usize) -> Vec<Result<PathBuf, AppError>> {
+		let semaphore = Semaphore::new(concurrency.max(1));
+		future::join_all(files.iter().map(|file| async {
+			// the semaphore is never closed, so this always gets a permit
+			let _permit = semaphore.acquire().await.ok();
+			self.download_file(file, dir).await
+		})).await
+	}
+
+	// into a .part file first like the blocking installer, so a failed download never leaves a truncated jar. checked
+	// against the file's sha1 if CF has one
+	pub async fn download_file(&self, file: &File, dir: &Path) -> Result<PathBuf, AppError> {
+		let url = file.download_url.clone().unwrap_or_else(|| Curseforge::cdn_url(file.id, &file.file_name));
+		let path = install::contained_path(dir, Path::new(&file.file_name))?;
+		let part = install::part_path(&path);
+		output::info(format!("Downloading {}", url));
+		let response = self.client.get(&url).send().await
+			.map_err(|e| AppError::Api(format!("Error downloading file {}", url), ApiError::TransportError(url.clone(), Box::new(e))))?;
+		api::check_status(response.status().as_u16() as i32, &url).context(&format!("Error downloading file {}", url))?;
+
+		let mut out = fs::File::create(&part).await.context(&format!("Error creating file {:?}", part))?;
+		let mut hasher = hash::Sha1Stream::new();
+		let mut body = response.bytes_stream();
+		while let Some(chunk) = body.next().await {
+			let chunk = chunk.map_err(|e| AppError::Api(format!("Error downloading file {}", url), ApiError::TransportError(url.clone(), Box::new(e))))?;
+			hasher.update(&chunk);
+			out.write_all(&chunk).await.context(&format!("Error writing downloaded file {:?}", part))?;
+		}
+		// tokio only finishes the last write once it's flushed
+		out.flush().await.context(&format!("Error writing downloaded file {:?}", part))?;
+		drop(out);
+
+		let sha1 = hasher.hex();
+		if let Some(expected) = file.hashes.iter().find(|h| h.algo == HashAlgo::Sha1).filter(|h| !h.value.eq_ignore_ascii_case(&sha1)) {
+			let _ = fs::remove_file(&part).await;
+			return Err(AppError::Other(format!("Hash mismatch for {}: expected sha1 {}, got {}", file.file_name, expected.value, sha1)));
+		}
+		fs::rename(&part, &path).await.context(&format!("Error moving {:?} into place", part))?;
+		Ok(path)
+	}
+
+	async fn api_get<T: DeserializeOwned>(&self, suburl: &str) -> Result<T, ApiError> {
+		let query_url = format!("{}{}", self.base_url, suburl);
+		log::debug!("GET {}", query_url);
+		self.with_retries(&query_url, || self.client.get(&query_url).header("x-api-key", &self.key)).await
+	}
+
+	async fn api_post<T: DeserializeOwned, U: Serialize>(&self, suburl: &str, body: &U) -> Result<T, ApiError> {
+		let query_url = format!("{}{}", self.base_url, suburl);
+		log::debug!("POST {}", query_url);
+		self.with_retries(&query_url, || self.client.post(&query_url).header("x-api-key", &self.key).header("Accept", "application/json").json(body)).await
+	}
+
+	// the request is rebuilt for every attempt, a sent RequestBuilder can't be reused
+	async fn with_retries<T: DeserializeOwned>(&self, query_url: &str, request: impl Fn() -> reqwest::RequestBuilder) -> Result<T, ApiError> {
+		let mut delay = API_RETRY_DELAY;
+		for _ in 1..API_ATTEMPTS {
+			match send(request(), query_url).await {
+				Err(e) if e.is_retryable() => {
+					output::warn(format!("{}, retrying in {}s", e, delay.as_secs()));
+					tokio::time::sleep(delay).await;
+					delay *= 2;
+				},
+				result => return result
+			}
+		}
+		send(request(), query_url).await
+	}
+}
+
+// status codes and parsing are handled exactly like the blocking client's
+async fn send<T: DeserializeOwned>(request: reqwest::RequestBuilder, query_url: &str) -> Result<T, ApiError> {
+	let transport = |e: reqwest::Error| ApiError::TransportError(query_url.to_string(), Box::new(e));
+	let response = request.send().await.map_err(transport)?;
+	let status = response.status().as_u16() as i32;
+	let body = response.bytes().await.map_err(transport)?.to_vec();
+	api::parse_response(HttpResponse { status, body }, query_url)
+}
@@ -0,0 +1,76 @@
+use std::{fs::{self, File}, io::{self, Seek, SeekFrom, Write}, path::{Path, PathBuf}};
+
+use crate::error::{AppError, ErrorContext};
+
+// writes go to a sibling temp file that's only renamed over the target by commit(), so an
+// interrupted write never leaves a truncated file where a complete one is expected.
+// dropping without committing throws the temp file away
+pub struct AtomicFile {
+	target: PathBuf,
+	tmp: PathBuf,
+	file: Option<File>
+}
+
+impl AtomicFile {
+	pub fn create(target: &Path) -> Result<Self, AppError> {
+		let mut tmp = target.to_path_buf().into_os_string();
+		tmp.push(".tmp");
+		let tmp = PathBuf::from(tmp);
+		let file = File::create(&tmp).context(&format!("Error opening file {:?}", tmp))?;
+		Ok(Self { target: target.to_path_buf(), tmp, file: Some(file) })
+	}
+
+	pub fn commit(mut self) -> Result<(), AppError> {
+		if let Some(mut file) = self.file.take() {
+			file.flush().context(&format!("Error writing {:?}", self.tmp))?;
+		}
+		replace(&self.tmp, &self.target).context(&format!("Error moving {:?} into place", self.tmp))
+	}
+
+	fn file(&mut self) -> &mut File {
+		self.file.as_mut().expect("file is only taken by commit, which consumes self")
+	}
+}
+
+impl Write for AtomicFile {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.file().write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.file().flush()
+	}
+}
+
+// the zip writer needs to seek back to patch headers
+impl Seek for AtomicFile {
+	fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+		self.file().seek(pos)
+	}
+}
+
+impl Drop for AtomicFile {
+	fn drop(&mut self) {
+		if self.file.take().is_some() {
+			let _ = fs::remove_file(&self.tmp);
+		}
+	}
+}
+
+pub fn write(target: &Path, data: impl AsRef<[u8]>) -> Result<(), AppError> {
+	let mut file = AtomicFile::create(target)?;
+	file.write_all(data.as_ref()).context(&format!("Error writing {:?}", target))?;
+	file.commit()
+}
+
+// rename already replaces existing files everywhere, but windows refuses when the target is
+// read-only or briefly held open (antivirus likes to do that), so clear it out and try again
+pub fn replace(from: &Path, to: &Path) -> io::Result<()> {
+	match fs::rename(from, to) {
+		Err(_) if cfg!(windows) && to.exists() => {
+			fs::remove_file(to)?;
+			fs::rename(from, to)
+		},
+		result => result
+	}
+}
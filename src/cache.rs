@@ -0,0 +1,139 @@
+use std::{fmt, fs, io, path::{Path, PathBuf}, time::{Duration, SystemTime}};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::progress::format_bytes;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// one JSON file per project/file under the user cache dir, expired by mtime.
+// the cache is best-effort: any error reading or writing it just means a trip to the API.
+#[derive(Clone, Debug)]
+pub struct MetadataCache {
+	dir: PathBuf,
+	ttl: Duration
+}
+
+impl MetadataCache {
+	pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+		Self { dir, ttl }
+	}
+
+	// offline installs take any cached metadata, however old, since a file id's metadata doesn't change
+	pub fn with_ttl(self, ttl: Duration) -> Self {
+		Self { ttl, ..self }
+	}
+
+	pub fn default_dir() -> Option<PathBuf> {
+		dirs::cache_dir().map(|d| d.join("underlion"))
+	}
+
+	fn entry_path(&self, kind: &str, id: i32) -> PathBuf {
+		self.dir.join(kind).join(format!("{}.json", id))
+	}
+
+	pub fn get<T: DeserializeOwned>(&self, kind: &str, id: i32) -> Option<T> {
+		let path = self.entry_path(kind, id);
+		let age = fs::metadata(&path).ok()?
+			.modified().ok()
+			.and_then(|m| SystemTime::now().duration_since(m).ok())?;
+		if age > self.ttl {
+			return None;
+		}
+		let data = fs::read(&path).ok()?;
+		match serde_json::from_slice(&data) {
+			Ok(v) => {
+				log::debug!("Cache hit for {} {}", kind, id);
+				Some(v)
+			},
+			Err(e) => {
+				log::debug!("Ignoring unreadable cache entry {:?}: {}", path, e);
+				None
+			}
+		}
+	}
+
+	pub fn put<T: Serialize>(&self, kind: &str, id: i32, value: &T) {
+		let path = self.entry_path(kind, id);
+		let result = path.parent()
+			.map_or(Ok(()), fs::create_dir_all)
+			.and_then(|_| serde_json::to_vec(value).map_err(io::Error::other))
+			.and_then(|data| fs::write(&path, data));
+		if let Err(e) = result {
+			log::debug!("Couldn't write cache entry {:?}: {}", path, e);
+		}
+	}
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct CacheStats {
+	pub entries: usize,
+	pub bytes: u64
+}
+
+impl fmt::Display for CacheStats {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} cached entries ({})", self.entries, format_bytes(self.bytes))
+	}
+}
+
+// the entries clean would remove: all of them, or with older_than only those last written longer ago than that
+pub fn stats(dir: &Path, older_than: Option<Duration>) -> io::Result<CacheStats> {
+	let mut stats = CacheStats::default();
+	for (_, meta) in entries(dir, older_than)? {
+		stats.entries += 1;
+		stats.bytes += meta.len();
+	}
+	Ok(stats)
+}
+
+// returns what was removed. without older_than the whole cache dir goes, anything stray in it included
+pub fn clean(dir: &Path, older_than: Option<Duration>) -> io::Result<CacheStats> {
+	let stale = entries(dir, older_than)?;
+	let mut removed = CacheStats::default();
+	if older_than.is_none() {
+		removed.entries = stale.len();
+		removed.bytes = stale.iter().map(|(_, m)| m.len()).sum();
+		if dir.exists() {
+			fs::remove_dir_all(dir)?;
+		}
+		return Ok(removed);
+	}
+	for (path, meta) in stale {
+		fs::remove_file(&path)?;
+		removed.entries += 1;
+		removed.bytes += meta.len();
+		// only goes once the last entry of its kind has
+		if let Some(kind) = path.parent() {
+			let _ = fs::remove_dir(kind);
+		}
+	}
+	Ok(removed)
+}
+
+fn entries(dir: &Path, older_than: Option<Duration>) -> io::Result<Vec<(PathBuf, fs::Metadata)>> {
+	let mut found = Vec::new();
+	if !dir.exists() {
+		return Ok(found);
+	}
+	let now = SystemTime::now();
+	for kind in fs::read_dir(dir)? {
+		let kind = kind?.path();
+		if !kind.is_dir() {
+			continue;
+		}
+		for entry in fs::read_dir(&kind)? {
+			let entry = entry?;
+			let meta = entry.metadata()?;
+			if !meta.is_file() {
+				continue;
+			}
+			// an mtime in the future counts as fresh
+			let age = meta.modified().ok().and_then(|m| now.duration_since(m).ok()).unwrap_or_default();
+			if older_than.is_none_or(|max| age > max) {
+				found.push((entry.path(), meta));
+			}
+		}
+	}
+	Ok(found)
+}
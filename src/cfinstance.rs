@@ -0,0 +1,81 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{error::AppError, manifest::{FileInfo, LoaderInfo, Manifest, MinecraftInfo, MANIFEST_TYPE, MANIFEST_VERSION}, output, try_load_file};
+
+pub const INSTANCE_FILE_NAME: &str = "minecraftinstance.json";
+
+// the CurseForge app's record of an installed instance. it has far more fields than this, and which of
+// them are present varies between app versions, so everything not needed for a manifest is left out
+#[derive(Deserialize)]
+pub struct CfInstance {
+	pub name: String,
+	#[serde(rename = "gameVersion")]
+	pub game_version: String,
+	#[serde(rename = "baseModLoader")]
+	pub base_mod_loader: Option<BaseModLoader>,
+	#[serde(rename = "installedModpack")]
+	pub installed_modpack: Option<InstalledAddon>,
+	#[serde(rename = "installedAddons", default)]
+	pub installed_addons: Vec<InstalledAddon>
+}
+
+#[derive(Deserialize)]
+pub struct BaseModLoader {
+	// forge-47.2.0, or fabric-0.15.3-1.20.1 with the game version tacked on
+	pub name: String
+}
+
+#[derive(Deserialize)]
+pub struct InstalledAddon {
+	#[serde(rename = "addonID")]
+	pub addon_id: i32,
+	#[serde(rename = "installedFile")]
+	pub installed_file: Option<InstalledFile>
+}
+
+#[derive(Deserialize)]
+pub struct InstalledFile {
+	pub id: i32,
+	#[serde(rename = "displayName", default)]
+	pub display_name: String
+}
+
+impl CfInstance {
+	pub fn read(path: &Path) -> Result<Self, AppError> {
+		let data = try_load_file(path)?;
+		serde_json::from_str(&data).map_err(|e| AppError::Other(format!("Error parsing CurseForge instance {:?}: {}", path, e)))
+	}
+
+	// addons without an installed file (the app keeps some around after a failed install) are left out
+	pub fn to_manifest(&self) -> Manifest {
+		let mut files = Vec::new();
+		for addon in &self.installed_addons {
+			match &addon.installed_file {
+				Some(file) => files.push(FileInfo { project_id: addon.addon_id, file_id: file.id, required: true }),
+				None => output::warn(format!("Project {} has no installed file, leaving it out", addon.addon_id))
+			}
+		}
+		let mod_loaders = self.base_mod_loader.iter()
+			.map(|l| LoaderInfo { id: self.loader_id(&l.name), primary: true })
+			.collect();
+		let version = self.installed_modpack.as_ref()
+			.and_then(|p| p.installed_file.as_ref())
+			.map_or_else(String::new, |f| f.display_name.clone());
+		Manifest {
+			minecraft: MinecraftInfo { version: self.game_version.clone(), mod_loaders },
+			manifest_type: MANIFEST_TYPE.into(),
+			overrides: "overrides".into(),
+			manifest_version: MANIFEST_VERSION,
+			version,
+			author: String::new(),
+			name: self.name.clone(),
+			files
+		}
+	}
+
+	fn loader_id(&self, name: &str) -> String {
+		name.strip_suffix(&format!("-{}", self.game_version)).unwrap_or(name).to_string()
+	}
+}
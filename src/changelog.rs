@@ -0,0 +1,145 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::Serialize;
+
+use crate::{api::Curseforge, diff, error::{AppError, ErrorContext}, output, try_load_manifest};
+
+#[derive(Serialize)]
+pub struct ModChangelog {
+	pub project_id: i32,
+	pub name: Option<String>,
+	pub old_file_id: i32,
+	pub new_file_id: i32,
+	pub new_file_name: Option<String>,
+	// plain text, None if CF wouldn't give it to us
+	pub changelog: Option<String>
+}
+
+// the changelogs of the new file of every mod whose file changed between two packs (zips or manifest.json files)
+pub fn changelog(cf: &Curseforge, old_path: &Path, new_path: &Path) -> Result<Vec<ModChangelog>, AppError> {
+	let mut diff = diff::diff_manifests(&try_load_manifest(old_path)?, &try_load_manifest(new_path)?);
+	diff.resolve_names(cf)?;
+	let new_ids: Vec<i32> = diff.changed.iter().map(|c| c.new_file_id).collect();
+	let file_names: HashMap<i32, String> = if new_ids.is_empty() {
+		HashMap::new()
+	} else {
+		cf.get_files(&new_ids)
+			.context("Error getting file metadata")?
+			.into_iter()
+			.map(|f| (f.id, f.file_name))
+			.collect()
+	};
+
+	let mut changelogs = Vec::new();
+	for change in diff.changed {
+		let changelog = match cf.get_file_changelog(change.project_id, change.new_file_id) {
+			Ok(html) => Some(html_to_text(&html)),
+			Err(e) => {
+				output::warn(format!("Couldn't get the changelog for project {} file {}: {}", change.project_id, change.new_file_id, e));
+				None
+			}
+		};
+		changelogs.push(ModChangelog {
+			project_id: change.project_id,
+			name: change.name,
+			old_file_id: change.old_file_id,
+			new_file_id: change.new_file_id,
+			new_file_name: file_names.get(&change.new_file_id).cloned(),
+			changelog
+		});
+	}
+	changelogs.sort_by_key(|c| c.name.as_deref().unwrap_or_default().to_lowercase());
+	Ok(changelogs)
+}
+
+impl ModChangelog {
+	fn title(&self) -> String {
+		let name = self.name.clone().unwrap_or_else(|| format!("Project {}", self.project_id));
+		match &self.new_file_name {
+			Some(file) => format!("{} ({})", name, file),
+			None => format!("{} (file {} -> {})", name, self.old_file_id, self.new_file_id)
+		}
+	}
+
+	fn body(&self) -> &str {
+		match self.changelog.as_deref() {
+			Some("") => "No changelog given.",
+			Some(text) => text,
+			None => "Changelog unavailable."
+		}
+	}
+}
+
+pub fn print(changelogs: &[ModChangelog]) {
+	for c in changelogs {
+		output::warn(format!("~ {}", c.title()));
+		for line in c.body().lines() {
+			println!("    {}", line);
+		}
+		println!();
+	}
+	output::info(format!("{} changed mods", changelogs.len()));
+}
+
+// one section per mod, ready to paste into release notes
+pub fn to_markdown(changelogs: &[ModChangelog]) -> String {
+	let mut md = String::from("# Changelog\n");
+	if changelogs.is_empty() {
+		md.push_str("\nNo mods were updated.\n");
+	}
+	for c in changelogs {
+		md.push_str(&format!("\n## {}\n\n{}\n", c.title(), c.body()));
+	}
+	md
+}
+
+// CF changelogs are HTML from a rich text editor. this only keeps the line structure and list bullets,
+// which is all a terminal or a markdown file needs
+pub fn html_to_text(html: &str) -> String {
+	let mut text = String::new();
+	let mut rest = html;
+	while let Some(start) = rest.find('<') {
+		text.push_str(&rest[..start]);
+		let Some(end) = rest[start..].find('>') else {
+			rest = &rest[start..];
+			break;
+		};
+		let tag = rest[start + 1..start + end].trim().to_lowercase();
+		let name = tag.trim_start_matches('/').split(|c: char| c.is_whitespace() || c == '/').next().unwrap_or_default();
+		match name {
+			"br" | "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "tr" => text.push('\n'),
+			"li" if !tag.starts_with('/') => text.push_str("\n- "),
+			_ => ()
+		}
+		rest = &rest[start + end + 1..];
+	}
+	text.push_str(rest);
+	let text = decode_entities(&text);
+
+	// trim each line and squash runs of blank lines left by nested block tags
+	let mut out = String::new();
+	let mut blank = false;
+	for line in text.lines().map(str::trim) {
+		if line.is_empty() {
+			blank = !out.is_empty();
+			continue;
+		}
+		if blank {
+			out.push('\n');
+			blank = false;
+		}
+		out.push_str(line);
+		out.push('\n');
+	}
+	out.trim_end().to_string()
+}
+
+fn decode_entities(text: &str) -> String {
+	text.replace("&nbsp;", " ")
+		.replace("&lt;", "<")
+		.replace("&gt;", ">")
+		.replace("&quot;", "\"")
+		.replace("&#39;", "'")
+		.replace("&#x27;", "'")
+		.replace("&amp;", "&")
+}
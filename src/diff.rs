@@ -0,0 +1,146 @@
+use std::{collections::{BTreeMap, HashMap}, fs::File, path::Path};
+
+use serde::Serialize;
+use zip::ZipArchive;
+
+use crate::{api::Curseforge, error::{AppError, ErrorContext}, manifest::Manifest, output, try_open_pack};
+
+#[derive(Serialize, Default)]
+pub struct PackDiff {
+	pub added: Vec<ModEntry>,
+	pub removed: Vec<ModEntry>,
+	pub changed: Vec<ModChange>,
+	pub overrides_added: Vec<String>,
+	pub overrides_removed: Vec<String>,
+	pub overrides_changed: Vec<String>
+}
+
+#[derive(Serialize)]
+pub struct ModEntry {
+	pub project_id: i32,
+	pub file_id: i32,
+	pub name: Option<String>
+}
+
+#[derive(Serialize)]
+pub struct ModChange {
+	pub project_id: i32,
+	pub old_file_id: i32,
+	pub new_file_id: i32,
+	pub name: Option<String>
+}
+
+pub fn diff_packs(old_path: &Path, new_path: &Path) -> Result<PackDiff, AppError> {
+	let (mut old_zip, old) = try_open_pack(old_path)?;
+	let (mut new_zip, new) = try_open_pack(new_path)?;
+	let mut diff = diff_manifests(&old, &new);
+
+	let old_overrides = override_crcs(&mut old_zip, &old.overrides)?;
+	let new_overrides = override_crcs(&mut new_zip, &new.overrides)?;
+	for (path, crc) in &new_overrides {
+		match old_overrides.get(path) {
+			None => diff.overrides_added.push(path.clone()),
+			Some(old_crc) if old_crc != crc => diff.overrides_changed.push(path.clone()),
+			_ => ()
+		}
+	}
+	diff.overrides_removed = old_overrides.keys()
+		.filter(|p| !new_overrides.contains_key(*p))
+		.cloned()
+		.collect();
+	Ok(diff)
+}
+
+pub fn diff_manifests(old: &Manifest, new: &Manifest) -> PackDiff {
+	let old_files: HashMap<i32, i32> = old.files.iter().map(|f| (f.project_id, f.file_id)).collect();
+	let new_files: HashMap<i32, i32> = new.files.iter().map(|f| (f.project_id, f.file_id)).collect();
+	let mut diff = PackDiff::default();
+	for file in &new.files {
+		match old_files.get(&file.project_id) {
+			None => diff.added.push(ModEntry { project_id: file.project_id, file_id: file.file_id, name: None }),
+			Some(&old_id) if old_id != file.file_id => diff.changed.push(ModChange {
+				project_id: file.project_id,
+				old_file_id: old_id,
+				new_file_id: file.file_id,
+				name: None
+			}),
+			_ => ()
+		}
+	}
+	for file in &old.files {
+		if !new_files.contains_key(&file.project_id) {
+			diff.removed.push(ModEntry { project_id: file.project_id, file_id: file.file_id, name: None });
+		}
+	}
+	diff
+}
+
+impl PackDiff {
+	pub fn resolve_names(&mut self, cf: &Curseforge) -> Result<(), AppError> {
+		let ids: Vec<i32> = self.added.iter().map(|m| m.project_id)
+			.chain(self.removed.iter().map(|m| m.project_id))
+			.chain(self.changed.iter().map(|m| m.project_id))
+			.collect();
+		if ids.is_empty() {
+			return Ok(());
+		}
+		let names: HashMap<i32, String> = cf.get_mods(&ids)
+			.context("Error getting mod metadata")?
+			.into_iter()
+			.map(|m| (m.id, m.name))
+			.collect();
+		for m in self.added.iter_mut().chain(self.removed.iter_mut()) {
+			m.name = names.get(&m.project_id).cloned();
+		}
+		for m in self.changed.iter_mut() {
+			m.name = names.get(&m.project_id).cloned();
+		}
+		Ok(())
+	}
+
+	pub fn print(&self) {
+		for m in &self.added {
+			output::success(format!("+ {} (file {})", label(m.project_id, &m.name), m.file_id));
+		}
+		for m in &self.removed {
+			output::error(format!("- {} (file {})", label(m.project_id, &m.name), m.file_id));
+		}
+		for m in &self.changed {
+			output::warn(format!("~ {}: file {} -> {}", label(m.project_id, &m.name), m.old_file_id, m.new_file_id));
+		}
+		for path in &self.overrides_added {
+			output::success(format!("+ override {}", path));
+		}
+		for path in &self.overrides_removed {
+			output::error(format!("- override {}", path));
+		}
+		for path in &self.overrides_changed {
+			output::warn(format!("~ override {}", path));
+		}
+		output::info(format!("{} added, {} removed, {} changed mods; {} added, {} removed, {} changed overrides",
+			self.added.len(), self.removed.len(), self.changed.len(),
+			self.overrides_added.len(), self.overrides_removed.len(), self.overrides_changed.len()));
+	}
+}
+
+fn label(project_id: i32, name: &Option<String>) -> String {
+	match name {
+		Some(n) => format!("{} (project {})", n, project_id),
+		None => format!("project {}", project_id)
+	}
+}
+
+// relative override path -> crc32, sorted so output is stable
+fn override_crcs(zip: &mut ZipArchive<File>, overrides: &str) -> Result<BTreeMap<String, u32>, AppError> {
+	let prefix = format!("{}/", overrides);
+	let mut crcs = BTreeMap::new();
+	for i in 0..zip.len() {
+		let entry = zip.by_index_raw(i).context("Error reading pack zip")?;
+		if entry.is_file() {
+			if let Some(rel) = entry.name().strip_prefix(&prefix) {
+				crcs.insert(rel.to_string(), entry.crc32());
+			}
+		}
+	}
+	Ok(crcs)
+}
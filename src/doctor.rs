@@ -0,0 +1,117 @@
+use std::{fs, path::Path};
+
+use crate::{api::{self, ApiError, Curseforge}, error::AppError, output, proxy::ProxyConfig};
+
+const PROBE_TIMEOUT: u64 = 15;
+
+pub enum Status {
+	Pass,
+	Fail,
+	// an earlier check failed in a way that makes this one meaningless
+	Skipped
+}
+
+pub struct Check {
+	pub name: &'static str,
+	pub status: Status,
+	pub detail: String,
+	// what to do about a failure
+	pub fix: Option<String>
+}
+
+impl Check {
+	fn pass(name: &'static str, detail: impl Into<String>) -> Self {
+		Self { name, status: Status::Pass, detail: detail.into(), fix: None }
+	}
+
+	fn fail(name: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+		Self { name, status: Status::Fail, detail: detail.into(), fix: Some(fix.into()) }
+	}
+
+	fn skip(name: &'static str, detail: impl Into<String>) -> Self {
+		Self { name, status: Status::Skipped, detail: detail.into(), fix: None }
+	}
+}
+
+// everything a first install needs, in the order problems would show up. `key` is however the CLI
+// found it, the client is built from it once it's known to exist
+pub fn run(key: Result<String, AppError>, client: impl Fn(String) -> Curseforge, proxy: &ProxyConfig, base_url: &str, dir: &Path) -> Vec<Check> {
+	let mut checks = Vec::new();
+	let key = match key {
+		Ok(key) => {
+			checks.push(Check::pass("API key", "found"));
+			Some(key)
+		},
+		Err(e) => {
+			checks.push(Check::fail("API key", e.to_string(), "Run `underlion grab-key`, pass --key or --key-file, or set CF_API_KEY"));
+			None
+		}
+	};
+
+	let api_reachable = match reachable(base_url, proxy) {
+		Ok(status) => {
+			checks.push(Check::pass("CF API", format!("{} answered ({})", base_url, status)));
+			true
+		},
+		Err(e) => {
+			checks.push(Check::fail("CF API", format!("Can't reach {}: {}", base_url, e), "Check your internet connection and firewall, or set a proxy with --proxy or HTTPS_PROXY"));
+			false
+		}
+	};
+	let cdn = api::CDN_BASE_URL;
+	match reachable(cdn, proxy) {
+		Ok(status) => checks.push(Check::pass("CF CDN", format!("{} answered ({})", cdn, status))),
+		Err(e) => checks.push(Check::fail("CF CDN", format!("Can't reach {}: {}", cdn, e), "Mods are downloaded from here, check your firewall or proxy settings, or use --mirror"))
+	}
+
+	checks.push(match (key, api_reachable) {
+		(Some(key), true) => match client(key).check_key() {
+			Ok(()) => Check::pass("Key accepted", "CF accepted the key"),
+			Err(ApiError::ForbiddenError(_)) => Check::fail("Key accepted", "CF rejected the key", "The key is wrong or has been revoked, run `underlion grab-key` to get a new one"),
+			Err(e) => Check::fail("Key accepted", e.to_string(), "CF couldn't be asked about the key, try again later")
+		},
+		(None, _) => Check::skip("Key accepted", "no key to check"),
+		(_, false) => Check::skip("Key accepted", "the API can't be reached")
+	});
+
+	checks.push(match writable(dir) {
+		Ok(detail) => Check::pass("Write access", detail),
+		Err(e) => Check::fail("Write access", e, "Install somewhere else, or fix the folder's permissions")
+	});
+	checks
+}
+
+pub fn print(checks: &[Check]) {
+	for check in checks {
+		match check.status {
+			Status::Pass => output::success(format!("[ ok ] {}: {}", check.name, check.detail)),
+			Status::Fail => output::error(format!("[FAIL] {}: {}", check.name, check.detail)),
+			Status::Skipped => output::info(format!("[skip] {}: {}", check.name, check.detail))
+		}
+		if let Some(fix) = &check.fix {
+			output::info(format!("       {}", fix));
+		}
+	}
+}
+
+pub fn failures(checks: &[Check]) -> usize {
+	checks.iter().filter(|c| matches!(c.status, Status::Fail)).count()
+}
+
+// any HTTP response counts, the point is only whether the connection can be made
+fn reachable(url: &str, proxy: &ProxyConfig) -> Result<i32, minreq::Error> {
+	let request = minreq::get(url).with_timeout(PROBE_TIMEOUT);
+	Ok(proxy.apply(request, url)?.send()?.status_code)
+}
+
+// a folder that doesn't exist yet is fine as long as the nearest one that does can be written to
+fn writable(dir: &Path) -> Result<String, String> {
+	let existing = dir.ancestors()
+		.find(|p| p.as_os_str().is_empty() || p.is_dir())
+		.map(|p| if p.as_os_str().is_empty() { Path::new(".") } else { p })
+		.ok_or_else(|| format!("None of {:?} exists", dir))?;
+	let probe = existing.join(format!(".underlion-doctor-{}", std::process::id()));
+	fs::write(&probe, b"").map_err(|e| format!("Can't write to {:?}: {}", existing, e))?;
+	let _ = fs::remove_file(&probe);
+	Ok(if existing == dir { format!("{:?} is writable", dir) } else { format!("{:?} doesn't exist yet, but {:?} is writable", dir, existing) })
+}
@@ -0,0 +1,272 @@
+use std::{fs::File, io::Write, path::{Path, PathBuf}};
+
+use zip::{ZipArchive, ZipWriter, write::FileOptions, CompressionMethod};
+
+use crate::{atomic::{self, AtomicFile}, api::{self, Curseforge, FileRelationType, FileReleaseType, Mod}, error::{AppError, ErrorContext, TryExpect}, loader, manifest::{FileInfo, Manifest}, output, try_open, try_open_pack, try_read_manifest, MANIFEST_NAME};
+
+// what add does about the optional dependencies of the file it adds
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OptionalDeps {
+	// one prompt each, defaulting to no
+	Ask,
+	All,
+	None
+}
+
+// how add, and update with --latest, pick a file for a project
+#[derive(Clone)]
+pub struct FileChoice {
+	// instead of the pack's Minecraft version
+	pub game_version: Option<String>,
+	// the least stable release type that's acceptable, anything more stable is too
+	pub release_type: FileReleaseType,
+	// with nothing that stable, take the newest of the next tier down rather than giving up
+	pub allow_fallback: bool
+}
+
+impl Default for FileChoice {
+	fn default() -> Self {
+		Self { game_version: None, release_type: FileReleaseType::Release, allow_fallback: false }
+	}
+}
+
+// a pack being edited, either a bare manifest.json or a pack zip
+pub struct EditablePack {
+	path: PathBuf,
+	pub manifest: Manifest,
+	zip: Option<ZipArchive<File>>
+}
+
+impl EditablePack {
+	pub fn open(path: &Path) -> Result<Self, AppError> {
+		if path.extension().is_some_and(|e| e == "json") {
+			let manifest = try_read_manifest(&mut try_open(path)?)?;
+			Ok(Self { path: path.to_path_buf(), manifest, zip: None })
+		} else {
+			let (zip, manifest) = try_open_pack(path)?;
+			Ok(Self { path: path.to_path_buf(), manifest, zip: Some(zip) })
+		}
+	}
+
+	// writes in place unless an output path is given; zips are rebuilt next to the target and renamed over it
+	pub fn save(mut self, output_path: Option<&Path>) -> Result<(), AppError> {
+		let target = output_path.unwrap_or(&self.path).to_path_buf();
+		let manifest_json = serde_json::to_string_pretty(&self.manifest)
+			.map_err(|e| AppError::Other(format!("Error serializing manifest: {}", e)))?;
+		let zip = match self.zip.as_mut() {
+			Some(z) if target.extension().is_none_or(|e| e != "json") => z,
+			_ => return atomic::write(&target, manifest_json)
+		};
+
+		let mut writer = ZipWriter::new(AtomicFile::create(&target)?);
+		let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+		writer.start_file(MANIFEST_NAME, options).context("Error writing pack zip")?;
+		writer.write_all(manifest_json.as_bytes()).context("Error writing pack zip")?;
+		for i in 0..zip.len() {
+			let entry = zip.by_index_raw(i).context("Error reading pack zip")?;
+			if entry.name() == MANIFEST_NAME {
+				continue;
+			}
+			writer.raw_copy_file(entry).context("Error writing pack zip")?;
+		}
+		let file = writer.finish().context("Error writing pack zip")?;
+		drop(self.zip);
+		file.commit()
+	}
+}
+
+pub fn resolve_project(cf: &Curseforge, slug_or_id: &str) -> Result<Mod, AppError> {
+	if let Ok(id) = slug_or_id.parse::<i32>() {
+		return cf.get_mod(id).context(&format!("Error getting project {}", id));
+	}
+	cf.get_mod_by_slug(slug_or_id).context(&format!("Error looking up {}", slug_or_id))
+}
+
+pub fn add(cf: &Curseforge, pack_path: &Path, slug_or_id: &str, file_id: Option<i32>, choice: &FileChoice, output_path: Option<&Path>, optional: OptionalDeps) -> Result<(), AppError> {
+	let mut pack = EditablePack::open(pack_path)?;
+	let project = resolve_project(cf, slug_or_id)?;
+	if let Some(existing) = pack.manifest.files.iter().find(|f| f.project_id == project.id) {
+		output::warn(format!("{} is already in the pack (file {}), skipping", project.name, existing.file_id));
+		return Ok(());
+	}
+
+	let file_id = match file_id {
+		Some(id) => id,
+		None => newest_file(cf, &project, &pack.manifest, choice)?
+	};
+	pack.manifest.files.push(FileInfo { project_id: project.id, file_id, required: true });
+	let mut added = vec![format!("{} (project {}, file {})", project.name, project.id, file_id)];
+
+	for dep in optional_deps(cf, &project, file_id, &pack.manifest, optional)? {
+		match newest_file(cf, &dep, &pack.manifest, choice) {
+			Ok(id) => {
+				pack.manifest.files.push(FileInfo { project_id: dep.id, file_id: id, required: true });
+				added.push(format!("{} (project {}, file {})", dep.name, dep.id, id));
+			},
+			Err(e) => output::warn(format!("Not adding {}: {}", dep.name, e))
+		}
+	}
+	pack.save(output_path)?;
+	for a in added {
+		output::success(format!("Added {}", a));
+	}
+	Ok(())
+}
+
+pub(crate) fn newest_file(cf: &Curseforge, project: &Mod, manifest: &Manifest, choice: &FileChoice) -> Result<i32, AppError> {
+	let game_version = choice.game_version.as_deref().unwrap_or(&manifest.minecraft.version);
+	// CF only tags mod files with a loader. resource packs, shaders and data packs have none, so filtering those
+	// by one finds nothing, and they don't need the pack to have a loader at all
+	let loader = match project.class_id {
+		Some(api::CLASS_MODS) => Some(loader::primary_loader_id(manifest)?),
+		_ => None
+	};
+	let files = cf.get_mod_files(project.id, Some(game_version), loader.as_ref().map(|l| l.kind.mod_loader_type()))
+		.context(&format!("Error getting files for {}", project.name))?;
+	let compatible: Vec<&api::File> = files.iter().filter(|f| f.is_available && f.lists_game_version(game_version)).collect();
+	if compatible.is_empty() {
+		let with = loader.map(|l| format!(" with {}", l.kind.name())).unwrap_or_default();
+		return Err(AppError::Other(format!("{} has no file for Minecraft {}{}, try another --game-version",
+			project.name, game_version, with)));
+	}
+	// release types count up from the most stable, so a tier takes in everything below it.
+	// fileDate is ISO 8601, so it sorts as a string
+	let newest = |tier: u8| compatible.iter()
+		.filter(|f| u8::from(f.release_type) <= tier)
+		.max_by(|a, b| a.file_date.cmp(&b.file_date));
+	let wanted = u8::from(choice.release_type);
+	if let Some(file) = newest(wanted) {
+		return Ok(file.id);
+	}
+	let fallback = compatible.iter().min_by_key(|f| u8::from(f.release_type)).map(|f| f.release_type);
+	match fallback {
+		Some(tier) if choice.allow_fallback => {
+			let file = newest(u8::from(tier)).try_expect("No file in the fallback tier")?;
+			output::warn(format!("{} has no {} file for Minecraft {}, using {} file {}", project.name, choice.release_type.to_string().to_lowercase(), game_version, tier.to_string().to_lowercase(), file.file_name));
+			Ok(file.id)
+		},
+		_ => Err(AppError::Other(format!("{} has no {} file for Minecraft {}, only less stable ones (pass --allow-fallback to take one)",
+			project.name, choice.release_type.to_string().to_lowercase(), game_version)))
+	}
+}
+
+// the optional dependencies of the added file that aren't in the pack yet, narrowed down to the ones wanted.
+// only the added file's own are looked at, not those of the dependencies it brings in
+fn optional_deps(cf: &Curseforge, project: &Mod, file_id: i32, manifest: &Manifest, optional: OptionalDeps) -> Result<Vec<Mod>, AppError> {
+	let file = cf.get_mod_file(project.id, file_id).context(&format!("Error getting file {} of {}", file_id, project.name))?;
+	let ids: Vec<i32> = file.dependencies.iter()
+		.filter(|d| d.relation_type == FileRelationType::OptionalDependency)
+		.map(|d| d.mod_id)
+		.filter(|id| !manifest.files.iter().any(|f| f.project_id == *id))
+		.collect();
+	if ids.is_empty() {
+		return Ok(Vec::new());
+	}
+	let mut deps = cf.get_mods(&ids).context(&format!("Error getting the optional dependencies of {}", project.name))?;
+	deps.sort_by(|a, b| a.name.cmp(&b.name));
+	Ok(match optional {
+		OptionalDeps::All => deps,
+		OptionalDeps::None => {
+			let names: Vec<&str> = deps.iter().map(|d| d.name.as_str()).collect();
+			output::info(format!("Not adding optional dependencies of {}: {}", project.name, names.join(", ")));
+			Vec::new()
+		},
+		OptionalDeps::Ask => {
+			output::info(format!("{} has {} optional dependencies that aren't in the pack", project.name, deps.len()));
+			deps.into_iter()
+				.filter(|d| {
+					if !d.summary.is_empty() {
+						output::info(format!("  {}: {}", d.name, d.summary));
+					}
+					output::confirm(format!("Add {} (project {})?", d.name, d.id))
+				})
+				.collect()
+		}
+	})
+}
+
+// numeric ids are matched directly, so removing by id works without an API key
+pub fn remove(cf: impl FnOnce() -> Result<Curseforge, AppError>, pack_path: &Path, slug_or_id: &str, output_path: Option<&Path>) -> Result<(), AppError> {
+	let mut pack = EditablePack::open(pack_path)?;
+	let (project_id, label) = match slug_or_id.parse::<i32>() {
+		Ok(id) => (id, format!("project {}", id)),
+		Err(_) => {
+			let project = resolve_project(&cf()?, slug_or_id)?;
+			(project.id, format!("{} (project {})", project.name, project.id))
+		}
+	};
+
+	let before = pack.manifest.files.len();
+	let removed: Vec<FileInfo> = pack.manifest.files.iter().filter(|f| f.project_id == project_id).cloned().collect();
+	pack.manifest.files.retain(|f| f.project_id != project_id);
+	if pack.manifest.files.len() == before {
+		return Err(AppError::Other(format!("{} isn't in {:?}", label, pack_path)));
+	}
+	pack.save(output_path)?;
+	for file in removed {
+		output::success(format!("Removed {}, file {}", label, file.file_id));
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use parking_lot::Mutex;
+
+	use crate::{api::ApiError, http::{HttpClient, HttpResponse}};
+
+	use super::*;
+
+	// answers every request with one shader file and remembers what was asked for
+	#[derive(Default)]
+	struct FakeCf(Mutex<Vec<String>>);
+
+	impl HttpClient for FakeCf {
+		fn get(&self, url: &str, _headers: &[(&str, &str)]) -> Result<HttpResponse, ApiError> {
+			self.0.lock().push(url.to_string());
+			let body = r#"{"data": [{
+				"id": 501, "gameId": 432, "modId": 77, "isAvailable": true,
+				"displayName": "shiny-1.0.zip", "fileName": "shiny-1.0.zip", "releaseType": 1, "fileStatus": 4,
+				"hashes": [], "fileDate": "2024-01-01T00:00:00.000Z", "fileLength": 10, "downloadCount": 0,
+				"downloadUrl": null, "gameVersions": ["1.20.1"], "sortableGameVersions": [],
+				"dependencies": [], "fileFingerprint": 0, "modules": []
+			}]}"#;
+			Ok(HttpResponse { status: 200, body: body.into() })
+		}
+
+		fn post(&self, url: &str, headers: &[(&str, &str)], _body: &[u8]) -> Result<HttpResponse, ApiError> {
+			self.get(url, headers)
+		}
+	}
+
+	fn project(class_id: i32) -> Mod {
+		serde_json::from_value(serde_json::json!({
+			"id": 77, "gameId": 432, "name": "Shiny", "slug": "shiny", "summary": "", "status": 4,
+			"links": {"websiteUrl": null, "wikiUrl": null, "issuesUrl": null, "sourceUrl": null},
+			"downloadCount": 0.0, "isFeatured": false, "primaryCategoryId": 0, "categories": [], "classId": class_id,
+			"authors": [], "logo": null, "screenshots": [], "mainFileId": 501, "latestFiles": [], "latestFilesIndexes": [],
+			"dateCreated": "", "dateModified": "", "dateReleased": "", "allowModDistribution": null,
+			"gamePopularityRank": 0, "isAvailable": true, "thumbsUpCount": null
+		})).unwrap()
+	}
+
+	#[test]
+	fn non_mods_arent_filtered_by_loader() {
+		let fake = Arc::new(FakeCf::default());
+		let cf = Curseforge::new("key".into()).with_http_client(fake.clone());
+		let mut manifest: Manifest = serde_json::from_str(r#"{
+			"minecraft": {"version": "1.20.1", "modLoaders": []}, "manifestType": "minecraftModpack",
+			"manifestVersion": 1, "version": "1.0", "author": "", "name": "Test", "files": []
+		}"#).unwrap();
+		// no loader in the pack at all, which a shader pack doesn't care about
+		assert_eq!(newest_file(&cf, &project(api::CLASS_SHADERS), &manifest, &FileChoice::default()).unwrap(), 501);
+		assert!(!fake.0.lock()[0].contains("modLoaderType"));
+		// but a mod still needs one
+		assert!(newest_file(&cf, &project(api::CLASS_MODS), &manifest, &FileChoice::default()).is_err());
+		manifest.minecraft.mod_loaders = serde_json::from_str(r#"[{"id": "forge-47.2.0", "primary": true}]"#).unwrap();
+		newest_file(&cf, &project(api::CLASS_MODS), &manifest, &FileChoice::default()).unwrap();
+		assert!(fake.0.lock()[1].contains("modLoaderType=1"));
+	}
+}
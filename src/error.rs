@@ -0,0 +1,173 @@
+use std::{error::Error, fmt::{self, Display}, io, path::StripPrefixError};
+
+use zip::result::ZipError;
+
+use crate::{api::ApiError, manifest::{FormatError, LoaderIdError}};
+
+// new variants can turn up in minor releases, so code embedding the library needs a catch-all arm
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AppError {
+	Io(String, io::Error),
+	Zip(String, ZipError),
+	Api(String, ApiError),
+	Http(String, minreq::Error),
+	Manifest(serde_json::Error),
+	// a manifest.json that isn't a CF modpack manifest, or a version of one we don't understand
+	Format(FormatError),
+	Loader(LoaderIdError),
+	KeyNotFound(String),
+	PartialInstall(usize),
+	Interrupted,
+	Other(String)
+}
+
+pub const EXIT_GENERIC: i32 = 1;
+pub const EXIT_AUTH: i32 = 2;
+pub const EXIT_NETWORK: i32 = 3;
+pub const EXIT_PARTIAL_INSTALL: i32 = 4;
+// what shells report for a process killed by SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+impl AppError {
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Self::KeyNotFound(_) | Self::Api(_, ApiError::ForbiddenError(_)) => EXIT_AUTH,
+			// anything worth retrying is the network or CF's end, not something the user can fix
+			Self::Http(..) => EXIT_NETWORK,
+			Self::Api(_, e) if e.is_retryable() => EXIT_NETWORK,
+			Self::PartialInstall(_) => EXIT_PARTIAL_INSTALL,
+			Self::Interrupted => EXIT_INTERRUPTED,
+			_ => EXIT_GENERIC
+		}
+	}
+}
+
+impl Display for AppError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Io(ctx, err) => write!(f, "{}: {}", ctx, err),
+			Self::Zip(ctx, err) => write!(f, "{}: {}", ctx, err),
+			Self::Api(ctx, err) => write!(f, "{}: {}", ctx, err),
+			Self::Http(ctx, err) => write!(f, "{}: {}", ctx, err),
+			Self::Manifest(err) => write!(f, "Error parsing pack manifest: {}", err),
+			Self::Format(err) => write!(f, "{}", err),
+			Self::Loader(err) => write!(f, "{}", err),
+			Self::KeyNotFound(msg) => write!(f, "{}", msg),
+			Self::PartialInstall(failed) => write!(f, "Install incomplete: {} item(s) failed or were cancelled", failed),
+			Self::Interrupted => write!(f, "Interrupted, run the install again to finish it"),
+			Self::Other(msg) => write!(f, "{}", msg)
+		}
+	}
+}
+
+impl Error for AppError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Io(_, err) => Some(err),
+			Self::Zip(_, err) => Some(err),
+			Self::Api(_, err) => Some(err),
+			Self::Http(_, err) => Some(err),
+			Self::Manifest(err) => Some(err),
+			Self::Format(err) => Some(err),
+			Self::Loader(err) => Some(err),
+			_ => None
+		}
+	}
+}
+
+pub trait IntoAppError {
+	fn into_app_error(self, ctx: &str) -> AppError;
+}
+
+impl IntoAppError for io::Error {
+	fn into_app_error(self, ctx: &str) -> AppError {
+		AppError::Io(ctx.to_string(), self)
+	}
+}
+
+impl IntoAppError for ZipError {
+	fn into_app_error(self, ctx: &str) -> AppError {
+		AppError::Zip(ctx.to_string(), self)
+	}
+}
+
+impl IntoAppError for ApiError {
+	fn into_app_error(self, ctx: &str) -> AppError {
+		AppError::Api(ctx.to_string(), self)
+	}
+}
+
+impl IntoAppError for minreq::Error {
+	fn into_app_error(self, ctx: &str) -> AppError {
+		AppError::Http(ctx.to_string(), self)
+	}
+}
+
+impl IntoAppError for StripPrefixError {
+	fn into_app_error(self, ctx: &str) -> AppError {
+		AppError::Other(format!("{}: {}", ctx, self))
+	}
+}
+
+pub trait ErrorContext<T> {
+	fn context(self, ctx: &str) -> Result<T, AppError>;
+}
+
+impl<T, E: IntoAppError> ErrorContext<T> for Result<T, E> {
+	fn context(self, ctx: &str) -> Result<T, AppError> {
+		self.map_err(|e| e.into_app_error(ctx))
+	}
+}
+
+pub trait TryExpect<T> {
+	fn try_expect(self, msg: &str) -> Result<T, AppError>;
+}
+
+impl<T> TryExpect<T> for Option<T> {
+	fn try_expect(self, msg: &str) -> Result<T, AppError> {
+		match self {
+			Some(t) => Ok(t),
+			None => Err(AppError::Other(msg.into()))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn api(err: ApiError) -> AppError {
+		AppError::Api("ctx".into(), err)
+	}
+
+	#[test]
+	fn exit_codes() {
+		let url = || "https://example.com".to_string();
+		assert_eq!(AppError::KeyNotFound("no key".into()).exit_code(), EXIT_AUTH);
+		assert_eq!(api(ApiError::ForbiddenError(url())).exit_code(), EXIT_AUTH);
+		assert_eq!(AppError::Http("ctx".into(), minreq::Error::AddressNotFound).exit_code(), EXIT_NETWORK);
+		assert_eq!(api(ApiError::HTTPError(minreq::Error::AddressNotFound, url())).exit_code(), EXIT_NETWORK);
+		assert_eq!(api(ApiError::TransportError(url(), "reset".into())).exit_code(), EXIT_NETWORK);
+		assert_eq!(api(ApiError::ServerError(url())).exit_code(), EXIT_NETWORK);
+		assert_eq!(api(ApiError::BadHTTPResponse(url(), 503)).exit_code(), EXIT_NETWORK);
+		assert_eq!(api(ApiError::BadHTTPResponse(url(), 429)).exit_code(), EXIT_NETWORK);
+		assert_eq!(api(ApiError::BadHTTPResponse(url(), 400)).exit_code(), EXIT_GENERIC);
+		assert_eq!(api(ApiError::NotFoundError(url())).exit_code(), EXIT_GENERIC);
+		assert_eq!(api(ApiError::SlugNotFound("jei".into())).exit_code(), EXIT_GENERIC);
+		assert_eq!(AppError::PartialInstall(2).exit_code(), EXIT_PARTIAL_INSTALL);
+		assert_eq!(AppError::Interrupted.exit_code(), EXIT_INTERRUPTED);
+		assert_eq!(AppError::Other("anything".into()).exit_code(), EXIT_GENERIC);
+	}
+
+	// download failures used to come back as AppError::Other and so looked like a generic failure
+	#[test]
+	fn download_statuses_keep_their_class() {
+		let url = "https://edge.forgecdn.net/files/1/2/a.jar";
+		let status = |code| crate::api::check_status(code, url).context("Error downloading file").unwrap_err();
+		assert_eq!(status(502).exit_code(), EXIT_NETWORK);
+		assert_eq!(status(500).exit_code(), EXIT_NETWORK);
+		assert_eq!(status(404).exit_code(), EXIT_GENERIC);
+		assert!(crate::api::check_status(206, url).is_ok());
+	}
+}
@@ -0,0 +1,100 @@
+use std::{io::{self, Write}, sync::Arc};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::{output, progress, summary::InstallSummary};
+
+// what an install reports as it goes. mods are identified by project and file id,
+// slug is only there when CF's mod metadata could be fetched
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum InstallEvent {
+	ResolveStart { files: usize },
+	// bytes is the total across every file, when CF's file metadata was available
+	DownloadsStart { files: usize, bytes: Option<u64> },
+	DownloadStart { project_id: i32, file_id: i32, slug: Option<String>, file_name: String, bytes: Option<u64> },
+	DownloadProgress { project_id: i32, file_id: i32, slug: Option<String>, done: u64, total: Option<u64> },
+	DownloadDone { project_id: i32, file_id: i32, slug: Option<String>, bytes: u64 },
+	DownloadError { project_id: i32, file_id: i32, slug: Option<String>, msg: String },
+	// overall progress, sent every few seconds while downloading. files_done counts failed ones too
+	Progress { files_done: u32, files: u32, done: u64, total: Option<u64>, bytes_per_sec: u64 },
+	DownloadsDone { done: u64, total: Option<u64>, bytes_per_sec: u64 },
+	ExtractStart { files: usize },
+	ExtractError { path: String, msg: String },
+	ExtractDone { extracted: usize, failed: usize },
+	Summary { downloaded: usize, verified: usize, skipped: usize, failed: usize, cancelled: usize }
+}
+
+impl InstallEvent {
+	pub fn summary(summary: &InstallSummary) -> Self {
+		Self::Summary {
+			downloaded: summary.downloaded,
+			verified: summary.verified,
+			skipped: summary.skipped,
+			failed: summary.failures.len() + summary.override_failures.len(),
+			cancelled: summary.cancelled
+		}
+	}
+}
+
+// called from the download workers as well as the installing thread, so it has to be Sync.
+// events come in the order they happen on each thread, but different mods' events interleave
+pub trait InstallObserver: Send + Sync {
+	fn on_event(&self, event: InstallEvent);
+}
+
+impl<F: Fn(InstallEvent) + Send + Sync> InstallObserver for F {
+	fn on_event(&self, event: InstallEvent) {
+		self(event)
+	}
+}
+
+pub(crate) fn notify(observer: Option<&Arc<dyn InstallObserver>>, event: InstallEvent) {
+	if let Some(observer) = observer {
+		observer.on_event(event);
+	}
+}
+
+// the CLI's normal output: a status line every few seconds and once downloads are done.
+// everything else is already printed as it happens
+pub struct ConsoleObserver;
+
+impl InstallObserver for ConsoleObserver {
+	fn on_event(&self, event: InstallEvent) {
+		match event {
+			InstallEvent::Progress { files_done, files, done, total, bytes_per_sec } =>
+				output::info(format!("{}/{} files, {}", files_done, files, progress::status_line(done, total, bytes_per_sec))),
+			InstallEvent::DownloadsDone { done, total, bytes_per_sec } => output::info(format!("Downloaded {}", progress::status_line(done, total, bytes_per_sec))),
+			_ => ()
+		}
+	}
+}
+
+// one JSON object per line on stdout, for GUI wrappers. the lock keeps lines from different workers whole
+pub struct NdjsonObserver {
+	out: Mutex<io::Stdout>
+}
+
+impl NdjsonObserver {
+	pub fn new() -> Self {
+		Self { out: Mutex::new(io::stdout()) }
+	}
+}
+
+impl Default for NdjsonObserver {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl InstallObserver for NdjsonObserver {
+	fn on_event(&self, event: InstallEvent) {
+		if let Ok(line) = serde_json::to_string(&event) {
+			let mut out = self.out.lock();
+			let _ = writeln!(out, "{}", line);
+			let _ = out.flush();
+		}
+	}
+}
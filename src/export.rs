@@ -0,0 +1,71 @@
+use std::{fs, io::Write, path::Path};
+
+use zip::{ZipWriter, write::FileOptions, CompressionMethod};
+
+use crate::{api::Curseforge, atomic::AtomicFile, error::{AppError, ErrorContext}, glob::{self, Glob}, instance, manifest::Manifest, output, record, update, MANIFEST_NAME};
+
+// everything in the game dir except the pack's own files (mods, resource packs etc.), the install record, update
+// backups and anything matching `ignore` goes under overrides
+pub fn export(cf: &Curseforge, manifest: &Manifest, install_dir: &Path, output_path: &Path, ignore: &[Glob]) -> Result<(), AppError> {
+	let game_dir = instance::detect_game_dir(install_dir);
+	let file_ids: Vec<i32> = manifest.files.iter().map(|f| f.file_id).collect();
+	let classes = crate::install::resolve_classes(cf, &manifest.files);
+	let mod_jars: Vec<String> = cf.get_files(&file_ids)
+		.context("Error getting file metadata")?
+		.into_iter()
+		.map(|f| format!("{}/{}", instance::content_folder(classes.get(&f.mod_id).copied()), f.file_name))
+		.collect();
+
+	let mut overrides = Vec::new();
+	collect_files(&game_dir, "", &mut overrides)?;
+	overrides.retain(|rel| !mod_jars.contains(rel) && rel != record::RECORD_NAME && !rel.starts_with(&format!("{}/", update::BACKUP_DIR)));
+	let before = overrides.len();
+	overrides.retain(|rel| !glob::any_match(ignore, rel));
+	if before > overrides.len() {
+		output::info(format!("Leaving out {} ignored files", before - overrides.len()));
+	}
+	// an earlier export sitting in the folder would otherwise end up inside the new one
+	if let (Ok(out), Ok(dir)) = (output_path.canonicalize(), game_dir.canonicalize()) {
+		if let Ok(rel) = out.strip_prefix(&dir) {
+			let rel = rel.to_string_lossy().replace('\\', "/");
+			overrides.retain(|r| *r != rel);
+		}
+	}
+
+	let mut manifest = manifest.clone();
+	manifest.overrides = "overrides".into();
+	let manifest_json = serde_json::to_string_pretty(&manifest)
+		.map_err(|e| AppError::Other(format!("Error serializing manifest: {}", e)))?;
+
+	let mut writer = ZipWriter::new(AtomicFile::create(output_path)?);
+	let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+	writer.start_file(MANIFEST_NAME, options).context("Error writing pack zip")?;
+	writer.write_all(manifest_json.as_bytes()).context("Error writing pack zip")?;
+	for rel in &overrides {
+		let path = game_dir.join(rel);
+		let data = fs::read(&path).context(&format!("Error reading {:?}", path))?;
+		writer.start_file(format!("overrides/{}", rel), options).context("Error writing pack zip")?;
+		writer.write_all(&data).context("Error writing pack zip")?;
+	}
+	writer.finish().context("Error writing pack zip")?.commit()?;
+	output::success(format!("Exported {} ({} mods, {} override files) to {:?}", manifest.name, manifest.files.len(), overrides.len(), output_path));
+	Ok(())
+}
+
+// relative paths use '/' whatever the platform, since they end up as zip entry names
+fn collect_files(dir: &Path, prefix: &str, out: &mut Vec<String>) -> Result<(), AppError> {
+	let mut entries: Vec<_> = fs::read_dir(dir).context(&format!("Error listing {:?}", dir))?
+		.collect::<Result<_, _>>()
+		.context(&format!("Error listing {:?}", dir))?;
+	entries.sort_by_key(|e| e.file_name());
+	for entry in entries {
+		let rel = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+		let file_type = entry.file_type().context(&format!("Error reading {:?}", entry.path()))?;
+		if file_type.is_dir() {
+			collect_files(&entry.path(), &format!("{}/", rel), out)?;
+		} else if file_type.is_file() {
+			out.push(rel);
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,55 @@
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+
+use crate::{api::Curseforge, error::{AppError, ErrorContext}, manifest::FileInfo, output};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum FilterMode {
+	Include,
+	Exclude
+}
+
+// one slug or project id per line, blank lines and #comments ignored
+pub fn read_list(path: &Path) -> Result<Vec<String>, AppError> {
+	let contents = fs::read_to_string(path).context(&format!("Error reading mod list {:?}", path))?;
+	Ok(contents.lines()
+		.map(|l| l.split('#').next().unwrap_or("").trim())
+		.filter(|l| !l.is_empty())
+		.map(|l| l.to_string())
+		.collect())
+}
+
+// entries have to name a project that's actually in the pack, so a typo doesn't silently filter nothing
+pub fn apply(cf: &Curseforge, files: Vec<FileInfo>, entries: &[String], mode: FilterMode) -> Result<Vec<FileInfo>, AppError> {
+	let in_pack: HashSet<i32> = files.iter().map(|f| f.project_id).collect();
+	let slugs: HashMap<String, i32> = if entries.iter().any(|e| e.parse::<i32>().is_err()) {
+		let project_ids: Vec<i32> = in_pack.iter().copied().collect();
+		cf.get_mods(&project_ids)
+			.context("Error getting mod metadata to resolve slugs")?
+			.into_iter()
+			.map(|m| (m.slug, m.id))
+			.collect()
+	} else {
+		HashMap::new()
+	};
+
+	let mut listed = HashSet::new();
+	let mut unknown = Vec::new();
+	for entry in entries {
+		match entry.parse::<i32>().ok().or_else(|| slugs.get(entry).copied()) {
+			Some(id) if in_pack.contains(&id) => {
+				listed.insert(id);
+			},
+			_ => unknown.push(entry.as_str())
+		}
+	}
+	if !unknown.is_empty() {
+		return Err(AppError::Other(format!("Not in this pack: {}", unknown.join(", "))));
+	}
+
+	let total = files.len();
+	let kept: Vec<FileInfo> = files.into_iter()
+		.filter(|f| listed.contains(&f.project_id) == (mode == FilterMode::Include))
+		.collect();
+	output::info(format!("Filtered out {} of {} mods, installing {}", total - kept.len(), total, kept.len()));
+	Ok(kept)
+}
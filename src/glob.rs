@@ -0,0 +1,73 @@
+use crate::error::AppError;
+
+// shell-style patterns over '/'-separated relative paths: * and ? stay within a path segment, ** crosses them.
+// a pattern without a '/' is matched against every segment on its own, so `*.log` and `logs` work at any depth.
+// matching a folder matches everything under it
+#[derive(Clone, Debug)]
+pub struct Glob {
+	pattern: Vec<char>,
+	any_segment: bool
+}
+
+impl Glob {
+	pub fn new(pattern: &str) -> Result<Self, AppError> {
+		let trimmed = pattern.trim_matches('/');
+		if trimmed.is_empty() {
+			return Err(AppError::Other(format!("Empty pattern {:?}", pattern)));
+		}
+		Ok(Self {
+			pattern: trimmed.chars().collect(),
+			any_segment: !trimmed.contains('/')
+		})
+	}
+
+	pub fn matches(&self, path: &str) -> bool {
+		let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+		if self.any_segment {
+			return segments.iter().any(|s| self.matches_str(s));
+		}
+		// every leading run of segments, so a pattern naming a folder covers its contents
+		(1..=segments.len()).any(|n| self.matches_str(&segments[..n].join("/")))
+	}
+
+	fn matches_str(&self, s: &str) -> bool {
+		let s: Vec<char> = s.chars().collect();
+		match_from(&self.pattern, &s)
+	}
+}
+
+pub fn parse_all(patterns: &[String]) -> Result<Vec<Glob>, AppError> {
+	patterns.iter().map(|p| Glob::new(p)).collect()
+}
+
+pub fn any_match(globs: &[Glob], path: &str) -> bool {
+	globs.iter().any(|g| g.matches(path))
+}
+
+fn match_from(pattern: &[char], s: &[char]) -> bool {
+	match pattern.first() {
+		None => s.is_empty(),
+		Some('*') if pattern.get(1) == Some(&'*') => {
+			// `**/` can also match nothing at all, so `a/**/b` matches `a/b`
+			let rest = &pattern[2..];
+			if rest.first() == Some(&'/') && match_from(&rest[1..], s) {
+				return true;
+			}
+			(0..=s.len()).any(|i| match_from(rest, &s[i..]))
+		},
+		Some('*') => {
+			let rest = &pattern[1..];
+			for i in 0..=s.len() {
+				if match_from(rest, &s[i..]) {
+					return true;
+				}
+				if s.get(i) == Some(&'/') {
+					break;
+				}
+			}
+			false
+		},
+		Some('?') => s.first().is_some_and(|c| *c != '/') && match_from(&pattern[1..], &s[1..]),
+		Some(c) => s.first() == Some(c) && match_from(&pattern[1..], &s[1..])
+	}
+}
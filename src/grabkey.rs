@@ -0,0 +1,276 @@
+use std::{fs::{self, OpenOptions}, io::{self, BufWriter, Read, Seek, Write}, path::Path, thread, time::Duration};
+
+use regex::Regex;
+use zip::ZipArchive;
+
+use crate::{api::{ApiError, Curseforge}, error::{AppError, ErrorContext, TryExpect}, fetch_streaming_timeout, output, proxy::ProxyConfig, try_open_zip, try_read_zip_entry, TempFile};
+
+// newest first. Overwolf takes old versions down eventually, so add new ones at the top as they come out
+pub const KNOWN_VERSIONS: &[&str] = &["0.196.1.11"];
+const KEY_GRAB_LOCATION: &str = "dist/desktop/desktop.js";
+// tried in order, the first capture group is the key. the JS around it has changed before: quoted and
+// unquoted property names, and assignments once the bundler rewrote the config object
+pub const KEY_PATTERNS: &[&str] = &[
+	r#"cfCoreApiKey":"(.*?)""#,
+	r#"cfCoreApiKey:"(.*?)""#,
+	r#"cfCoreApiKey["']?\s*[:=]\s*["'`]([^"'`]+)["'`]"#
+];
+// how many versions discovery will probe before giving up
+const MAX_PROBES: usize = 30;
+const PROBE_TIMEOUT: u64 = 15;
+const DOWNLOAD_ATTEMPTS: u32 = 3;
+const DOWNLOAD_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+pub fn opk_url(version: &str) -> String {
+	format!("https://appsdl-overwolf-com.akamaized.net/prod/apps/cchhcaiapeikjbdbpfplgmpobbcdkdaphclbmkbj/{version}/app.opk")
+}
+
+pub struct KeyGrabber {
+	proxy: ProxyConfig,
+	patterns: KeyPatterns,
+	// seconds for the whole OPK download, None waits forever
+	timeout: Option<u64>
+}
+
+impl KeyGrabber {
+	pub fn new(proxy: ProxyConfig, patterns: KeyPatterns, timeout: Option<u64>) -> Self {
+		Self { proxy, patterns, timeout }
+	}
+
+	// with no version, tries each known version and then whatever discovery turns up.
+	// "latest" skips straight to discovery. a key only counts once `check` accepts it
+	pub fn grab_key(&self, version: Option<&str>, check: &dyn Fn(&str) -> Result<(), AppError>) -> Result<String, AppError> {
+		let mut tried = Vec::new();
+		let versions: Vec<String> = match version {
+			Some("latest") => vec![self.discover_latest()?],
+			Some(v) => vec![v.to_string()],
+			None => KNOWN_VERSIONS.iter().map(|v| v.to_string()).collect()
+		};
+		for v in versions {
+			if let Some(key) = self.try_version(&v, check) {
+				return Ok(key);
+			}
+			tried.push(v);
+		}
+		if version.is_none() {
+			output::info("Looking for a newer version of the CF extension");
+			let latest = self.discover_latest()?;
+			if !tried.contains(&latest) {
+				if let Some(key) = self.try_version(&latest, check) {
+					return Ok(key);
+				}
+				tried.push(latest);
+			}
+		}
+		Err(AppError::KeyNotFound(format!("Couldn't get a key from version(s) {} of the CF extension, try --cf-version or --cf-url", tried.join(", "))))
+	}
+
+	fn try_version(&self, version: &str, check: &dyn Fn(&str) -> Result<(), AppError>) -> Option<String> {
+		match self.key_from_url(&opk_url(version)).and_then(|key| check(&key).map(|_| key)) {
+			Ok(key) => {
+				output::info(format!("Got the key from version {} of the CF extension", version));
+				Some(key)
+			},
+			Err(e) => {
+				output::warn(format!("Version {} of the CF extension didn't work: {}", version, e));
+				None
+			}
+		}
+	}
+
+	pub fn key_from_url(&self, url: &str) -> Result<String, AppError> {
+		let temp = TempFile::new("app.opk");
+		self.download(url, temp.path())?;
+		let mut cf_zip = try_open_zip(temp.path())?;
+		// where the key has always been so far, checked first since the bundle is full of other scripts
+		let known = match read_text(&mut cf_zip, KEY_GRAB_LOCATION) {
+			Ok(text) => match self.patterns.find(&text) {
+				Ok(key) => return Ok(key),
+				Err(e) => e
+			},
+			Err(e) => e
+		};
+		log::info!("No key in {}: {}", KEY_GRAB_LOCATION, known);
+		output::info(format!("The key isn't in {} any more, searching the rest of the extension", KEY_GRAB_LOCATION));
+		let scripts: Vec<String> = cf_zip.file_names()
+			.filter(|n| n.ends_with(".js") && *n != KEY_GRAB_LOCATION)
+			.map(String::from)
+			.collect();
+		for name in scripts {
+			if let Ok(key) = read_text(&mut cf_zip, &name).and_then(|text| self.patterns.find(&text)) {
+				output::info(format!("Found the key in {}", name));
+				return Ok(key);
+			}
+		}
+		Err(known)
+	}
+
+	// the OPK is big and Akamai can be slow, so it goes to disk, and a dropped connection
+	// picks up from where it got to instead of starting over
+	fn download(&self, url: &str, path: &Path) -> Result<(), AppError> {
+		let mut delay = DOWNLOAD_RETRY_DELAY;
+		let mut attempt = 1;
+		loop {
+			match self.download_once(url, path) {
+				Err(e) if attempt < DOWNLOAD_ATTEMPTS && retryable(&e) => {
+					output::warn(format!("{}, retrying in {}s", e, delay.as_secs()));
+					thread::sleep(delay);
+					delay *= 2;
+					attempt += 1;
+				},
+				result => return result
+			}
+		}
+	}
+
+	fn download_once(&self, url: &str, path: &Path) -> Result<(), AppError> {
+		let offset = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+		let mut body = fetch_streaming_timeout(url, &self.proxy, offset, self.timeout)?;
+		let resumed = offset > 0 && body.is_partial();
+		let file = OpenOptions::new().create(true).write(true).append(resumed).truncate(!resumed).open(path)
+			.context(&format!("Error opening file {:?}", path))?;
+		let mut out = BufWriter::new(file);
+		io::copy(&mut body, &mut out).context(&format!("Error downloading file {}", url))?;
+		out.flush().context(&format!("Error writing {:?}", path))
+	}
+
+	// Overwolf has no public listing of versions, so this walks up from the newest known one, bumping the
+	// build number, then the patch, then the minor version, for as long as the CDN has something there.
+	// new patches and minors have so far started again from 1
+	pub fn discover_latest(&self) -> Result<String, AppError> {
+		let mut latest = parse_version(KNOWN_VERSIONS[0]).try_expect("The newest known CF extension version isn't a four part version")?;
+		let mut probes = 0;
+		'outer: while probes < MAX_PROBES {
+			let [major, minor, patch, build] = latest;
+			for candidate in [[major, minor, patch, build + 1], [major, minor, patch + 1, 1], [major, minor + 1, 1, 1]] {
+				probes += 1;
+				if self.exists(&opk_url(&format_version(candidate))) {
+					latest = candidate;
+					continue 'outer;
+				}
+			}
+			break;
+		}
+		let latest = format_version(latest);
+		log::info!("Newest CF extension version found is {} after {} probes", latest, probes);
+		Ok(latest)
+	}
+
+	fn exists(&self, url: &str) -> bool {
+		let request = minreq::head(url).with_timeout(PROBE_TIMEOUT);
+		let status = self.proxy.apply(request, url).and_then(|r| r.send()).map(|r| r.status_code);
+		log::debug!("HEAD {} -> {:?}", url, status);
+		matches!(status, Ok(200..=299))
+	}
+}
+
+// a regex match on a file that's changed shape could be anything, so make sure CF takes it before it's saved
+pub fn check_key(cf: &Curseforge) -> Result<(), AppError> {
+	match cf.check_key() {
+		Ok(()) => Ok(()),
+		Err(ApiError::ForbiddenError(_)) => Err(AppError::KeyNotFound("The extracted value isn't a valid key".into())),
+		Err(e) => Err(AppError::Api("Error checking the extracted key".into(), e))
+	}
+}
+
+pub struct KeyPatterns(Vec<Regex>);
+
+impl KeyPatterns {
+	// a custom pattern goes ahead of the built in ones, for when CF changes things before a new release is out
+	pub fn new(custom: Option<&str>) -> Result<Self, AppError> {
+		let mut patterns = Vec::new();
+		if let Some(custom) = custom {
+			let pat = Regex::new(custom).map_err(|e| AppError::Other(format!("Invalid key pattern {:?}: {}", custom, e)))?;
+			if pat.captures_len() < 2 {
+				return Err(AppError::Other(format!("Key pattern {:?} needs a capture group around the key", custom)));
+			}
+			patterns.push(pat);
+		}
+		patterns.extend(KEY_PATTERNS.iter().map(|p| Regex::new(p).expect("built in key patterns are valid")));
+		Ok(Self(patterns))
+	}
+
+	pub fn find(&self, text: &str) -> Result<String, AppError> {
+		self.0.iter()
+			.find_map(|pat| pat.captures(text).and_then(|c| c.get(1)).map(|m| m.as_str()).filter(|k| !k.is_empty()))
+			.map(String::from)
+			.ok_or_else(|| AppError::KeyNotFound(format!("Key not found with any known pattern, the CF extension has likely changed. Tried:\n  {}",
+				self.0.iter().map(Regex::as_str).collect::<Vec<_>>().join("\n  "))))
+	}
+}
+
+// scripts aren't always valid UTF-8, and the key is ASCII either way
+fn read_text<R: Read + Seek>(zip: &mut ZipArchive<R>, name: &str) -> Result<String, AppError> {
+	let mut bytes = Vec::new();
+	try_read_zip_entry(zip, name)?.read_to_end(&mut bytes).context(&format!("Error reading {} from the CF extension", name))?;
+	Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+// dropped connections, and statuses from Akamai that might be different next time (5xx, 429). a 404 won't be
+fn retryable(e: &AppError) -> bool {
+	match e {
+		AppError::Http(..) | AppError::Io(..) => true,
+		AppError::Api(_, e) => e.is_retryable(),
+		_ => false
+	}
+}
+
+fn parse_version(version: &str) -> Option<[u32; 4]> {
+	let parts: Vec<u32> = version.split('.').map(|p| p.parse().ok()).collect::<Option<_>>()?;
+	parts.try_into().ok()
+}
+
+fn format_version(v: [u32; 4]) -> String {
+	format!("{}.{}.{}.{}", v[0], v[1], v[2], v[3])
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn find(text: &str) -> Option<String> {
+		KeyPatterns::new(None).unwrap().find(text).ok()
+	}
+
+	#[test]
+	fn finds_the_key() {
+		let key = Some("$2a$10$abcdef".to_string());
+		// the original config object, then after the bundler dropped the quotes around the name
+		assert_eq!(find(r#"var config={"cfCoreApiKey":"$2a$10$abcdef","env":"prod"};"#), key);
+		assert_eq!(find(r#"const c={cfCoreApiKey:"$2a$10$abcdef",env:"prod"}"#), key);
+		assert_eq!(find(r#"const c={cfCoreApiKey:'$2a$10$abcdef',env:'prod'}"#), key);
+		assert_eq!(find(r#"e.cfCoreApiKey = '$2a$10$abcdef';"#), key);
+		assert_eq!(find(r#"e.cfCoreApiKey="$2a$10$abcdef""#), key);
+		assert_eq!(find(r#"e.cfCoreApiKey = `$2a$10$abcdef`"#), key);
+	}
+
+	#[test]
+	fn no_key() {
+		assert_eq!(find(r#"var config={"apiKey":"something else"};"#), None);
+		assert_eq!(find(r#"{"cfCoreApiKey":""}"#), None);
+		assert_eq!(find(""), None);
+		let err = KeyPatterns::new(None).unwrap().find("nothing here").unwrap_err();
+		assert!(matches!(err, AppError::KeyNotFound(_)));
+	}
+
+	#[test]
+	fn custom_pattern_goes_first() {
+		let patterns = KeyPatterns::new(Some(r#"apiToken="(.*?)""#)).unwrap();
+		assert_eq!(patterns.find(r#"apiToken="custom" cfCoreApiKey:"builtin""#).unwrap(), "custom");
+		assert_eq!(patterns.find(r#"cfCoreApiKey:"builtin""#).unwrap(), "builtin");
+		assert!(KeyPatterns::new(Some("apiToken=.*")).is_err());
+		assert!(KeyPatterns::new(Some("(unclosed")).is_err());
+	}
+
+	#[test]
+	fn retries_what_might_work_next_time() {
+		let status = |code| crate::api::check_status(code, "https://example.com/app.opk").context("Error downloading file").unwrap_err();
+		assert!(retryable(&status(503)));
+		assert!(retryable(&status(500)));
+		assert!(retryable(&status(429)));
+		assert!(!retryable(&status(404)));
+		assert!(!retryable(&status(403)));
+		assert!(retryable(&AppError::Http("ctx".into(), minreq::Error::AddressNotFound)));
+		assert!(!retryable(&AppError::KeyNotFound("no key".into())));
+	}
+}
@@ -0,0 +1,52 @@
+use std::io::{self, Read, Write};
+
+use sha1::{Digest, Sha1};
+
+pub struct Sha1Stream(Sha1);
+
+impl Sha1Stream {
+	pub fn new() -> Self {
+		Self(Sha1::new())
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		self.0.update(data);
+	}
+
+	pub fn update_from(&mut self, mut reader: impl Read) -> io::Result<()> {
+		io::copy(&mut reader, &mut self.0)?;
+		Ok(())
+	}
+
+	pub fn hex(self) -> String {
+		format!("{:x}", self.0.finalize())
+	}
+}
+
+// hashes whatever passes through on the way to `inner`
+pub struct HashWriter<W: Write> {
+	inner: W,
+	hasher: Sha1Stream
+}
+
+impl<W: Write> HashWriter<W> {
+	pub fn new(inner: W) -> Self {
+		Self { inner, hasher: Sha1Stream::new() }
+	}
+
+	pub fn finish(self) -> (W, String) {
+		(self.inner, self.hasher.hex())
+	}
+}
+
+impl<W: Write> Write for HashWriter<W> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let written = self.inner.write(buf)?;
+		self.hasher.update(&buf[..written]);
+		Ok(written)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.inner.flush()
+	}
+}
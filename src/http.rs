@@ -0,0 +1,55 @@
+use crate::{api::ApiError, proxy::ProxyConfig};
+
+pub struct HttpResponse {
+	pub status: i32,
+	pub body: Vec<u8>
+}
+
+// the transport under Curseforge, so embedders can bring their own (reqwest, a caching layer, a mock).
+// only transport failures are errors here; a 4xx/5xx is still an Ok response, Curseforge sorts those out.
+// ApiError::TransportError is the variant for implementations that don't use minreq
+pub trait HttpClient: Send + Sync {
+	fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<HttpResponse, ApiError>;
+	fn post(&self, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<HttpResponse, ApiError>;
+}
+
+// what Curseforge uses unless told otherwise
+#[derive(Clone, Default, Debug)]
+pub struct MinreqClient {
+	proxy: ProxyConfig,
+	// in seconds, for the whole request. None waits as long as the connection stays up
+	timeout: Option<u64>
+}
+
+impl MinreqClient {
+	pub fn new(proxy: ProxyConfig) -> Self {
+		Self { proxy, timeout: None }
+	}
+
+	pub fn with_timeout(mut self, secs: u64) -> Self {
+		self.timeout = Some(secs);
+		self
+	}
+
+	fn send(&self, request: minreq::Request, url: &str, headers: &[(&str, &str)]) -> Result<HttpResponse, ApiError> {
+		let request = match self.timeout {
+			Some(secs) => request.with_timeout(secs),
+			None => request
+		};
+		let request = headers.iter().fold(request, |r, (name, value)| r.with_header(*name, *value));
+		let response = self.proxy.apply(request, url)
+			.and_then(|r| r.send())
+			.map_err(|e| ApiError::HTTPError(e, url.to_string()))?;
+		Ok(HttpResponse { status: response.status_code, body: response.into_bytes() })
+	}
+}
+
+impl HttpClient for MinreqClient {
+	fn get(&self, url: &str, headers: &[(&str, &str)]) -> Result<HttpResponse, ApiError> {
+		self.send(minreq::get(url), url, headers)
+	}
+
+	fn post(&self, url: &str, headers: &[(&str, &str)], body: &[u8]) -> Result<HttpResponse, ApiError> {
+		self.send(minreq::post(url).with_body(body), url, headers)
+	}
+}
@@ -0,0 +1,801 @@
+use std::{collections::{BTreeSet, HashMap}, fs, io::{self, BufWriter, Read, Seek, Write}, path::{Component, Path, PathBuf}, sync::Arc, thread, time::{Duration, Instant}};
+
+use parking_lot::Mutex;
+use zip::ZipArchive;
+
+use crate::{api::{self, ApiError, Curseforge, FileRelationType, FileStatus, Mod}, atomic::{self, AtomicFile}, error::{AppError, ErrorContext, TryExpect}, events::{self, InstallEvent, InstallObserver}, fetch_streaming_timeout, filter::{self, FilterMode}, glob::{self, Glob}, hash, instance::{self, InstanceFormat}, loader, lock::Lockfile, manifest::{FileInfo, Manifest}, output, plan::{ConfirmPlan, InstallPlan}, progress::{self, Progress}, ratelimit::RateLimiter, record::InstallRecord, scripts, server, space, summary::{InstallSummary, InstalledFile, ModResult, Outcome}, threadpool::{Cancel, ScopedPool, Semaphore}, try_mkdir, try_open, try_open_write, try_open_zip, try_read_pack_manifest, try_read_zip_entry, verify};
+
+const DOWNLOAD_CHUNK_SIZE: usize = 16 * 1024;
+// a progress event per chunk would be thousands per mod
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(250);
+
+// downloads mostly wait on the network, so auto goes a bit past the core count
+const AUTO_THREADS_PER_CPU: u32 = 2;
+const MAX_AUTO_THREADS: u32 = 8;
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Parallelism {
+	#[default]
+	Auto,
+	Threads(u32)
+}
+
+impl Parallelism {
+	pub fn threads(self, file_count: usize) -> u32 {
+		match self {
+			Self::Threads(n) => n,
+			Self::Auto => {
+				let cpus = thread::available_parallelism().map_or(1, |n| n.get() as u32);
+				let threads = (cpus * AUTO_THREADS_PER_CPU)
+					.min(MAX_AUTO_THREADS)
+					.min(file_count.try_into().unwrap_or(u32::MAX))
+					.max(1);
+				log::info!("Using {} threads ({} CPUs, {} files)", threads, cpus, file_count);
+				threads
+			}
+		}
+	}
+}
+
+// how downloads are spread out and throttled, shared by install and repair
+#[derive(Clone, Default)]
+pub struct DownloadSettings {
+	pub parallel: Parallelism,
+	pub max_connections: Option<u32>,
+	pub max_rate: Option<u64>,
+	pub mirrors: Vec<String>,
+	// for the whole batch of downloads. whatever's still running then is cut off and the rest cancelled
+	pub timeout: Option<Duration>
+}
+
+#[derive(Clone, Default)]
+pub struct InstallOptions {
+	pub download: DownloadSettings,
+	pub fail_fast: bool,
+	pub instance_format: InstanceFormat,
+	pub server: bool,
+	pub scripts: bool,
+	pub install_loader: bool,
+	pub write_lock: Option<PathBuf>,
+	pub locked: Option<PathBuf>,
+	pub filter: Option<(FilterMode, Vec<String>)>,
+	pub skip_space_check: bool,
+	pub allow_status: Vec<FileStatus>,
+	// refuse manifests that list a project twice instead of keeping the highest file id
+	pub strict: bool,
+	// gets progress as it happens, for callers that want more than the printed messages
+	pub observer: Option<Arc<dyn InstallObserver>>,
+	// cancelling it (from a Ctrl-C handler, say) stops new downloads and extraction from starting.
+	// downloads already running are finished, so nothing is left half-written. fail_fast cancels it too
+	pub cancel: Cancel,
+	// shown the plan before anything is downloaded, returning false stops the install there
+	pub confirm: Option<ConfirmPlan>,
+	// matched against paths inside the overrides folder. with any includes, only matching overrides are
+	// extracted, and excludes are taken out after that
+	pub override_include: Vec<Glob>,
+	pub override_exclude: Vec<Glob>,
+	// skip one of the two phases. setting both is an error
+	pub no_overrides: bool,
+	pub overrides_only: bool
+}
+
+impl InstallOptions {
+	pub(crate) fn check_phases(&self) -> Result<(), AppError> {
+		if self.no_overrides && self.overrides_only {
+			return Err(AppError::Other("no_overrides and overrides_only can't both be set, that leaves nothing to install".into()));
+		}
+		Ok(())
+	}
+
+	// the overrides this install will extract, after --no-overrides and the include/exclude patterns
+	pub(crate) fn override_entries<T: Read + Seek>(&self, pack: &ZipArchive<T>, manifest: &Manifest) -> Vec<String> {
+		if self.no_overrides {
+			return Vec::new();
+		}
+		filter_overrides(override_entries(pack, manifest, self.server), &manifest.overrides, self)
+	}
+}
+
+// failed downloads and overrides don't make this an Err, they're counted in the summary
+pub fn install_pack(cf: &Curseforge, pack_path: &Path, install_to: &Path, opts: &InstallOptions) -> Result<InstallSummary, AppError> {
+	opts.check_phases()?;
+	let mut pack = try_open_zip(pack_path)?;
+	
+	let mut manifest = try_read_pack_manifest(&mut pack)?;
+	manifest.dedup(opts.strict)?;
+	let observer = opts.observer.as_ref();
+	events::notify(observer, InstallEvent::ResolveStart { files: manifest.files.len() });
+	log::info!("Installing {} {} by {} (Minecraft {}, {} files) into {:?}",
+		manifest.name, manifest.version, manifest.author, manifest.minecraft.version, manifest.files.len(), install_to);
+
+	// fail before downloading anything rather than after
+	if opts.install_loader || opts.scripts {
+		loader::primary_loader_id(&manifest)?;
+	}
+	try_mkdir(install_to)?;
+	let game_dir = opts.instance_format.prepare(install_to, &manifest)?;
+	let mut mods_folder = game_dir.clone();
+	mods_folder.push("mods");
+	try_mkdir(&mods_folder)?;
+
+	let mut expected_sha1 = HashMap::new();
+	let files = if opts.overrides_only {
+		Vec::new()
+	} else if let Some(lock_path) = &opts.locked {
+		let lock = Lockfile::read(lock_path)?;
+		expected_sha1 = lock.hashes();
+		lock.file_infos()
+	} else if opts.server {
+		server::select_server_files(cf, manifest.files.clone())?
+	} else {
+		manifest.files.clone()
+	};
+	let files = match &opts.filter {
+		Some((mode, entries)) => filter::apply(cf, files, entries, *mode)?,
+		None => files
+	};
+	if let Some(lock_path) = &opts.write_lock {
+		Lockfile::resolve(cf, &files)?.write(lock_path)?;
+		output::info(format!("Wrote lockfile {:?}", lock_path));
+	}
+
+	let fnames = opts.override_entries(&pack, &manifest);
+
+	// everything about every file is known from here on, so nothing past this point talks to the API
+	// except for the downloads themselves
+	let settings = &opts.download;
+	let metadata = resolve_metadata(cf, &files, settings.parallel.threads(files.len()), &opts.cancel)?;
+	check_file_statuses(&metadata, &opts.allow_status)?;
+	check_incompatible(&metadata, opts.strict)?;
+	let total_bytes: u64 = metadata.values().map(|f| f.file_length.max(0) as u64).sum();
+	let override_bytes = override_size(&mut pack, &fnames);
+	if !opts.skip_space_check {
+		space::check(&game_dir, total_bytes + override_bytes)?;
+	}
+	if let Some(confirm) = &opts.confirm {
+		let plan = InstallPlan {
+			pack_name: manifest.name.clone(),
+			pack_version: manifest.version.clone(),
+			minecraft_version: manifest.minecraft.version.clone(),
+			game_dir: game_dir.clone(),
+			mods: files.len(),
+			mod_bytes: total_bytes,
+			unsized_mods: metadata.values().filter(|f| f.file_length <= 0).count(),
+			overrides_folder: manifest.overrides.clone(),
+			overrides: fnames.len(),
+			override_bytes,
+			fallback: metadata.values().filter(|f| f.download_url.is_none()).map(|f| f.file_name.clone()).collect()
+		};
+		if !confirm(&plan) {
+			return Err(AppError::Other("Install cancelled".into()));
+		}
+	}
+	let progress = Arc::new(Progress::new(Some(total_bytes)));
+	let cancel = opts.cancel.clone();
+	let mods = resolve_mods(cf, &files);
+	let download_opts = DownloadOptions {
+		game_dir: game_dir.clone(),
+		classes: class_ids(&mods),
+		slugs: mods.into_values().map(|m| (m.id, m.slug)).collect(),
+		observer: opts.observer.clone(),
+		mirrors: settings.mirrors.clone(),
+		expected_sha1,
+		connections: settings.max_connections.map(|n| Arc::new(Semaphore::new(n))),
+		rate_limit: settings.max_rate.map(|r| Arc::new(RateLimiter::new(r))),
+		progress: Some(progress.clone()),
+		metadata,
+		fail_cancel: if opts.fail_fast { Some(cancel.clone()) } else { None },
+		timeout: settings.timeout
+	};
+	let total = files.len();
+	let threads = settings.parallel.threads(total);
+	events::notify(observer, InstallEvent::DownloadsStart { files: total, bytes: Some(total_bytes) });
+	let results = download_all(cf, files, threads, &download_opts, &cancel);
+	events::notify(observer, InstallEvent::DownloadsDone { done: progress.done(), total: progress.total(), bytes_per_sec: progress.bytes_per_sec() });
+	
+	events::notify(observer, InstallEvent::ExtractStart { files: fnames.len() });
+	let extracting = fnames.len();
+	let (extracted, override_failures) = extract_overrides(&mut pack, fnames, &manifest.overrides, &game_dir, opts.fail_fast, &cancel, observer)?;
+	events::notify(observer, InstallEvent::ExtractDone { extracted: extracting - override_failures.len(), failed: override_failures.len() });
+
+	if opts.install_loader && !cancel.is_cancelled() {
+		loader::install_loader(&game_dir, &manifest, opts.server, cf.proxy())?;
+	}
+	if opts.scripts && !cancel.is_cancelled() {
+		scripts::write_server_scripts(&game_dir, &manifest, cf.proxy())?;
+	}
+
+	let mut summary = InstallSummary::from_results(results, total, extracted, override_failures);
+	summary.skipped = manifest.files.len().saturating_sub(total);
+	InstallRecord::update(&game_dir, &manifest, &summary, opts.server)?;
+	events::notify(observer, InstallEvent::summary(&summary));
+	Ok(summary)
+}
+
+// downloads whatever verify finds missing or corrupt. None if everything was already fine
+pub fn repair(cf: &Curseforge, manifest: &Manifest, install_dir: &Path, settings: &DownloadSettings) -> Result<Option<InstallSummary>, AppError> {
+	let report = verify::verify(cf, manifest, install_dir, settings.parallel)?;
+	// an unresolved file can't be downloaded either, CF has just said it can't find it
+	if report.unresolved > 0 {
+		output::warn(format!("Couldn't check or repair {} file(s) CF couldn't find", report.unresolved));
+	}
+	let broken: Vec<&verify::FileReport> = report.files.iter().filter(|f| matches!(f.status, verify::FileStatus::Missing | verify::FileStatus::Corrupt)).collect();
+	if broken.is_empty() {
+		output::success(format!("All {} mods are intact, nothing to repair", report.ok));
+		return Ok(None);
+	}
+	output::info(format!("Repairing {} missing and {} corrupt mod(s)", report.missing, report.corrupt));
+
+	let files: Vec<FileInfo> = broken.iter()
+		.map(|f| FileInfo { project_id: f.project_id, file_id: f.file_id, required: true })
+		.collect();
+	let total = files.len();
+	let expected_sha1 = broken.iter().filter_map(|f| f.sha1.clone().map(|h| (f.file_id, h))).collect();
+	let results = download_files(cf, files, &instance::detect_game_dir(install_dir), expected_sha1, settings, &Cancel::new())?;
+	Ok(Some(InstallSummary::from_results(results, total, Vec::new(), Vec::new())))
+}
+
+// a bare download of some files into a game dir, without the rest of an install around it
+pub(crate) fn download_files(cf: &Curseforge, files: Vec<FileInfo>, game_dir: &Path, expected_sha1: HashMap<i32, String>, settings: &DownloadSettings, cancel: &Cancel) -> Result<Vec<ModResult>, AppError> {
+	let threads = settings.parallel.threads(files.len());
+	let opts = DownloadOptions {
+		game_dir: game_dir.to_path_buf(),
+		classes: resolve_classes(cf, &files),
+		slugs: HashMap::new(),
+		observer: None,
+		mirrors: settings.mirrors.clone(),
+		expected_sha1,
+		connections: settings.max_connections.map(|n| Arc::new(Semaphore::new(n))),
+		rate_limit: settings.max_rate.map(|r| Arc::new(RateLimiter::new(r))),
+		progress: None,
+		metadata: resolve_metadata(cf, &files, threads, cancel)?,
+		fail_cancel: None,
+		timeout: settings.timeout
+	};
+	Ok(download_all(cf, files, threads, &opts, cancel))
+}
+
+// the entries under the pack's overrides folder, minus client-only ones for a server
+pub(crate) fn override_entries<T: Read + Seek>(pack: &ZipArchive<T>, manifest: &Manifest, server: bool) -> Vec<String> {
+	let prefix = format!("{}/", manifest.overrides);
+	if !pack.file_names().any(|f| f.starts_with(&prefix)) {
+		log::info!("Pack has no {} folder, nothing to extract", manifest.overrides);
+		return Vec::new();
+	}
+	pack.file_names()
+		.filter(|fname| match fname.strip_prefix(&prefix) {
+			Some(rest) => !(server && server::is_client_only_override(rest)),
+			None => false
+		})
+		.map(String::from)
+		.collect()
+}
+
+fn filter_overrides(fnames: Vec<String>, overrides: &str, opts: &InstallOptions) -> Vec<String> {
+	if opts.override_include.is_empty() && opts.override_exclude.is_empty() {
+		return fnames;
+	}
+	let prefix = format!("{}/", overrides);
+	let mut skipped = 0;
+	let kept: Vec<String> = fnames.into_iter()
+		.filter(|fname| {
+			// folder entries aren't extracted anyway, files create whatever folders they need
+			if fname.ends_with('/') {
+				return true;
+			}
+			let rel = fname.strip_prefix(&prefix).unwrap_or(fname);
+			let keep = (opts.override_include.is_empty() || glob::any_match(&opts.override_include, rel)) && !glob::any_match(&opts.override_exclude, rel);
+			if !keep {
+				log::info!("Skipping override {}", rel);
+				skipped += 1;
+			}
+			keep
+		})
+		.collect();
+	if skipped > 0 {
+		output::info(format!("Skipping {} override files because of --override-include/--override-exclude", skipped));
+	}
+	kept
+}
+
+pub(crate) fn override_size<T: Read + Seek>(pack: &mut ZipArchive<T>, fnames: &[String]) -> u64 {
+	fnames.iter().map(|fname| pack.by_name(fname).map(|e| e.size()).unwrap_or(0)).sum()
+}
+
+// the files that were written, and the ones that failed with why
+pub(crate) type Extracted = (Vec<InstalledFile>, Vec<(String, AppError)>);
+
+// failures are returned rather than stopping the install. fail_fast cancels the rest of it after the first one,
+// leaving the caller to record and report what did get done
+pub(crate) fn extract_overrides<T: Read + Seek>(pack: &mut ZipArchive<T>, fnames: Vec<String>, overrides: &str, game_dir: &Path, fail_fast: bool, cancel: &Cancel, observer: Option<&Arc<dyn InstallObserver>>) -> Result<Extracted, AppError> {
+	let mut extracted = Vec::new();
+	let mut failures = Vec::new();
+	for fname in fnames {
+		if cancel.is_cancelled() {
+			break;
+		}
+		match extract_override(pack, &fname, overrides, game_dir) {
+			Ok(file) => extracted.extend(file),
+			Err(e) => {
+				output::error(&e);
+				events::notify(observer, InstallEvent::ExtractError { path: fname.clone(), msg: e.to_string() });
+				failures.push((fname, e));
+				// like a failed download: stop here, but still hand back what was done so it gets recorded
+				if fail_fast {
+					cancel.cancel();
+				}
+			}
+		}
+	}
+	Ok((extracted, failures))
+}
+
+// None for folder entries
+fn extract_override<T: Read + Seek>(pack: &mut ZipArchive<T>, fname: &str, overrides: &str, install_to: &Path) -> Result<Option<InstalledFile>, AppError> {
+	let mut entry = try_read_zip_entry(pack, fname)?;
+	if entry.is_file() {
+		let name = entry
+			.enclosed_name()
+			.try_expect("Could not properly format name for writing to filesystem")?
+			.strip_prefix(format!("{}/", overrides))
+			.context("Error converting path")?
+			.to_owned();
+		let ext_path = contained_path(install_to, &name)?;
+		// straight from the zip to disk, overrides can include big resource packs
+		let mut out = hash::HashWriter::new(BufWriter::new(AtomicFile::create(&ext_path)?));
+		io::copy(&mut entry, &mut out).context(&format!("Error extracting {}", fname))?;
+		let (out, sha1) = out.finish();
+		out.into_inner().map_err(|e| AppError::Io(format!("Error writing {:?}", ext_path), e.into_error()))?.commit()?;
+		return Ok(Some(InstalledFile::new(install_to, &ext_path, sha1)));
+	}
+	Ok(None)
+}
+
+// joins an archive-supplied relative path onto base, refusing anything that would land outside it.
+// the components are checked before anything is created, then the parent is canonicalized so a
+// symlink already sitting in the install dir can't be used to walk back out either
+pub(crate) fn contained_path(base: &Path, rel: &Path) -> Result<PathBuf, AppError> {
+	if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+		return Err(AppError::Other(format!("Refusing to extract {:?}, it points outside the install directory", rel)));
+	}
+	let path = base.join(rel);
+	let parent = path.parent().try_expect("Extracted path has no parent directory")?;
+	try_mkdir(parent)?;
+	let base = base.canonicalize().context(&format!("Error resolving {:?}", base))?;
+	let parent = parent.canonicalize().context(&format!("Error resolving {:?}", parent))?;
+	if !parent.starts_with(&base) {
+		return Err(AppError::Other(format!("Refusing to extract {:?}, it resolves to {:?} outside the install directory", rel, parent)));
+	}
+	Ok(path)
+}
+
+fn download_all(cf: &Curseforge, files: Vec<FileInfo>, threads: u32, opts: &DownloadOptions, cancel: &Cancel) -> Vec<ModResult> {
+	let deadline = opts.timeout.and_then(|t| Instant::now().checked_add(t));
+	// by file id, so whatever's left when the time runs out can be named
+	let running = Mutex::new(BTreeSet::new());
+	ScopedPool::new(threads).run_watched(files, cancel, |file| {
+		running.lock().insert(file.file_id);
+		let result = download_mod(file, cf, opts, deadline);
+		running.lock().remove(&result.file_id);
+		result
+	}, |wait| loop {
+		// a progress report every REPORT_INTERVAL, with the deadline cutting the last wait short
+		let step = deadline.map_or(progress::REPORT_INTERVAL, |d| d.saturating_duration_since(Instant::now()).min(progress::REPORT_INTERVAL));
+		if wait.wait_timeout(step) {
+			break;
+		}
+		if let (Some(deadline), Some(timeout)) = (deadline, opts.timeout) {
+			if Instant::now() >= deadline {
+				cancel.cancel();
+				let stragglers: Vec<String> = running.lock().iter()
+					.map(|id| opts.metadata.get(id).map_or_else(|| format!("file {}", id), |f| f.file_name.clone()))
+					.collect();
+				output::warn(format!("Downloads didn't finish within {}, not starting any more. Giving up on: {}",
+					humantime::format_duration(timeout), stragglers.join(", ")));
+				break;
+			}
+		}
+		if let Some(p) = &opts.progress {
+			events::notify(opts.observer.as_ref(), InstallEvent::Progress {
+				files_done: wait.completed(),
+				files: wait.total(),
+				done: p.done(),
+				total: p.total(),
+				bytes_per_sec: p.bytes_per_sec()
+			});
+		}
+	})
+}
+
+#[derive(Clone)]
+struct DownloadOptions {
+	game_dir: PathBuf,
+	// project id to CF class, decides which folder a file goes in
+	classes: HashMap<i32, i32>,
+	// by project id, only used to label events
+	slugs: HashMap<i32, String>,
+	observer: Option<Arc<dyn InstallObserver>>,
+	mirrors: Vec<String>,
+	// by file id, only set for locked installs
+	expected_sha1: HashMap<i32, String>,
+	// shared by all workers, caps simultaneous CDN downloads below the thread count
+	connections: Option<Arc<Semaphore>>,
+	// also shared, paces the bytes written across all workers
+	rate_limit: Option<Arc<RateLimiter>>,
+	progress: Option<Arc<Progress>>,
+	metadata: HashMap<i32, api::File>,
+	fail_cancel: Option<Cancel>,
+	timeout: Option<Duration>
+}
+
+fn download_mod(file: FileInfo, cf: &Curseforge, opts: &DownloadOptions, deadline: Option<Instant>) -> ModResult {
+	let (outcome, installed) = match download(&file, cf, opts, deadline) {
+		Ok((o, installed)) => (o, Some(installed)),
+		Err(e) => {
+			output::error(&e);
+			events::notify(opts.observer.as_ref(), InstallEvent::DownloadError {
+				project_id: file.project_id,
+				file_id: file.file_id,
+				slug: opts.slugs.get(&file.project_id).cloned(),
+				msg: e.to_string()
+			});
+			if let Some(cancel) = &opts.fail_cancel {
+				cancel.cancel();
+			}
+			(Outcome::Failed(e), None)
+		}
+	};
+	ModResult {
+		project_id: file.project_id,
+		file_id: file.file_id,
+		outcome,
+		installed
+	}
+}
+
+// past the deadline, the request times out mid-download and leaves a .part to resume
+fn download(file: &FileInfo, cf: &Curseforge, opts: &DownloadOptions, deadline: Option<Instant>) -> Result<(Outcome, InstalledFile), AppError> {
+	let meta = opts.metadata.get(&file.file_id).try_expect("File metadata wasn't resolved before downloading")?;
+	let url = meta.download_url.clone().unwrap_or_else(|| Curseforge::cdn_url(meta.id, &meta.file_name));
+	let filename = meta.file_name.clone();
+	let dir = opts.game_dir.join(instance::content_folder(opts.classes.get(&file.project_id).copied()));
+	try_mkdir(&dir)?;
+	let path = contained_path(&dir, Path::new(&filename))?;
+	// anything left over from an interrupted run gets picked up where it stopped
+	let part = part_path(&path);
+	let offset = fs::metadata(&part).map(|m| m.len()).unwrap_or(0);
+
+	let _permit = opts.connections.as_ref().map(|c| c.acquire());
+	// rounded up past the deadline, so download_all gets to name it as a straggler before it's cut off
+	let timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()).as_secs() + 1);
+	let mut response = None;
+	for mirror in &opts.mirrors {
+		if let Some(mirror_url) = mirror_url(mirror, &url) {
+			match fetch_streaming_timeout(&mirror_url, cf.proxy(), offset, timeout) {
+				Ok(r) => {
+					response = Some(r);
+					break;
+				},
+				Err(e) => output::warn(format!("Mirror failed, trying next: {}", e))
+			}
+		}
+	}
+	let response = match response {
+		Some(r) => r,
+		None => fetch_streaming_timeout(&url, cf.proxy(), offset, timeout)?
+	};
+
+	let resumed = offset > 0 && response.is_partial();
+	let already = if resumed { offset } else { 0 };
+	let expected_len = response.content_length();
+	// CF has files with a fileLength of 0, for those the response is the only word on how big they are
+	let total_len = match meta.file_length {
+		len if len > 0 => Some(len as u64),
+		_ => {
+			let len = expected_len.map(|len| already + len);
+			if let (Some(progress), Some(len)) = (&opts.progress, len) {
+				progress.grow(len);
+			}
+			len
+		}
+	};
+	let slug = opts.slugs.get(&file.project_id).cloned();
+	events::notify(opts.observer.as_ref(), InstallEvent::DownloadStart {
+		project_id: file.project_id,
+		file_id: file.file_id,
+		slug: slug.clone(),
+		file_name: filename.clone(),
+		bytes: total_len
+	});
+
+	let mut hasher = hash::Sha1Stream::new();
+	if resumed {
+		log::info!("Resuming {} from byte {}", filename, offset);
+		hasher.update_from(try_open(&part)?).context(&format!("Error reading {:?}", part))?;
+		if let Some(progress) = &opts.progress {
+			progress.resumed(offset);
+		}
+	} else if offset > 0 {
+		log::info!("Server won't resume {}, starting over", filename);
+	}
+	let started = Instant::now();
+	let mut last_event = Instant::now();
+	let written = write_stream(response, &part, resumed, &mut hasher, opts, |written| {
+		if opts.observer.is_some() && last_event.elapsed() >= PROGRESS_EVENT_INTERVAL {
+			last_event = Instant::now();
+			events::notify(opts.observer.as_ref(), InstallEvent::DownloadProgress {
+				project_id: file.project_id,
+				file_id: file.file_id,
+				slug: slug.clone(),
+				done: already + written,
+				total: total_len
+			});
+		}
+	})?;
+	if expected_len.is_some_and(|len| len != written) {
+		return Err(AppError::Other(format!("Download of {} ended early, run the install again to resume it", filename)));
+	}
+	let size = already + written;
+	if let Some(total_len) = total_len.filter(|len| *len != size) {
+		// a short file can still be resumed, a long one is just wrong
+		if size > total_len {
+			let _ = fs::remove_file(&part);
+		}
+		return Err(AppError::Other(format!("Size mismatch for {}: expected {} bytes, got {}", filename, total_len, size)));
+	}
+
+	let sha1 = hasher.hex();
+	let outcome = match opts.expected_sha1.get(&file.file_id) {
+		Some(expected) if !sha1.eq_ignore_ascii_case(expected) => {
+			// not worth resuming a corrupt file
+			let _ = fs::remove_file(&part);
+			return Err(AppError::Other(format!("Hash mismatch for {}: expected sha1 {}, got {}", filename, expected, sha1)));
+		},
+		Some(_) => Outcome::Verified,
+		None => Outcome::Downloaded
+	};
+	atomic::replace(&part, &path).context(&format!("Error moving {:?} into place", part))?;
+	events::notify(opts.observer.as_ref(), InstallEvent::DownloadDone { project_id: file.project_id, file_id: file.file_id, slug, bytes: size });
+	let elapsed = started.elapsed();
+	output::info(format!("Finished {} ({} in {:.1}s, {}/s)", filename, progress::format_bytes(written), elapsed.as_secs_f64(),
+		progress::format_bytes(progress::rate(written, elapsed) as u64)));
+	Ok((outcome, InstalledFile::new(&opts.game_dir, &path, sha1)))
+}
+
+// by project id. anything missing is treated as a mod
+pub fn resolve_classes(cf: &Curseforge, files: &[FileInfo]) -> HashMap<i32, i32> {
+	class_ids(&resolve_mods(cf, files))
+}
+
+// by project id, empty if CF couldn't be reached
+pub fn resolve_mods(cf: &Curseforge, files: &[FileInfo]) -> HashMap<i32, Mod> {
+	let project_ids: Vec<i32> = files.iter().map(|f| f.project_id).collect();
+	match cf.get_mods(&project_ids) {
+		Ok(mods) => mods.into_iter().map(|m| (m.id, m)).collect(),
+		Err(e) => {
+			output::warn(format!("Couldn't get mod metadata, everything will go in mods/: {}", e));
+			HashMap::new()
+		}
+	}
+}
+
+fn class_ids(mods: &HashMap<i32, Mod>) -> HashMap<i32, i32> {
+	mods.values().filter_map(|m| m.class_id.map(|c| (m.id, c))).collect()
+}
+
+// by file id. downloads still work without it, they just can't be size checked
+// a file that can't be resolved stops the install before anything is downloaded
+pub fn resolve_metadata(cf: &Curseforge, files: &[FileInfo], threads: u32, cancel: &Cancel) -> Result<HashMap<i32, api::File>, AppError> {
+	let (metadata, unresolved) = try_resolve_metadata(cf, files, threads, cancel)?;
+	if unresolved.is_empty() {
+		return Ok(metadata);
+	}
+	let mut failed: Vec<String> = unresolved.iter()
+		.map(|(file, e)| format!("project {} file {} ({})", file.project_id, file.file_id, e))
+		.collect();
+	failed.sort();
+	Err(AppError::Other(format!("Couldn't get the details of {} file(s) from CF, nothing was downloaded: {}", failed.len(), failed.join(", "))))
+}
+
+// the metadata that could be got by file id, and the files it couldn't be got for with why
+pub(crate) type Resolved = (HashMap<i32, api::File>, Vec<(FileInfo, ApiError)>);
+
+// one batch request for everything, then one request each for whatever the batch didn't return (CF leaves
+// out some files, and the whole batch can fail)
+pub(crate) fn try_resolve_metadata(cf: &Curseforge, files: &[FileInfo], threads: u32, cancel: &Cancel) -> Result<Resolved, AppError> {
+	let file_ids: Vec<i32> = files.iter().map(|f| f.file_id).collect();
+	let mut metadata: HashMap<i32, api::File> = match cf.get_files(&file_ids) {
+		Ok(resolved) => resolved.into_iter().map(|f| (f.id, f)).collect(),
+		Err(e) => {
+			output::warn(format!("Couldn't get file metadata in one go, fetching it file by file: {}", e));
+			HashMap::new()
+		}
+	};
+	let missing: Vec<&FileInfo> = files.iter().filter(|f| !metadata.contains_key(&f.file_id)).collect();
+	if missing.is_empty() {
+		return Ok((metadata, Vec::new()));
+	}
+	log::info!("Fetching metadata for {} files one at a time", missing.len());
+	let results = ScopedPool::new(threads).run(missing, cancel, |f| (f, cf.get_mod_file(f.project_id, f.file_id)));
+	if cancel.is_cancelled() {
+		return Err(AppError::Interrupted);
+	}
+	let mut unresolved = Vec::new();
+	for (file, result) in results {
+		match result {
+			Ok(meta) => {
+				metadata.insert(meta.id, meta);
+			},
+			Err(e) => {
+				log::warn!("Couldn't resolve project {} file {}: {}", file.project_id, file.file_id, e);
+				unresolved.push((file.clone(), e));
+			}
+		}
+	}
+	Ok((metadata, unresolved))
+}
+
+// anything CF hasn't approved gets a warning, but a malware flag stops the install unless explicitly allowed
+fn check_file_statuses(metadata: &HashMap<i32, api::File>, allowed: &[FileStatus]) -> Result<(), AppError> {
+	let mut flagged = Vec::new();
+	for file in metadata.values() {
+		let status = file.file_status;
+		if status.is_released() || allowed.contains(&status) {
+			continue;
+		}
+		if status == FileStatus::MalwareDetected {
+			flagged.push(format!("{} (project {}, file {})", file.file_name, file.mod_id, file.id));
+		} else {
+			output::warn(format!("{} (project {}, file {}) has status {}, installing it anyway", file.file_name, file.mod_id, file.id, status));
+		}
+	}
+	if flagged.is_empty() {
+		Ok(())
+	} else {
+		flagged.sort();
+		Err(AppError::Other(format!("CurseForge flagged malware in {}; refusing to install (pass --allow-status malware-detected to override)", flagged.join(", "))))
+	}
+}
+
+// a file can name projects it doesn't work with. either side saying so is enough, and a pair is only reported once
+fn check_incompatible(metadata: &HashMap<i32, api::File>, strict: bool) -> Result<(), AppError> {
+	let by_project: HashMap<i32, &api::File> = metadata.values().map(|f| (f.mod_id, f)).collect();
+	let mut pairs = BTreeSet::new();
+	for file in metadata.values() {
+		for dep in file.dependencies.iter().filter(|d| d.relation_type == FileRelationType::Incompatible) {
+			if dep.mod_id != file.mod_id && by_project.contains_key(&dep.mod_id) {
+				pairs.insert((file.mod_id.min(dep.mod_id), file.mod_id.max(dep.mod_id)));
+			}
+		}
+	}
+	let conflicts: Vec<String> = pairs.into_iter()
+		.map(|(a, b)| format!("{} (project {}) and {} (project {})", by_project[&a].file_name, a, by_project[&b].file_name, b))
+		.collect();
+	if strict && !conflicts.is_empty() {
+		return Err(AppError::Other(format!("Pack has mods marked incompatible with each other: {}", conflicts.join("; "))));
+	}
+	for conflict in conflicts {
+		output::warn(format!("Incompatible mods in the pack: {}", conflict));
+	}
+	Ok(())
+}
+
+pub(crate) fn part_path(path: &Path) -> PathBuf {
+	let mut part = path.to_path_buf().into_os_string();
+	part.push(".part");
+	PathBuf::from(part)
+}
+
+// copies a chunk at a time so memory use doesn't grow with the file size or thread count, returns how much was written
+fn write_stream(mut body: impl Read, path: &Path, append: bool, hasher: &mut hash::Sha1Stream, opts: &DownloadOptions, mut on_chunk: impl FnMut(u64)) -> Result<u64, AppError> {
+	let mut out = if append {
+		fs::OpenOptions::new().append(true).open(path).context(&format!("Error opening file {:?}", path))?
+	} else {
+		try_open_write(path)?
+	};
+	let mut buf = vec![0; DOWNLOAD_CHUNK_SIZE];
+	let mut written = 0;
+	loop {
+		let read = body.read(&mut buf).context(&format!("Error downloading {:?}", path))?;
+		if read == 0 {
+			break;
+		}
+		if let Some(limiter) = &opts.rate_limit {
+			limiter.acquire(read as u64);
+		}
+		hasher.update(&buf[..read]);
+		out.write_all(&buf[..read]).context(&format!("Error writing downloaded file {:?}", path))?;
+		written += read as u64;
+		if let Some(progress) = &opts.progress {
+			progress.transferred(read as u64);
+		}
+		on_chunk(written);
+	}
+	Ok(written)
+}
+
+// swaps the scheme and host of a CDN url for the mirror's, keeping the path
+fn mirror_url(mirror: &str, url: &str) -> Option<String> {
+	let (_, rest) = url.split_once("://")?;
+	let path = &rest[rest.find('/')?..];
+	Some(format!("{}{}", mirror.trim_end_matches('/'), path))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, process};
+
+	use super::*;
+	use crate::tests::zip_of;
+
+	fn scratch_dir(name: &str) -> PathBuf {
+		let dir = env::temp_dir().join(format!("underlion-{}-{}", name, process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir.canonicalize().unwrap()
+	}
+
+	#[test]
+	fn malicious_overrides_are_refused() {
+		let dir = scratch_dir("evil-zip");
+		let game_dir = dir.join("game");
+		fs::create_dir_all(&game_dir).unwrap();
+		let evil_abs = dir.join("evil-abs");
+		let evil_abs_name = evil_abs.to_str().unwrap().to_string();
+		let mut pack = zip_of(&[
+			("overrides/config/ok.cfg", "ok"),
+			("overrides/../../evil", "evil"),
+			(&evil_abs_name, "evil"),
+			("overrides/options.txt", "fine")
+		]);
+		let fnames = pack.file_names().map(String::from).collect();
+		let (extracted, failures) = extract_overrides(&mut pack, fnames, "overrides", &game_dir, false, &Cancel::new(), None).unwrap();
+
+		let mut written: Vec<&str> = extracted.iter().map(|f| f.path.as_str()).collect();
+		written.sort();
+		assert_eq!(written, ["config/ok.cfg", "options.txt"]);
+		assert_eq!(fs::read_to_string(game_dir.join("config/ok.cfg")).unwrap(), "ok");
+		let mut refused: Vec<&str> = failures.iter().map(|(name, _)| name.as_str()).collect();
+		refused.sort();
+		assert_eq!(refused, [evil_abs_name.as_str(), "overrides/../../evil"]);
+		assert!(!dir.join("evil").exists() && !env::temp_dir().join("evil").exists());
+		assert!(!evil_abs.exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	// the first failure stops the extraction, but what was already written still comes back to be recorded
+	#[test]
+	fn fail_fast_keeps_what_was_extracted() {
+		let dir = scratch_dir("fail-fast");
+		let mut pack = zip_of(&[
+			("overrides/first.txt", "1"),
+			("overrides/../../evil", "evil"),
+			("overrides/last.txt", "2")
+		]);
+		let fnames = vec!["overrides/first.txt".to_string(), "overrides/../../evil".to_string(), "overrides/last.txt".to_string()];
+		let cancel = Cancel::new();
+		let (extracted, failures) = extract_overrides(&mut pack, fnames, "overrides", &dir, true, &cancel, None).unwrap();
+		assert_eq!(extracted.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(), ["first.txt"]);
+		assert_eq!(failures.len(), 1);
+		assert!(cancel.is_cancelled());
+		assert!(!dir.join("last.txt").exists());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn contained_path_stays_in_base() {
+		let dir = scratch_dir("contained");
+		assert_eq!(contained_path(&dir, Path::new("config/a.cfg")).unwrap(), dir.join("config/a.cfg"));
+		assert!(contained_path(&dir, Path::new("../evil")).is_err());
+		assert!(contained_path(&dir, Path::new("config/../../evil")).is_err());
+		assert!(contained_path(&dir, &dir.join("evil")).is_err());
+		#[cfg(unix)]
+		{
+			let outside = scratch_dir("contained-outside");
+			std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+			assert!(contained_path(&dir, Path::new("link/evil")).is_err());
+			fs::remove_dir_all(&outside).unwrap();
+		}
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,123 @@
+use std::{path::{Path, PathBuf}};
+
+use clap::ArgEnum;
+use serde::Serialize;
+
+use crate::{api::{CLASS_DATA_PACKS, CLASS_RESOURCE_PACKS, CLASS_SHADERS}, atomic, error::AppError, manifest::{LoaderId, LoaderInfo, LoaderKind, Manifest}};
+
+#[derive(ArgEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InstanceFormat {
+	#[default]
+	Plain,
+	Prism
+}
+
+#[derive(Serialize)]
+struct MmcPack {
+	components: Vec<MmcComponent>,
+	#[serde(rename = "formatVersion")]
+	format_version: u32
+}
+
+#[derive(Serialize)]
+struct MmcComponent {
+	uid: String,
+	version: String,
+	#[serde(skip_serializing_if = "std::ops::Not::not")]
+	important: bool
+}
+
+impl MmcComponent {
+	fn new(uid: &str, version: &str) -> Self {
+		Self {
+			uid: uid.to_string(),
+			version: version.to_string(),
+			important: false
+		}
+	}
+}
+
+impl InstanceFormat {
+	// sets up the instance skeleton and returns where mods and overrides should go
+	pub fn prepare(&self, install_to: &Path, manifest: &Manifest) -> Result<PathBuf, AppError> {
+		match self {
+			Self::Plain => Ok(install_to.to_path_buf()),
+			Self::Prism => {
+				let mut pack = MmcPack {
+					components: vec![MmcComponent {
+						important: true,
+						..MmcComponent::new("net.minecraft", &manifest.minecraft.version)
+					}],
+					format_version: 1
+				};
+				for loader in &manifest.minecraft.mod_loaders {
+					pack.components.extend(loader_components(loader, &manifest.minecraft.version)?);
+				}
+
+				let mut pack_path = install_to.to_path_buf();
+				pack_path.push("mmc-pack.json");
+				let pack_json = serde_json::to_string_pretty(&pack)
+					.map_err(|e| AppError::Other(format!("Error serializing mmc-pack.json: {}", e)))?;
+				atomic::write(&pack_path, pack_json)?;
+
+				let mut cfg_path = install_to.to_path_buf();
+				cfg_path.push("instance.cfg");
+				let cfg = format!("InstanceType=OneSix\nname={}\n", manifest.name);
+				atomic::write(&cfg_path, cfg)?;
+
+				let mut game_dir = install_to.to_path_buf();
+				game_dir.push(".minecraft");
+				Ok(game_dir)
+			}
+		}
+	}
+}
+
+// where the vanilla launcher keeps its game folder on this OS
+pub fn default_minecraft_dir() -> Option<PathBuf> {
+	if cfg!(windows) {
+		// %APPDATA%, the roaming one
+		dirs::config_dir().map(|d| d.join(".minecraft"))
+	} else if cfg!(target_os = "macos") {
+		dirs::data_dir().map(|d| d.join("minecraft"))
+	} else {
+		dirs::home_dir().map(|d| d.join(".minecraft"))
+	}
+}
+
+// plain installs keep everything at the top level, Prism instances under .minecraft
+pub fn detect_game_dir(install_dir: &Path) -> PathBuf {
+	let prism = install_dir.join(".minecraft");
+	if !install_dir.join("mods").is_dir() && prism.is_dir() {
+		prism
+	} else {
+		install_dir.to_path_buf()
+	}
+}
+
+// where a project's files go in the game dir, by CF class. data packs are per-world and there's no
+// world at install time, so they go to the global datapacks folder that loader mods like Global Data Packs read
+pub fn content_folder(class_id: Option<i32>) -> &'static str {
+	match class_id {
+		Some(CLASS_RESOURCE_PACKS) => "resourcepacks",
+		Some(CLASS_SHADERS) => "shaderpacks",
+		Some(CLASS_DATA_PACKS) => "datapacks",
+		_ => "mods"
+	}
+}
+
+fn loader_components(loader: &LoaderInfo, mc_version: &str) -> Result<Vec<MmcComponent>, AppError> {
+	let LoaderId { kind, version } = loader.parse().map_err(AppError::Loader)?;
+	Ok(match kind {
+		LoaderKind::Forge => vec![MmcComponent::new("net.minecraftforge", &version)],
+		LoaderKind::NeoForge => vec![MmcComponent::new("net.neoforged", &version)],
+		LoaderKind::Fabric => vec![
+			MmcComponent::new("net.fabricmc.intermediary", mc_version),
+			MmcComponent::new("net.fabricmc.fabric-loader", &version)
+		],
+		LoaderKind::Quilt => vec![
+			MmcComponent::new("net.fabricmc.intermediary", mc_version),
+			MmcComponent::new("org.quiltmc.quilt-loader", &version)
+		]
+	})
+}
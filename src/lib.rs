@@ -0,0 +1,349 @@
+pub mod threadpool;
+pub mod manifest;
+pub mod api;
+pub mod http;
+pub mod error;
+pub mod summary;
+pub mod proxy;
+pub mod output;
+pub mod instance;
+mod server;
+mod loader;
+mod scripts;
+pub mod merge;
+pub mod diff;
+pub mod changelog;
+pub mod edit;
+pub mod export;
+mod hash;
+pub mod lock;
+pub mod verify;
+pub mod cache;
+pub mod atomic;
+pub mod ratelimit;
+mod progress;
+mod space;
+pub mod filter;
+pub mod glob;
+pub mod install;
+pub mod events;
+pub mod cfinstance;
+pub mod offline;
+pub mod plan;
+pub mod grabkey;
+pub mod doctor;
+pub mod search;
+pub mod record;
+pub mod update;
+#[cfg(feature = "async")]
+pub mod asyncapi;
+
+use std::{env, fs::{self, File}, io::{self, BufWriter, Read, Seek}, path::{Path, PathBuf}, process};
+
+use atomic::AtomicFile;
+use api::ApiError;
+use error::{AppError, ErrorContext};
+use manifest::Manifest;
+use proxy::ProxyConfig;
+use zip::{ZipArchive, read::ZipFile};
+
+pub use api::Curseforge;
+#[cfg(feature = "async")]
+pub use asyncapi::AsyncCurseforge;
+pub use events::{InstallEvent, InstallObserver};
+pub use install::{install_pack, DownloadSettings, InstallOptions, Parallelism};
+pub use summary::InstallSummary;
+pub use threadpool::Cancel;
+
+#[cfg(not(any(feature = "rustls", feature = "native-tls")))]
+compile_error!("enable the rustls or native-tls feature, every CurseForge endpoint is https");
+
+pub const MANIFEST_NAME: &str = "manifest.json";
+const MODRINTH_INDEX_NAME: &str = "modrinth.index.json";
+
+pub fn fetch(url: &str, proxy: &ProxyConfig) -> Result<minreq::Response, AppError> {
+	output::info(format!("Downloading {}", url));
+	let response = proxy.apply(minreq::get(url), url)
+		.context("Invalid proxy")?
+		.send()
+		.context(&format!("Error downloading file {}", url))?;
+	log::debug!("{} responded {}", url, response.status_code);
+	api::check_status(response.status_code, url).context(&format!("Error downloading file {}", url))?;
+	Ok(response)
+}
+
+// a non-zero offset asks the server for just the rest of the file, check is_partial() for whether it obliged
+pub(crate) fn fetch_streaming(url: &str, proxy: &ProxyConfig, offset: u64) -> Result<StreamingBody, AppError> {
+	fetch_streaming_timeout(url, proxy, offset, None)
+}
+
+// the timeout covers the whole download, not just connecting, so it needs to allow for big files on slow links
+pub(crate) fn fetch_streaming_timeout(url: &str, proxy: &ProxyConfig, offset: u64, timeout: Option<u64>) -> Result<StreamingBody, AppError> {
+	output::info(format!("Downloading {}", url));
+	match send_streaming(url, proxy, offset, timeout) {
+		// nothing past the offset, so whatever's in the .part isn't the start of the file the server has now.
+		// the full response isn't partial, which tells the caller to start over
+		Err(AppError::Api(_, ApiError::BadHTTPResponse(_, 416))) if offset > 0 => {
+			log::info!("{} can't resume from byte {}, downloading it from the start", url, offset);
+			send_streaming(url, proxy, 0, timeout)
+		},
+		result => result
+	}
+}
+
+fn send_streaming(url: &str, proxy: &ProxyConfig, offset: u64, timeout: Option<u64>) -> Result<StreamingBody, AppError> {
+	let mut request = minreq::get(url);
+	if let Some(secs) = timeout {
+		request = request.with_timeout(secs);
+	}
+	if offset > 0 {
+		request = request.with_header("Range", format!("bytes={}-", offset));
+	}
+	let response = proxy.apply(request, url)
+		.context("Invalid proxy")?
+		.send_lazy()
+		.context(&format!("Error downloading file {}", url))?;
+	log::debug!("{} responded {}", url, response.status_code);
+	api::check_status(response.status_code, url).context(&format!("Error downloading file {}", url))?;
+	Ok(StreamingBody(response))
+}
+
+// minreq's lazy response is a byte iterator, this makes it usable with io::copy and friends
+pub(crate) struct StreamingBody(minreq::ResponseLazy);
+
+impl StreamingBody {
+	pub(crate) fn is_partial(&self) -> bool {
+		self.0.status_code == 206
+	}
+
+	pub(crate) fn content_length(&self) -> Option<u64> {
+		// minreq keeps header names as the server sent them
+		self.0.headers.iter()
+			.find(|(k, _)| k.eq_ignore_ascii_case("content-length"))
+			.and_then(|(_, v)| v.trim().parse().ok())
+	}
+}
+
+impl Read for StreamingBody {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut read = 0;
+		while read < buf.len() {
+			match self.0.next() {
+				Some(Ok((byte, _))) => {
+					buf[read] = byte;
+					read += 1;
+				},
+				Some(Err(e)) => return Err(io::Error::other(e)),
+				None => break
+			}
+		}
+		Ok(read)
+	}
+}
+
+// streams to disk rather than holding the whole body in memory, packs can be big
+pub fn fetch_to_file(url: &str, proxy: &ProxyConfig, path: &Path) -> Result<(), AppError> {
+	let mut body = fetch_streaming(url, proxy, 0)?;
+	let mut out = BufWriter::new(AtomicFile::create(path)?);
+	io::copy(&mut body, &mut out).context(&format!("Error downloading file {}", url))?;
+	out.into_inner().map_err(|e| AppError::Io(format!("Error writing {:?}", path), e.into_error()))?.commit()
+}
+
+// the last path segment of a URL, without any query string or fragment and with %XX escapes decoded
+pub fn url_file_name(url: &str) -> Option<String> {
+	let path = url.split(['?', '#']).next().unwrap_or(url);
+	let segment = path.rsplit('/').next().filter(|s| !s.is_empty())?;
+	let bytes = segment.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let hex = bytes.get(i + 1..i + 3).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+		match (bytes[i], hex) {
+			(b'%', Some(byte)) => {
+				decoded.push(byte);
+				i += 3;
+			},
+			(b, _) => {
+				decoded.push(b);
+				i += 1;
+			}
+		}
+	}
+	Some(String::from_utf8_lossy(&decoded).into_owned())
+}
+
+pub fn try_load_file(file: &Path) -> Result<String, AppError> {
+	fs::read_to_string(file).context(&format!("Error opening file {:?}", file))
+}
+
+// a file in the system temp dir that deletes itself when dropped
+pub struct TempFile(PathBuf);
+
+impl TempFile {
+	pub fn new(name: &str) -> Self {
+		Self(env::temp_dir().join(format!("underlion-{}-{}", process::id(), name)))
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempFile {
+	fn drop(&mut self) {
+		let _ = fs::remove_file(&self.0);
+	}
+}
+
+pub fn try_mkdir(path: &Path) -> Result<(), AppError> {
+	fs::create_dir_all(path).context(&format!("Error creating directory {:?}", path))
+}
+
+pub fn try_open(path: &Path) -> Result<File, AppError> {
+	File::open(path).context(&format!("Error opening file {:?}", path))
+}
+
+pub(crate) fn try_open_write(path: &Path) -> Result<File, AppError> {
+	File::create(path).context(&format!("Error opening file {:?}", path))
+}
+
+pub fn try_open_zip(path: &Path) -> Result<ZipArchive<File>, AppError> {
+	let file = try_open(path)?;
+	ZipArchive::new(file).context(&format!("Error opening pack zip {:?}", path))
+}
+
+pub fn try_read_zip_entry<'a, T: Read + Seek>(zip: &'a mut ZipArchive<T>, loc: &str) -> Result<ZipFile<'a>, AppError> {
+	zip.by_name(loc).context(&format!("Error reading zip entry {}", loc))
+}
+
+pub fn try_open_pack(path: &Path) -> Result<(ZipArchive<File>, Manifest), AppError> {
+	let mut zip = try_open_zip(path)?;
+	if find_manifest(&zip).is_none() && zip.by_name(MODRINTH_INDEX_NAME).is_ok() {
+		return Err(AppError::Other(format!("{:?} is a Modrinth pack, only CurseForge packs are supported", path)));
+	}
+	let manifest = try_read_pack_manifest(&mut zip)?;
+	Ok((zip, manifest))
+}
+
+// the manifest belongs at the root, but zipping the pack's folder instead of its contents puts everything a level
+// down. the shallowest one wins, since overrides can have other tools' manifest.json files deeper in
+pub fn find_manifest<T: Read + Seek>(zip: &ZipArchive<T>) -> Option<String> {
+	if zip.file_names().any(|n| n == MANIFEST_NAME) {
+		return Some(MANIFEST_NAME.into());
+	}
+	zip.file_names()
+		.filter(|n| n.rsplit('/').next() == Some(MANIFEST_NAME) && !n.starts_with("__MACOSX/"))
+		.min_by_key(|n| n.matches('/').count())
+		.map(String::from)
+}
+
+// reads the manifest wherever find_manifest finds it. when it's nested, overrides is moved under the same
+// folder, so it's still the prefix of the override entries
+pub fn try_read_pack_manifest<T: Read + Seek>(zip: &mut ZipArchive<T>) -> Result<Manifest, AppError> {
+	let name = find_manifest(zip).ok_or_else(|| AppError::Other(format!("No {} in the pack zip", MANIFEST_NAME)))?;
+	let mut manifest = try_read_manifest(&mut try_read_zip_entry(zip, &name)?)?;
+	if let Some(root) = name.strip_suffix(MANIFEST_NAME).filter(|r| !r.is_empty()) {
+		output::warn(format!("The pack's manifest is in {}, not at the top of the zip, using that", root));
+		manifest.overrides = format!("{}{}", root, manifest.overrides);
+	}
+	Ok(manifest)
+}
+
+// a pack zip, a bare manifest.json, or the CurseForge app's minecraftinstance.json
+pub fn try_load_manifest(path: &Path) -> Result<Manifest, AppError> {
+	if path.file_name().is_some_and(|n| n == cfinstance::INSTANCE_FILE_NAME) {
+		Ok(cfinstance::CfInstance::read(path)?.to_manifest())
+	} else if path.extension().is_some_and(|e| e == "json") {
+		try_read_manifest(&mut try_open(path)?)
+	} else {
+		try_open_pack(path).map(|(_, manifest)| manifest)
+	}
+}
+
+pub fn try_read_manifest<T: Read>(t: &mut T) -> Result<Manifest, AppError> {
+	let mut bytes = Vec::new();
+	t.read_to_end(&mut bytes).context("Error reading manifest")?;
+	// some editors save json with a UTF-8 BOM in front, which serde won't skip on its own
+	let json = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(&bytes).trim_ascii_start();
+	let value: serde_json::Value = serde_json::from_slice(json).map_err(AppError::Manifest)?;
+	manifest::check_format(&value).map_err(AppError::Format)?;
+	let manifest: Manifest = serde_json::from_value(value).map_err(AppError::Manifest)?;
+	for warning in manifest.minecraft.validate_loaders() {
+		output::warn(warning);
+	}
+	Ok(manifest)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use std::io::{Cursor, Write};
+
+	use zip::{ZipWriter, write::FileOptions};
+
+	use super::*;
+
+	pub(crate) fn zip_of(entries: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+		let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+		for (name, contents) in entries {
+			zip.start_file(*name, FileOptions::default()).unwrap();
+			zip.write_all(contents.as_bytes()).unwrap();
+		}
+		ZipArchive::new(zip.finish().unwrap()).unwrap()
+	}
+
+	const MINIMAL_MANIFEST: &str = r#"{
+		"minecraft": {"version": "1.20.1", "modLoaders": [{"id": "forge-47.2.0", "primary": true}]},
+		"manifestType": "minecraftModpack", "manifestVersion": 1,
+		"name": "Test", "version": "1.0", "author": "me",
+		"files": []
+	}"#;
+
+	#[test]
+	fn url_file_names() {
+		assert_eq!(url_file_name("https://example.com/packs/My%20Pack%201.0.zip").as_deref(), Some("My Pack 1.0.zip"));
+		assert_eq!(url_file_name("https://example.com/pack.zip?token=abc&x=1").as_deref(), Some("pack.zip"));
+		assert_eq!(url_file_name("https://example.com/pack.zip#files").as_deref(), Some("pack.zip"));
+		assert_eq!(url_file_name("https://example.com/packs/").as_deref(), None);
+		assert_eq!(url_file_name("https://example.com/packs/?name=pack.zip").as_deref(), None);
+		// not valid escapes, so left as they are
+		assert_eq!(url_file_name("https://example.com/100%zz.zip").as_deref(), Some("100%zz.zip"));
+		assert_eq!(url_file_name("https://example.com/pack%2").as_deref(), Some("pack%2"));
+	}
+
+	#[test]
+	fn minimal_manifest() {
+		let manifest = try_read_manifest(&mut MINIMAL_MANIFEST.as_bytes()).unwrap();
+		assert_eq!(manifest.overrides, "overrides");
+		assert!(manifest.files.is_empty());
+		// blank or null falls back to the default too, and stray slashes are dropped
+		for (overrides, expected) in [("null", "overrides"), (r#""""#, "overrides"), (r#"" /extra/ ""#, "extra")] {
+			let json = MINIMAL_MANIFEST.replace(r#""files""#, &format!(r#""overrides": {}, "files""#, overrides));
+			assert_eq!(try_read_manifest(&mut json.as_bytes()).unwrap().overrides, expected, "{}", overrides);
+		}
+	}
+
+	#[test]
+	fn pack_with_only_a_manifest() {
+		let mut pack = zip_of(&[(MANIFEST_NAME, MINIMAL_MANIFEST)]);
+		let manifest = try_read_pack_manifest(&mut pack).unwrap();
+		assert!(install::override_entries(&pack, &manifest, false).is_empty());
+	}
+
+	#[test]
+	fn bogus_manifest_type() {
+		let json = MINIMAL_MANIFEST.replace("minecraftModpack", "somethingElse");
+		assert!(matches!(try_read_manifest(&mut json.as_bytes()), Err(AppError::Format(manifest::FormatError::NotCurseForge(_)))));
+	}
+
+	#[test]
+	fn manifest_with_bom() {
+		let json = format!("\u{feff}{}", MINIMAL_MANIFEST);
+		assert!(try_read_manifest(&mut json.as_bytes()).is_ok());
+		// and through a pack, where the bytes come from the zip entry
+		let mut pack = zip_of(&[(MANIFEST_NAME, &json)]);
+		assert_eq!(try_read_pack_manifest(&mut pack).unwrap().overrides, "overrides");
+		// only the one at the very start is skipped
+		let json = format!("\u{feff}\u{feff}{}", MINIMAL_MANIFEST);
+		assert!(matches!(try_read_manifest(&mut json.as_bytes()), Err(AppError::Manifest(_))));
+	}
+}
@@ -0,0 +1,161 @@
+use std::{path::{Path, PathBuf}, process::Command};
+
+use serde::Deserialize;
+
+use crate::{atomic, error::{AppError, ErrorContext}, fetch, fetch_to_file, manifest::{LoaderId, LoaderKind, Manifest}, output, proxy::ProxyConfig};
+
+const FORGE_MAVEN: &str = "https://maven.minecraftforge.net/net/minecraftforge/forge";
+const NEOFORGE_MAVEN: &str = "https://maven.neoforged.net/releases/net/neoforged/neoforge";
+const FABRIC_META: &str = "https://meta.fabricmc.net/v2/versions";
+const QUILT_META: &str = "https://meta.quiltmc.org/v3/versions";
+
+#[derive(Deserialize)]
+struct FabricInstaller {
+	url: String,
+	version: String,
+	stable: bool
+}
+
+#[derive(Deserialize)]
+struct QuiltInstaller {
+	url: String,
+	version: String
+}
+
+pub fn primary_loader_id(manifest: &Manifest) -> Result<LoaderId, AppError> {
+	manifest.minecraft.primary_loader()?.parse().map_err(AppError::Loader)
+}
+
+pub fn install_loader(dir: &Path, manifest: &Manifest, server: bool, proxy: &ProxyConfig) -> Result<(), AppError> {
+	let mc = &manifest.minecraft.version;
+	let loader = primary_loader_id(manifest)?;
+	let (kind, version) = (loader.kind.name(), loader.version.as_str());
+	let dir_arg = dir.to_string_lossy().to_string();
+	let (installer, args) = match loader.kind {
+		LoaderKind::Forge | LoaderKind::NeoForge => {
+			let installer = if loader.kind == LoaderKind::Forge {
+				download_forge_installer(dir, mc, version, proxy)?
+			} else {
+				download_neoforge_installer(dir, version, proxy)?
+			};
+			// both use the same installer
+			if server {
+				(installer, vec!["--installServer".to_string(), dir_arg])
+			} else {
+				// the client installer refuses to run without a launcher profile file
+				let mut profiles = dir.to_path_buf();
+				profiles.push("launcher_profiles.json");
+				if !profiles.exists() {
+					atomic::write(&profiles, "{\"profiles\":{}}")?;
+				}
+				(installer, vec!["--installClient".to_string(), dir_arg])
+			}
+		},
+		LoaderKind::Fabric => {
+			let installer_info = stable_fabric_installer(proxy)?;
+			let installer = download_to(&installer_info.url, dir, &format!("fabric-installer-{}.jar", installer_info.version), proxy)?;
+			let mut args = vec![
+				if server { "server" } else { "client" }.to_string(),
+				"-dir".to_string(), dir_arg,
+				"-mcversion".to_string(), mc.to_string(),
+				"-loader".to_string(), version.to_string()
+			];
+			if server {
+				args.push("-downloadMinecraft".to_string());
+			} else {
+				args.push("-noprofile".to_string());
+			}
+			(installer, args)
+		},
+		LoaderKind::Quilt => {
+			let installer = download_quilt_installer(dir, proxy)?;
+			let mut args = vec![
+				"install".to_string(),
+				if server { "server" } else { "client" }.to_string(),
+				mc.to_string(),
+				version.to_string(),
+				format!("--install-dir={}", dir_arg)
+			];
+			args.push(if server { "--download-server" } else { "--no-profile" }.to_string());
+			(installer, args)
+		}
+	};
+
+	output::info(format!("Running {} installer for {}-{}", kind, kind, version));
+	let status = Command::new("java")
+		.arg("-jar")
+		.arg(&installer)
+		.args(&args)
+		.current_dir(dir)
+		.status()
+		.context("Error running java (is it installed and on your PATH?)")?;
+	if status.success() {
+		Ok(())
+	} else {
+		Err(AppError::Other(format!("{} installer exited with {}", kind, status)))
+	}
+}
+
+pub fn forge_installer_name(mc_version: &str, forge_version: &str) -> String {
+	format!("forge-{}-{}-installer.jar", mc_version, forge_version)
+}
+
+pub fn download_forge_installer(dir: &Path, mc_version: &str, forge_version: &str, proxy: &ProxyConfig) -> Result<PathBuf, AppError> {
+	let full_version = format!("{}-{}", mc_version, forge_version);
+	let name = forge_installer_name(mc_version, forge_version);
+	let url = format!("{}/{}/{}", FORGE_MAVEN, full_version, name);
+	download_to(&url, dir, &name, proxy)
+}
+
+pub fn neoforge_installer_name(neoforge_version: &str) -> String {
+	format!("neoforge-{}-installer.jar", neoforge_version)
+}
+
+// neoforge versions already encode the minecraft version (20.4.x is 1.20.4)
+pub fn download_neoforge_installer(dir: &Path, neoforge_version: &str, proxy: &ProxyConfig) -> Result<PathBuf, AppError> {
+	let name = neoforge_installer_name(neoforge_version);
+	let url = format!("{}/{}/{}", NEOFORGE_MAVEN, neoforge_version, name);
+	download_to(&url, dir, &name, proxy)
+}
+
+// the fabric server launcher sets everything else up on its first run
+pub fn download_fabric_server_launcher(dir: &Path, mc_version: &str, loader_version: &str, proxy: &ProxyConfig) -> Result<PathBuf, AppError> {
+	let installer = stable_fabric_installer(proxy)?;
+	let url = format!("{}/loader/{}/{}/{}/server/jar", FABRIC_META, mc_version, loader_version, installer.version);
+	download_to(&url, dir, "fabric-server-launch.jar", proxy)
+}
+
+fn stable_fabric_installer(proxy: &ProxyConfig) -> Result<FabricInstaller, AppError> {
+	let installers_url = format!("{}/installer", FABRIC_META);
+	let response = fetch(&installers_url, proxy)?;
+	let installers: Vec<FabricInstaller> = serde_json::from_slice(response.as_bytes())
+		.map_err(|e| AppError::Other(format!("Error parsing Fabric installer list: {}", e)))?;
+	installers.into_iter()
+		.find(|i| i.stable)
+		.ok_or_else(|| AppError::Other("No stable Fabric installer found".into()))
+}
+
+// quilt's meta lists installers newest first and doesn't flag stable ones
+pub fn download_quilt_installer(dir: &Path, proxy: &ProxyConfig) -> Result<PathBuf, AppError> {
+	let installers_url = format!("{}/installer", QUILT_META);
+	let response = fetch(&installers_url, proxy)?;
+	let installers: Vec<QuiltInstaller> = serde_json::from_slice(response.as_bytes())
+		.map_err(|e| AppError::Other(format!("Error parsing Quilt installer list: {}", e)))?;
+	let installer = installers.into_iter()
+		.next()
+		.ok_or_else(|| AppError::Other("No Quilt installer found".into()))?;
+	download_to(&installer.url, dir, &quilt_installer_name(&installer.version), proxy)
+}
+
+pub fn quilt_installer_name(installer_version: &str) -> String {
+	format!("quilt-installer-{}.jar", installer_version)
+}
+
+fn download_to(url: &str, dir: &Path, name: &str, proxy: &ProxyConfig) -> Result<PathBuf, AppError> {
+	let mut path = dir.to_path_buf();
+	path.push(name);
+	if !path.exists() {
+		fetch_to_file(url, proxy, &path)?;
+	}
+	Ok(path)
+}
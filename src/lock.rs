@@ -0,0 +1,70 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::{Curseforge, HashAlgo}, atomic, error::{AppError, ErrorContext}, manifest::FileInfo, try_load_file};
+
+#[derive(Serialize, Deserialize)]
+pub struct Lockfile {
+	pub files: Vec<LockEntry>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct LockEntry {
+	#[serde(rename = "projectId")]
+	pub project_id: i32,
+	#[serde(rename = "fileId")]
+	pub file_id: i32,
+	#[serde(rename = "fileName")]
+	pub file_name: String,
+	pub sha1: Option<String>,
+	#[serde(rename = "fileLength")]
+	pub file_length: i64
+}
+
+impl Lockfile {
+	pub fn resolve(cf: &Curseforge, files: &[FileInfo]) -> Result<Self, AppError> {
+		let file_ids: Vec<i32> = files.iter().map(|f| f.file_id).collect();
+		let mut resolved: HashMap<i32, crate::api::File> = cf.get_files(&file_ids)
+			.context("Error getting file metadata")?
+			.into_iter()
+			.map(|f| (f.id, f))
+			.collect();
+		let mut entries = Vec::new();
+		for file in files {
+			let meta = resolved.remove(&file.file_id)
+				.ok_or_else(|| AppError::Other(format!("CF returned no metadata for file {} (project {})", file.file_id, file.project_id)))?;
+			entries.push(LockEntry {
+				project_id: file.project_id,
+				file_id: file.file_id,
+				sha1: meta.hashes.iter().find(|h| h.algo == HashAlgo::Sha1).map(|h| h.value.to_lowercase()),
+				file_name: meta.file_name,
+				file_length: meta.file_length
+			});
+		}
+		Ok(Self { files: entries })
+	}
+
+	pub fn read(path: &Path) -> Result<Self, AppError> {
+		let data = try_load_file(path)?;
+		serde_json::from_str(&data).map_err(|e| AppError::Other(format!("Error parsing lockfile {:?}: {}", path, e)))
+	}
+
+	pub fn write(&self, path: &Path) -> Result<(), AppError> {
+		let json = serde_json::to_string_pretty(self)
+			.map_err(|e| AppError::Other(format!("Error serializing lockfile: {}", e)))?;
+		atomic::write(path, json)
+	}
+
+	pub fn file_infos(&self) -> Vec<FileInfo> {
+		self.files.iter()
+			.map(|e| FileInfo { project_id: e.project_id, file_id: e.file_id, required: true })
+			.collect()
+	}
+
+	pub fn hashes(&self) -> HashMap<i32, String> {
+		self.files.iter()
+			.filter_map(|e| e.sha1.clone().map(|h| (e.file_id, h)))
+			.collect()
+	}
+}
@@ -1,120 +1,230 @@
-mod threadpool;
-mod manifest;
-mod api;
+use std::{env, io::{self, IsTerminal}, path::{Path, PathBuf}, process, sync::Arc, time::Duration};
 
-use std::{fs::{self, File}, io::{Cursor, Read, Seek, Write}, path::{Path, PathBuf}};
-
-use api::Curseforge;
-use manifest::*;
-use regex::Regex;
-use threadpool::{BranchedExecutor, ThreadPool};
-use clap::{Parser, Subcommand};
-use zip::{ZipArchive, read::ZipFile};
-
-use crate::api::ErrorStringify;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use underlion::{api::{self, Curseforge, FileReleaseType, FileStatus}, atomic, cfinstance, changelog, cache::{self, MetadataCache}, diff, doctor, edit::{self, FileChoice, OptionalDeps}, grabkey, error::{AppError, ErrorContext, TryExpect, EXIT_INTERRUPTED}, events::{ConsoleObserver, NdjsonObserver}, export, fetch_to_file, filter::{self, FilterMode}, glob, install::{self, DownloadSettings, InstallOptions, Parallelism}, instance::{self, InstanceFormat}, merge, offline, output, plan::{ConfirmPlan, InstallPlan}, proxy::ProxyConfig, ratelimit, record::{self, InstallRecord}, search::SearchQuery, summary::InstallSummary, try_load_file, try_load_manifest, try_open_pack, try_open_zip, try_read_pack_manifest, update::{self, UpdateOptions}, verify, Cancel, TempFile};
 
 const DEFAULT_KEY_PATH: &str = ".cfkey";
-const MANIFEST_NAME: &str = "manifest.json";
-const KEY_GRAB_LOCATION: &str = "dist/desktop/desktop.js";
+// checked in this order, after --key and --key-file but before DEFAULT_KEY_PATH
+const KEY_ENV_VARS: [&str; 2] = ["CF_API_KEY", "UNDERLION_KEY"];
 
 #[derive(Parser, Debug)]
-#[clap()]
+#[clap(after_help = "EXIT CODES:
+    0    Success
+    1    Generic error
+    2    Authentication error (missing, invalid or forbidden API key)
+    3    Network error
+    4    Partial install (some mods or overrides failed)
+    130  Interrupted (Ctrl-C)")]
 struct Args {
 	#[clap(subcommand)]
-	action: Action
+	action: Action,
+
+	/// Send all requests through this proxy. (Overrides HTTP_PROXY/HTTPS_PROXY.)
+	#[clap(long, global = true)]
+	proxy: Option<String>,
+
+	/// Write a timestamped log of everything (including API requests) to this file.
+	#[clap(long, global = true)]
+	log_file: Option<PathBuf>,
+
+	/// Always fetch mod and file metadata from the API instead of the on-disk cache.
+	#[clap(long, global = true)]
+	no_cache: bool,
+
+	/// Keep the metadata cache in this folder instead of the user cache directory.
+	#[clap(long, global = true, env = "UNDERLION_CACHE_DIR")]
+	cache_dir: Option<PathBuf>,
+
+	/// Make at most this many CF API requests per second, to stay clear of CurseForge's rate limits.
+	#[clap(long, global = true, parse(try_from_str = parse_nonzero))]
+	api_rate: Option<u32>,
+
+	/// Send CF API requests to this base URL instead of https://api.curseforge.com/v1/.
+	#[clap(long, global = true, hide = true, env = "UNDERLION_API_BASE")]
+	api_base: Option<String>
 }
 
 fn main() {
 	let args = Args::parse();
-	if let Err(s) = run_command(args) {
-		println!("{}", s);
+	let result = run_command(args);
+	if let Err(e) = &result {
+		output::error(e);
+	}
+	log::logger().flush();
+	if let Err(e) = result {
+		process::exit(e.exit_code());
 	}
 }
 
-fn run_command(args: Args) -> Result<(), String> {
+// the global options every CF client is built from
+struct ClientArgs {
+	proxy: ProxyConfig,
+	cache: Option<MetadataCache>,
+	api_rate: Option<u64>,
+	api_base: String
+}
+
+fn client(args: &ClientArgs, key: String) -> Curseforge {
+	Curseforge::new(key)
+		.with_proxy(args.proxy.clone())
+		.with_cache(args.cache.clone())
+		.with_rate_limit(args.api_rate)
+		.with_base_url(&args.api_base)
+}
+
+fn run_command(args: Args) -> Result<(), AppError> {
+	if let Some(log_file) = &args.log_file {
+		output::init_log_file(log_file).context(&format!("Error opening log file {:?}", log_file))?;
+	}
+	let cache_dir = args.cache_dir.or_else(MetadataCache::default_dir);
+	let client_args = ClientArgs {
+		proxy: ProxyConfig::new(args.proxy),
+		cache: if args.no_cache {
+			None
+		} else {
+			cache_dir.clone().map(|dir| MetadataCache::new(dir, cache::DEFAULT_TTL))
+		},
+		api_rate: args.api_rate.map(u64::from),
+		api_base: args.api_base.unwrap_or_else(|| api::CF_BASE_URL.into())
+	};
 	match args.action {
 		Action::Install {
 			pack_zip,
 			install_to,
 			key_file,
 			key,
-			parallel
+			parallel,
+			max_connections,
+			max_rate,
+			fail_fast,
+			timeout,
+			mirror,
+			instance_format,
+			no_overrides,
+			overrides_only,
+			override_include,
+			override_exclude,
+			game_dir,
+			server,
+			scripts,
+			install_loader,
+			write_lock,
+			write_sums,
+			locked,
+			project,
+			file,
+			skip_space_check,
+			allow_status,
+			include,
+			exclude,
+			offline,
+			from,
+			strict,
+			events,
+			yes
 		} => {
-			let pack_name = pack_zip.file_stem().try_expect("No pack filename given?")?;
-			let install_to_path = path_or(&install_to, Path::new(pack_name)).to_path_buf();
-			let mut mods_folder = install_to_path.clone();
-			mods_folder.push("mods");
-
-			let mut pack = try_open_zip(&pack_zip)?;
-			
-			let manifest = {
-				// borrowck throws a fit about manifest_file if I don't limit its scope
-				let mut manifest_file = try_read_zip_entry(&mut pack, MANIFEST_NAME)?;
-				try_read_manifest(&mut manifest_file)?
-			};
-
-			try_mkdir(&install_to_path)?;
-			try_mkdir(&mods_folder)?;
-
+			// no key and no client, the pack zip has to be local too
+			if offline {
+				let pack_zip = pack_zip.try_expect("No pack given")?;
+				let from = from.try_expect("--offline needs --from")?;
+				let install_to = resolve_game_dir(game_dir)?.or(install_to);
+				let install_to_path = path_or(&install_to, Path::new(pack_zip.file_stem().try_expect("No pack filename given?")?)).to_path_buf();
+				let cancel = Cancel::new();
+				let interrupted = cancel_on_ctrl_c(&cancel);
+				let opts = InstallOptions {
+					fail_fast, instance_format, locked, skip_space_check, strict, cancel, no_overrides, overrides_only,
+					override_include: glob::parse_all(&override_include)?,
+					override_exclude: glob::parse_all(&override_exclude)?,
+					..InstallOptions::default()
+				};
+				let cache = client_args.cache.clone().map(|c| c.with_ttl(Duration::MAX));
+				let summary = offline::install_offline(&pack_zip, &install_to_path, &from, &opts, cache.as_ref())?;
+				summary.print();
+				write_sums_file(&summary, &write_sums)?;
+				if interrupted.is_cancelled() {
+					return Err(AppError::Interrupted);
+				}
+				if summary.failed_count() > 0 {
+					return Err(AppError::PartialInstall(summary.failed_count()));
+				}
+				return Ok(());
+			}
+			if events {
+				output::set_quiet(true);
+			}
+			// stdin is spent once the key has been read from it, so there's nothing to answer the prompt with
+			let yes = yes || key.as_deref() == Some("-");
 			let key = get_key(key, &key_file)?.trim().to_string();
+			let cf = client(&client_args, key);
 
-			let threads = parallel.unwrap_or(1);
-			let mv_mods = mods_folder.clone();
-			let exec = if threads > 1 {
-				let pool: ThreadPool<FileInfo> = ThreadPool::new::<_, Curseforge, _>(threads, 
-					move |file, cf| {
-						if let Err(e) = download(&file, cf, &mv_mods) {
-							println!("{}", e);
-						}
-					},
-					move || {
-						Curseforge::new(key.clone())
-					}
-				);
-				BranchedExecutor::Pooled(pool)
+			// with --project/--file the only positional is the install dir
+			let (pack_zip, install_to) = if project.is_some() {
+				(None, pack_zip.or(install_to))
 			} else {
-				let cf = Curseforge::new(key);
-				BranchedExecutor::ThisThread(Box::new(move |file| {
-					if let Err(e) = download(&file, &cf, &mv_mods) {
-						println!("{}", e);
-					}
-				}))
+				(pack_zip, install_to)
 			};
-			if let Ok(wait) = exec.exec(manifest.files) {
-				wait.wait();
-			} else {
-				println!("Threadpool error -- could not download files.")
-			}
-			
-			// extract overrides
-			let mut fnames = Vec::new();
-			for fname in pack.file_names() {
-				if let Some(s) = fname.split('/').next() {
-					if s == manifest.overrides {
-						fnames.push(fname.to_string());
-					}
-				}
+			// the temp file has to outlive the zip reading it, it deletes itself when dropped
+			let temp = TempFile::new("pack.zip");
+			let (pack_path, pack_file_name) = match (project, file, pack_zip) {
+				(Some(project_id), Some(file_id), _) => {
+					let name = download_pack_file(&cf, project_id, file_id, temp.path())?;
+					(temp.path().to_path_buf(), name)
+				},
+				(_, _, Some(path)) => match path.to_str().filter(|s| is_url(s)) {
+					Some(url) => {
+						fetch_to_file(url, cf.proxy(), temp.path())?;
+						(temp.path().to_path_buf(), pack_file_name(&path))
+					},
+					None => (path.clone(), path)
+				},
+				_ => return Err(AppError::Other("No pack given".into()))
+			};
+			let pack_name = pack_file_name.file_stem().try_expect("No pack filename given?")?;
+			let install_to = resolve_game_dir(game_dir)?.or(install_to);
+			let install_to_path = path_or(&install_to, Path::new(pack_name)).to_path_buf();
+
+			let filter = match (include, exclude) {
+				(Some(list), _) => Some((FilterMode::Include, filter::read_list(&list)?)),
+				(_, Some(list)) => Some((FilterMode::Exclude, filter::read_list(&list)?)),
+				_ => None
+			};
+			let opts = InstallOptions {
+				download: DownloadSettings { parallel, max_connections, max_rate, mirrors: mirror, timeout: timeout.map(Duration::from_secs) },
+				fail_fast,
+				instance_format,
+				server,
+				scripts,
+				install_loader,
+				write_lock,
+				locked,
+				filter,
+				skip_space_check,
+				allow_status,
+				strict,
+				observer: Some(if events { Arc::new(NdjsonObserver::new()) } else { Arc::new(ConsoleObserver) }),
+				cancel: Cancel::new(),
+				// the plan is printed either way, it's just not waited on in scripts
+				confirm: (!events).then(|| -> ConfirmPlan {
+					Arc::new(move |plan: &InstallPlan| {
+						plan.print();
+						yes || !io::stdout().is_terminal() || output::confirm("Continue?")
+					})
+				}),
+				override_include: glob::parse_all(&override_include)?,
+				override_exclude: glob::parse_all(&override_exclude)?,
+				no_overrides,
+				overrides_only
+			};
+			let interrupted = cancel_on_ctrl_c(&opts.cancel);
+			let summary = install::install_pack(&cf, &pack_path, &install_to_path, &opts)?;
+			summary.print();
+			write_sums_file(&summary, &write_sums)?;
+			if interrupted.is_cancelled() {
+				return Err(AppError::Interrupted);
 			}
-			
-			for fname in fnames {
-				let mut entry = try_read_zip_entry(&mut pack, &fname)?;
-				if entry.is_file() {
-					let name = entry
-						.enclosed_name()
-						.try_expect("Could not properly format name for writing to filesystem")?
-						.strip_prefix(&format!("{}/", manifest.overrides))
-						.stringify_error("Error converting path")?
-						.to_owned();
-					let mut ext_path = install_to_path.clone();
-					ext_path.push(name);
-					if let Some(p) = ext_path.parent() {
-						try_mkdir(&p)?;
-					}
-					let mut data = Vec::new();
-					entry.read_to_end(&mut data).stringify_error("Could not read zip data")?;
-					let mut file = try_open_write(&ext_path)?;
-					file.write_all(&data).stringify_error("Error writing override data to disk")?;
-				}
+			if summary.failed_count() > 0 {
+				return Err(AppError::PartialInstall(summary.failed_count()));
 			}
 		},
 		Action::FindBad {
@@ -122,96 +232,426 @@ fn run_command(args: Args) -> Result<(), String> {
 			key_file,
 			key
 		} => {
-			let mut pack = try_open_zip(&pack_zip)?;
-			let mut manifest_file = try_read_zip_entry(&mut pack, MANIFEST_NAME)?;
-			let manifest = try_read_manifest(&mut manifest_file)?;
+			let manifest = try_read_pack_manifest(&mut try_open_zip(&pack_zip)?)?;
 			let key = get_key(key, &key_file)?.trim().to_string();
 
 			let mut mod_ids = Vec::new();
-			let cf = Curseforge::new(key);
+			let cf = client(&client_args, key);
 			for file in manifest.files {
 				mod_ids.push(file.project_id);
 			}
-			let mods = cf.get_mods(&mod_ids).stringify_error("Error getting mod metadata")?;
+			let mods = cf.get_mods(&mod_ids).context("Error getting mod metadata")?;
 			for m in mods {
 				if let Some(false) = m.allow_mod_distribution {
-					println!("Non-downloadable mod: {} ({})", m.name, m.slug);
+					output::warn(format!("Non-downloadable mod: {} ({})", m.name, m.slug));
 				}
 			}
 		},
-		Action::GrabKey {
-			cf_version,
-			cf_url
+		Action::Merge {
+			packs,
+			output,
+			on_conflict,
+			pin
+		} => {
+			merge::merge(&packs, &output, on_conflict, &pin)?;
+		},
+		Action::Diff {
+			old_pack,
+			new_pack,
+			json,
+			names,
+			key_file,
+			key
+		} => {
+			let mut diff = diff::diff_packs(&old_pack, &new_pack)?;
+			if names {
+				let key = get_key(key, &key_file)?.trim().to_string();
+				diff.resolve_names(&client(&client_args, key))?;
+			}
+			if json {
+				let json = serde_json::to_string_pretty(&diff)
+					.map_err(|e| AppError::Other(format!("Error serializing diff: {}", e)))?;
+				println!("{}", json);
+			} else {
+				diff.print();
+			}
+		},
+		Action::Changelog {
+			old_pack,
+			new_pack,
+			json,
+			markdown,
+			key_file,
+			key
+		} => {
+			let key = get_key(key, &key_file)?.trim().to_string();
+			let cf = client(&client_args, key);
+			let changelogs = changelog::changelog(&cf, &old_pack, &new_pack)?;
+			if json {
+				let json = serde_json::to_string_pretty(&changelogs)
+					.map_err(|e| AppError::Other(format!("Error serializing changelog: {}", e)))?;
+				println!("{}", json);
+			} else if markdown {
+				print!("{}", changelog::to_markdown(&changelogs));
+			} else {
+				changelog::print(&changelogs);
+			}
+		},
+		Action::Search {
+			query,
+			modpacks,
+			game_version,
+			limit,
+			key_file,
+			key
+		} => {
+			let key = get_key(key, &key_file)?.trim().to_string();
+			let cf = client(&client_args, key);
+			let query = SearchQuery {
+				text: Some(query),
+				class_id: Some(if modpacks { api::CLASS_MODPACKS } else { api::CLASS_MODS }),
+				game_version,
+				..SearchQuery::default()
+			};
+			let mut results = cf.search_all(query, Some(limit));
+			let mut shown = 0;
+			for m in results.by_ref() {
+				let m = m.map_err(|e| AppError::Api("Error searching CF".into(), e))?;
+				output::success(format!("{} ({}, project {}), {} downloads", m.name, m.slug, m.id, m.download_count as u64));
+				println!("    {}", m.summary);
+				shown += 1;
+			}
+			match results.pagination() {
+				Some(p) if p.total_count > shown as u64 => output::info(format!("Showing {} of {} results, raise --limit to see more", shown, p.total_count)),
+				_ => output::info(format!("{} results", shown))
+			}
+		},
+		Action::Add {
+			pack,
+			project,
+			file_id,
+			game_version,
+			output,
+			with_optional,
+			no_optional,
+			key_file,
+			key
+		} => {
+			// stdin is spent once the key has been read from it, so there's nothing to answer the prompts with
+			let optional = if with_optional {
+				OptionalDeps::All
+			} else if no_optional || key.as_deref() == Some("-") || !io::stdin().is_terminal() {
+				OptionalDeps::None
+			} else {
+				OptionalDeps::Ask
+			};
+			let key = get_key(key, &key_file)?.trim().to_string();
+			let cf = client(&client_args, key);
+			// the newest file whatever its release type, the user picked the mod so they can pick a file too
+			let choice = FileChoice { game_version, release_type: FileReleaseType::Alpha, allow_fallback: false };
+			edit::add(&cf, &pack, &project, file_id, &choice, output.as_deref(), optional)?;
+		},
+		Action::Remove {
+			pack,
+			project,
+			output,
+			key_file,
+			key
 		} => {
-			let url = if let Some(url) = cf_url {
-				url
+			let cf = || Ok(client(&client_args, get_key(key, &key_file)?.trim().to_string()));
+			edit::remove(cf, &pack, &project, output.as_deref())?;
+		},
+		Action::Verify {
+			pack_zip,
+			install_dir,
+			json,
+			list_extras,
+			prune,
+			yes,
+			parallel,
+			key_file,
+			key
+		} => {
+			let manifest = try_load_manifest(&pack_zip)?;
+			let key = get_key(key, &key_file)?.trim().to_string();
+			let files = verify::resolve(&client(&client_args, key), &manifest, parallel)?;
+			let mut report = verify::check(&files, &install_dir, parallel);
+			if list_extras || prune {
+				report.extras = verify::find_extras(&install_dir, &files)?;
+				if !files.unresolved.is_empty() {
+					output::warn("Some of the pack's files couldn't be looked up, their jars may be listed as extras");
+				}
+			}
+			if json {
+				let json = serde_json::to_string_pretty(&report)
+					.map_err(|e| AppError::Other(format!("Error serializing report: {}", e)))?;
+				println!("{}", json);
 			} else {
-				let ver = if let Some(k) = cf_version.as_ref() {
-					k
+				report.print();
+			}
+			if prune && !report.extras.is_empty() {
+				if !files.unresolved.is_empty() {
+					return Err(AppError::Other(format!("Not pruning: {} of the pack's files couldn't be looked up on CF, so their jars can't be told apart from extras",
+						files.unresolved.len())));
+				}
+				if yes || output::confirm(format!("Delete {} file(s) that aren't part of the pack?", report.extras.len())) {
+					verify::prune(&install_dir, &report.extras)?;
 				} else {
-					"0.196.1.11"
-				};
-				format!("https://appsdl-overwolf-com.akamaized.net/prod/apps/cchhcaiapeikjbdbpfplgmpobbcdkdaphclbmkbj/{ver}/app.opk")
+					output::info("Not deleting anything");
+				}
+			}
+			if !report.is_clean() {
+				return Err(AppError::Other(format!("Verification failed: {}", report.tally())));
+			}
+		},
+		Action::Repair {
+			pack_zip,
+			install_dir,
+			parallel,
+			max_connections,
+			max_rate,
+			mirror,
+			key_file,
+			key
+		} => {
+			let manifest = try_load_manifest(&pack_zip)?;
+			let key = get_key(key, &key_file)?.trim().to_string();
+			let cf = client(&client_args, key);
+			let settings = DownloadSettings { parallel, max_connections, max_rate, mirrors: mirror, timeout: None };
+			if let Some(summary) = install::repair(&cf, &manifest, &install_dir, &settings)? {
+				summary.print();
+				if summary.failed_count() > 0 {
+					return Err(AppError::PartialInstall(summary.failed_count()));
+				}
+			}
+		},
+		Action::Update {
+			pack_zip,
+			in_place,
+			dry_run,
+			keep_backup,
+			parallel,
+			max_connections,
+			max_rate,
+			mirror,
+			latest,
+			game_version,
+			release_type,
+			allow_fallback,
+			key_file,
+			key
+		} => {
+			let game_dir = instance::detect_game_dir(&in_place);
+			let record = InstallRecord::read(&game_dir)?
+				.try_expect(&format!("No {} in {:?}, install the pack there first", record::RECORD_NAME, game_dir))?;
+			let (mut pack, mut manifest) = try_open_pack(&pack_zip)?;
+			manifest.dedup(false)?;
+			if let Some(old) = &record.pack {
+				output::info(format!("Updating {} {} to {} {}", old.name, old.version, manifest.name, manifest.version));
+			}
+			// a dry run can do without a key, it just can't say how big the downloads are then. unless it's
+			// looking for newer files, which needs the API
+			let cf = get_key(key, &key_file)
+				.map(|key| client(&client_args, key.trim().to_string()));
+			let cf = if latest {
+				let cf = cf?;
+				let choice = FileChoice { game_version, release_type, allow_fallback };
+				let changed = update::pick_latest(&cf, &mut manifest, &choice)?;
+				output::info(format!("{} of {} mods have a newer file", changed, manifest.files.len()));
+				Ok(cf)
+			} else {
+				cf
+			};
+			let mut plan = update::plan(&record, &manifest, &mut pack, &game_dir)?;
+			if let (Ok(cf), false) = (&cf, plan.download.is_empty()) {
+				plan.size_downloads(cf)?;
+			}
+			plan.print();
+			if plan.is_empty() {
+				output::success("Already up to date");
+				return Ok(());
+			}
+			if dry_run {
+				return Ok(());
+			}
+			let cf = cf?;
+			let options = UpdateOptions {
+				download: DownloadSettings { parallel, max_connections, max_rate, mirrors: mirror, timeout: None },
+				keep_backup
+			};
+			let cancel = Cancel::new();
+			let interrupted = cancel_on_ctrl_c(&cancel);
+			let summary = update::apply(&cf, plan, &manifest, &mut pack, &game_dir, &options, &cancel)?;
+			summary.print();
+			if interrupted.is_cancelled() {
+				return Err(AppError::Interrupted);
+			}
+			if summary.failed_count() > 0 {
+				return Err(AppError::PartialInstall(summary.failed_count()));
+			}
+		},
+		Action::Uninstall {
+			install_dir,
+			dry_run,
+			yes
+		} => {
+			let game_dir = instance::detect_game_dir(&install_dir);
+			let preview = record::uninstall(&game_dir, true)?;
+			output::info(format!("{} installed files in {:?}:", preview.deleted.len(), game_dir));
+			for rel in &preview.deleted {
+				output::info(format!("  {}", rel));
+			}
+			if !preview.missing.is_empty() {
+				output::info(format!("{} recorded files are already gone", preview.missing.len()));
+			}
+			for rel in &preview.refused {
+				output::warn(format!("Not deleting {}, it's outside {:?}", rel, game_dir));
+			}
+			if dry_run {
+				output::info("Dry run, nothing was deleted");
+			} else if yes || output::confirm(format!("Delete {} installed files from {:?}?", preview.deleted.len(), game_dir)) {
+				let done = record::uninstall(&game_dir, false)?;
+				output::success(format!("Deleted {} files", done.deleted.len()));
+			} else {
+				output::info("Not deleting anything");
+			}
+		},
+		Action::Export {
+			manifest,
+			install_dir,
+			output,
+			ignore,
+			key_file,
+			key
+		} => {
+			let ignore = glob::parse_all(&ignore)?;
+			let manifest = try_load_manifest(&manifest)?;
+			let key = get_key(key, &key_file)?.trim().to_string();
+			let cf = client(&client_args, key);
+			export::export(&cf, &manifest, &install_dir, &output, &ignore)?;
+		},
+		Action::ImportInstance { instance, output } => {
+			let manifest = cfinstance::CfInstance::read(&instance)?.to_manifest();
+			let json = serde_json::to_string_pretty(&manifest)
+				.map_err(|e| AppError::Other(format!("Error serializing manifest: {}", e)))?;
+			atomic::write(&output, json)?;
+			output::success(format!("Wrote {:?} ({} mods, Minecraft {})", output, manifest.files.len(), manifest.minecraft.version));
+		},
+		Action::Completions { shell } => {
+			clap_complete::generate(shell, &mut Args::command(), env!("CARGO_PKG_NAME"), &mut io::stdout());
+		},
+		Action::CleanCache {
+			older_than,
+			yes
+		} => {
+			let dir = cache_dir.try_expect("Couldn't work out where the cache directory is, pass --cache-dir")?;
+			let total = cache::stats(&dir, None).context(&format!("Error reading cache {:?}", dir))?;
+			output::info(format!("{:?} holds {}", dir, total));
+			let older_than = older_than.map(|days| Duration::from_secs(u64::from(days) * 24 * 60 * 60));
+			let stale = match older_than {
+				Some(_) => cache::stats(&dir, older_than).context(&format!("Error reading cache {:?}", dir))?,
+				None => total
+			};
+			if stale.entries == 0 {
+				output::success("Nothing to remove");
+			} else if yes || output::confirm(format!("Remove {}?", stale)) {
+				let removed = cache::clean(&dir, older_than).context(&format!("Error cleaning cache {:?}", dir))?;
+				output::success(format!("Removed {} from {:?}", removed, dir));
+			} else {
+				output::info("Not removing anything");
+			}
+		},
+		Action::Doctor {
+			install_dir,
+			key_file,
+			key
+		} => {
+			let key = get_key(key, &key_file).map(|k| k.trim().to_string());
+			// doctor checks the network, so it mustn't answer from the cache
+			let checks = doctor::run(key, |key| client(&client_args, key).with_cache(None), &client_args.proxy, &client_args.api_base, &install_dir);
+			doctor::print(&checks);
+			let failed = doctor::failures(&checks);
+			if failed > 0 {
+				return Err(AppError::Other(format!("{} check(s) failed", failed)));
+			}
+			output::success("Everything looks good");
+		},
+		Action::GrabKey {
+			cf_version,
+			cf_url,
+			key_pattern,
+			timeout
+		} => {
+			let grabber = grabkey::KeyGrabber::new(client_args.proxy.clone(), grabkey::KeyPatterns::new(key_pattern.as_deref())?, Some(u64::from(timeout)));
+			let check = |key: &str| grabkey::check_key(&client(&client_args, key.to_string()).with_cache(None));
+			let key = match cf_url {
+				Some(url) => {
+					let key = grabber.key_from_url(&url)?;
+					check(&key)?;
+					key
+				},
+				None => grabber.grab_key(cf_version.as_deref(), &check)?
 			};
-			grab_key(&url)?;
+			atomic::write(Path::new(DEFAULT_KEY_PATH), key)?;
 		}
 	}
 	Ok(())
 }
 
-fn grab_key(cf_url: &str) -> Result<(), String> {
-	let response = minreq::get(cf_url)
-		.send()
-		.stringify_error("Error making request to CF download")?;
-	let bytes = response.into_bytes();
-
-	let mut cf_zip = ZipArchive::new(Cursor::new(bytes)).stringify_error("Error reading CF download as zip")?;
-	let mut file_with_token = try_read_zip_entry(&mut cf_zip, KEY_GRAB_LOCATION)?;
-	let mut loaded_file_with_token = String::new();
-	file_with_token.read_to_string(&mut loaded_file_with_token).stringify_error("Error loading file containing token as string.")?;
-	let key = read_key_from_str(&loaded_file_with_token)?;
-
-	let mut key_file = try_open_write(Path::new(DEFAULT_KEY_PATH))?;
-	write!(key_file, "{}", key).stringify_error("Error writing to key file")?;
-
-	Ok(())
-}
-
-fn read_key_from_str(key_file: &str) -> Result<String, String> {
-	let pat = Regex::new("cfCoreApiKey\":\"(.*?)\"").unwrap();
-	let key = pat.captures(key_file).try_expect("Key not found in file -- likely changed.")?[1].to_string();
-	Ok(key)
+// returns the pack's file name, after making sure the project really is a modpack
+fn download_pack_file(cf: &Curseforge, project_id: i32, file_id: i32, path: &Path) -> Result<PathBuf, AppError> {
+	let project = cf.get_mod(project_id).context(&format!("Error getting project {}", project_id))?;
+	if project.class_id != Some(api::CLASS_MODPACKS) {
+		return Err(AppError::Other(format!("{} (project {}) isn't a modpack", project.name, project_id)));
+	}
+	let file = cf.get_mod_file(project_id, file_id).context(&format!("Error getting file {} of {}", file_id, project.name))?;
+	let url = file.download_url.clone().unwrap_or_else(|| Curseforge::cdn_url(file.id, &file.file_name));
+	fetch_to_file(&url, cf.proxy(), path)?;
+	Ok(PathBuf::from(file.file_name))
 }
 
-fn download(file: &FileInfo, cf: &Curseforge, mods_dir: &Path) -> Result<(), String> {
-	let url = cf.get_download_url(file.project_id, file.file_id).stringify_error("Error fetching download URL")?;
-	println!("Downloading {}", &url);
-	let response = match minreq::get(&url).send() {
-		Ok(r) => Ok(r),
-		Err(e) => Err(format!("Error downloading file {}: {}", url, e))
-	}?;
-	if response.status_code / 100 != 2 {
-		Err(format!("HTTP Error downloading file {}: {}", url, response.status_code))
-	} else {
-		let mut path = mods_dir.to_path_buf();
-		let filename = url.split("/").last().try_expect("Error getting filename, does URL have no slashes?")?;
-		path.push(&filename);
-		let mut file = try_open_write(&path)?;
-		if let Err(e) = file.write_all(response.as_bytes()) {
-			Err(format!("Error writing downloaded file {}: {}", filename, e))
-		} else {
-			Ok(())
+// the first Ctrl-C lets the downloads in progress finish and starts nothing new, a second one exits straight away.
+// either way, unfinished downloads are only ever .part files, which the next install resumes.
+// the returned flag is separate from `cancel` since --fail-fast cancels that too
+fn cancel_on_ctrl_c(cancel: &Cancel) -> Cancel {
+	let interrupted = Cancel::new();
+	let (cancel, flag) = (cancel.clone(), interrupted.clone());
+	let result = ctrlc::set_handler(move || {
+		if flag.is_cancelled() {
+			log::logger().flush();
+			process::exit(EXIT_INTERRUPTED);
 		}
+		flag.cancel();
+		cancel.cancel();
+		output::warn("Stopping once the downloads in progress finish, press Ctrl-C again to stop now");
+	});
+	if let Err(e) = result {
+		output::warn(format!("Couldn't set up Ctrl-C handling: {}", e));
 	}
+	interrupted
+}
+
+fn is_url(s: &str) -> bool {
+	s.starts_with("http://") || s.starts_with("https://")
 }
 
+// for URLs, the decoded last path segment; paths are returned as-is
+fn pack_file_name(path: &Path) -> PathBuf {
+	path.to_str()
+		.filter(|s| is_url(s))
+		.and_then(underlion::url_file_name)
+		.map_or_else(|| path.to_path_buf(), PathBuf::from)
+}
 
+// only ever one of these, parsed once, so the size of Install doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Action {
 	/// Installs a curseforge pack.
 	Install {
-		/// Curseforge pack zip to use.
-		pack_zip: PathBuf,
+		/// Curseforge pack zip to use. Can also be an http(s) URL to one.
+		#[clap(required_unless_present = "project")]
+		pack_zip: Option<PathBuf>,
 		/// Directory to install to
 		install_to: Option<PathBuf>,
 
@@ -219,13 +659,125 @@ enum Action {
 		#[clap(short = 'f', long)]
 		key_file: Option<PathBuf>,
 
-		/// Use a different CF API key. (Overrides key_file.)
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
 		#[clap(short, long)]
 		key: Option<String>,
 
-		/// Use parallel threads of provided count for downloads.
+		/// Use parallel threads of provided count for downloads, or `auto` to size it to the machine.
+		#[clap(short, long, default_value = "auto", parse(try_from_str = parse_parallel))]
+		parallel: Parallelism,
+
+		/// Allow at most this many downloads at once, however many threads there are.
+		#[clap(long, parse(try_from_str = parse_nonzero))]
+		max_connections: Option<u32>,
+
+		/// Cap total download speed, in bytes per second. Accepts k/m/g suffixes, e.g. 500k.
+		#[clap(long, parse(try_from_str = ratelimit::parse_rate))]
+		max_rate: Option<u64>,
+
+		/// Stop the install at the first failed download or override instead of continuing.
+		#[clap(long)]
+		fail_fast: bool,
+
+		/// Give up on downloads still running after this many seconds, and don't start any more.
+		#[clap(long, value_name = "SECS")]
+		timeout: Option<u64>,
+
+		/// Try downloading from this mirror base URL before the CF CDN. Can be given multiple times.
 		#[clap(short, long)]
-		parallel: Option<u32>
+		mirror: Vec<String>,
+
+		/// Lay the install out as a plain folder or as a Prism Launcher/MultiMC instance.
+		#[clap(long, arg_enum, default_value = "plain")]
+		instance_format: InstanceFormat,
+
+		/// Only download the mods, leaving the overrides (configs etc.) alone.
+		#[clap(long, conflicts_with = "overrides-only")]
+		no_overrides: bool,
+
+		/// Only extract the overrides, without downloading any mods.
+		#[clap(long, conflicts_with_all = &["write-lock", "install-loader", "scripts"])]
+		overrides_only: bool,
+
+		/// Only extract overrides matching this pattern, e.g. "config/**". Can be given multiple times.
+		#[clap(long)]
+		override_include: Vec<String>,
+
+		/// Don't extract overrides matching this pattern, e.g. "options.txt". Can be given multiple times.
+		#[clap(long)]
+		override_exclude: Vec<String>,
+
+		/// Install straight into a game folder instead of a new one. Without a value, the vanilla launcher's .minecraft is used.
+		#[clap(long, value_name = "DIR", conflicts_with_all = &["install-to", "instance-format"])]
+		game_dir: Option<Option<PathBuf>>,
+
+		/// Install a server: prefer server files and skip client-only projects and overrides.
+		#[clap(long)]
+		server: bool,
+
+		/// Write run.sh/run.bat launch scripts for the server, downloading the loader installer if needed.
+		#[clap(long, requires = "server")]
+		scripts: bool,
+
+		/// Download and run the pack's mod loader installer against the install directory. Requires java.
+		#[clap(long)]
+		install_loader: bool,
+
+		/// Write a lockfile pinning every resolved file and its hash to this path.
+		#[clap(long)]
+		write_lock: Option<PathBuf>,
+
+		/// After installing, write the SHA-1 of every installed file to this path, in sha1sum's format relative to the game folder.
+		#[clap(long, value_name = "PATH")]
+		write_sums: Option<PathBuf>,
+
+		/// Install exactly the files in this lockfile, verifying their hashes.
+		#[clap(long, conflicts_with_all = &["write-lock", "server"])]
+		locked: Option<PathBuf>,
+
+		/// Download and install this CF modpack project instead of a local zip. The only positional argument is then the install directory.
+		#[clap(long, requires = "file")]
+		project: Option<i32>,
+
+		/// The file of --project to install.
+		#[clap(long, requires = "project")]
+		file: Option<i32>,
+
+		/// Don't check that the install directory's volume has room for the pack before downloading.
+		#[clap(long)]
+		skip_space_check: bool,
+
+		/// Also accept files with these statuses (comma separated, e.g. deprecated,archived). Files that aren't approved or released are otherwise warned about, and malware-detected ones stop the install.
+		#[clap(long, use_value_delimiter = true)]
+		allow_status: Vec<FileStatus>,
+
+		/// Only install the mods in this file (one slug or project ID per line).
+		#[clap(long, conflicts_with = "exclude")]
+		include: Option<PathBuf>,
+
+		/// Skip the mods in this file (one slug or project ID per line).
+		#[clap(long)]
+		exclude: Option<PathBuf>,
+
+		/// Install without any network access, copying mods from --from instead of downloading them. Needs a lockfile (--locked) or metadata cached by an earlier install to know the file names.
+		#[clap(long, requires = "from", conflicts_with_all = &["server", "scripts", "install-loader", "write-lock", "project", "include", "exclude", "mirror"])]
+		offline: bool,
+
+		/// Directory of already downloaded mods for --offline, matched by file name or SHA-1. Subfolders are searched too.
+		#[clap(long, requires = "offline")]
+		from: Option<PathBuf>,
+
+		/// Refuse a manifest that lists the same project more than once, or mods CF has marked incompatible with each other, instead of warning.
+		#[clap(long)]
+		strict: bool,
+
+		/// Print progress as newline-delimited JSON events on stdout instead of the usual messages. Errors still go to stderr.
+		#[clap(long, conflicts_with = "offline")]
+		events: bool,
+
+		/// Don't ask for confirmation before downloading. Not needed when stdout isn't a terminal.
+		#[clap(short, long)]
+		yes: bool
 	},
 	/// Finds mods in a curseforge pack which have disabled downloads.
 	FindBad {
@@ -236,105 +788,446 @@ enum Action {
 		#[clap(short = 'f', long)]
 		key_file: Option<PathBuf>,
 
-		/// Use a different CF API key. (Overrides key_file.)
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
 		#[clap(short, long)]
 		key: Option<String>,
 	},
+	/// Merges several curseforge packs (or manifests) into one.
+	Merge {
+		/// Pack zips or manifest.json files to merge. Earlier packs win ties.
+		#[clap(required = true, min_values = 2)]
+		packs: Vec<PathBuf>,
+
+		/// Where to write the merged pack. A .json path writes just the manifest.
+		#[clap(short, long)]
+		output: PathBuf,
+
+		/// What to do when two packs ship the same override file.
+		#[clap(long, arg_enum, default_value = "skip")]
+		on_conflict: merge::ConflictPolicy,
+
+		/// Force a project to a specific file on conflict, as PROJECT_ID:FILE_ID. Can be given multiple times.
+		#[clap(long, parse(try_from_str = merge::parse_pin))]
+		pin: Vec<(i32, i32)>
+	},
+	/// Shows which mods and overrides changed between two versions of a curseforge pack.
+	Diff {
+		/// The older pack zip
+		old_pack: PathBuf,
+		/// The newer pack zip
+		new_pack: PathBuf,
+
+		/// Print the diff as JSON.
+		#[clap(long)]
+		json: bool,
+
+		/// Look up mod names from the CF API. (Needs an API key.)
+		#[clap(short, long)]
+		names: bool,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Shows the changelogs of every mod whose file changed between two versions of a pack.
+	Changelog {
+		/// The older pack zip or manifest.json
+		old_pack: PathBuf,
+		/// The newer pack zip or manifest.json
+		new_pack: PathBuf,
+
+		/// Print the changelogs as JSON.
+		#[clap(long)]
+		json: bool,
+
+		/// Print the changelogs as Markdown, e.g. for release notes.
+		#[clap(long, conflicts_with = "json")]
+		markdown: bool,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Searches CF for mods, or modpacks with --modpacks.
+	Search {
+		/// What to search for
+		query: String,
+
+		/// Search modpacks instead of mods.
+		#[clap(long)]
+		modpacks: bool,
+
+		/// Only show projects with files for this Minecraft version.
+		#[clap(long)]
+		game_version: Option<String>,
+
+		/// Show at most this many results. Pages are only fetched as far as needed.
+		#[clap(long, default_value = "20")]
+		limit: usize,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Adds a mod to a pack's manifest, picking its newest file for the pack's Minecraft version and loader.
+	Add {
+		/// The pack zip or manifest.json to edit
+		pack: PathBuf,
+		/// The mod's slug or project ID
+		project: String,
+
+		/// Add this specific file instead of the newest one.
+		#[clap(long)]
+		file_id: Option<i32>,
+
+		/// Pick the newest file for this Minecraft version instead of the pack's.
+		#[clap(long, conflicts_with = "file-id")]
+		game_version: Option<String>,
+
+		/// Write the result here instead of editing the pack in place.
+		#[clap(short, long)]
+		output: Option<PathBuf>,
+
+		/// Also add the file's optional dependencies without asking.
+		#[clap(long, conflicts_with = "no-optional")]
+		with_optional: bool,
+
+		/// Don't add any optional dependencies, and don't ask about them.
+		#[clap(long)]
+		no_optional: bool,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Removes a mod from a pack's manifest.
+	Remove {
+		/// The pack zip or manifest.json to edit
+		pack: PathBuf,
+		/// The mod's slug or project ID
+		project: String,
+
+		/// Write the result here instead of editing the pack in place.
+		#[clap(short, long)]
+		output: Option<PathBuf>,
+
+		/// Use a different file as the CF API key (only needed when removing by slug)
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Checks an installed pack's mods against the hashes CF has on record.
+	Verify {
+		/// The pack zip that was installed, its manifest.json, or the CurseForge app's minecraftinstance.json
+		pack_zip: PathBuf,
+		/// The directory it was installed to
+		install_dir: PathBuf,
+
+		/// Print the report as JSON.
+		#[clap(long)]
+		json: bool,
+
+		/// Also list files in mods/ that aren't part of the pack.
+		#[clap(long)]
+		list_extras: bool,
+
+		/// Delete files in mods/ that aren't part of the pack, after confirmation.
+		#[clap(long)]
+		prune: bool,
+
+		/// Don't ask for confirmation before pruning.
+		#[clap(short, long, requires = "prune")]
+		yes: bool,
+
+		/// Hash this many files at once, or `auto` to size it to the machine.
+		#[clap(short, long, default_value = "auto", parse(try_from_str = parse_parallel))]
+		parallel: Parallelism,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Re-downloads any of an installed pack's mods that are missing or don't match CF's hashes.
+	Repair {
+		/// The pack zip that was installed, its manifest.json, or the CurseForge app's minecraftinstance.json
+		pack_zip: PathBuf,
+		/// The directory it was installed to
+		install_dir: PathBuf,
+
+		/// Use parallel threads of provided count for downloads, or `auto` to size it to the machine.
+		#[clap(short, long, default_value = "auto", parse(try_from_str = parse_parallel))]
+		parallel: Parallelism,
+
+		/// Allow at most this many downloads at once, however many threads there are.
+		#[clap(long, parse(try_from_str = parse_nonzero))]
+		max_connections: Option<u32>,
+
+		/// Cap total download speed, in bytes per second. Accepts k/m/g suffixes, e.g. 500k.
+		#[clap(long, parse(try_from_str = ratelimit::parse_rate))]
+		max_rate: Option<u64>,
+
+		/// Try downloading from this mirror base URL before the CF CDN. Can be given multiple times.
+		#[clap(short, long)]
+		mirror: Vec<String>,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Updates an install to a new version of its pack, downloading only the mods that changed.
+	Update {
+		/// The new version of the pack zip
+		pack_zip: PathBuf,
+
+		/// The directory the old version was installed to. Needs the installed.json an install leaves there.
+		#[clap(long, value_name = "DIR")]
+		in_place: PathBuf,
+
+		/// Only show what would change.
+		#[clap(long)]
+		dry_run: bool,
+
+		/// Keep the replaced files in .backup in the install directory once the update has worked, instead of deleting them.
+		#[clap(long)]
+		keep_backup: bool,
+
+		/// Use parallel threads of provided count for downloads, or `auto` to size it to the machine.
+		#[clap(short, long, default_value = "auto", parse(try_from_str = parse_parallel))]
+		parallel: Parallelism,
+
+		/// Allow at most this many downloads at once, however many threads there are.
+		#[clap(long, parse(try_from_str = parse_nonzero))]
+		max_connections: Option<u32>,
+
+		/// Cap total download speed, in bytes per second. Accepts k/m/g suffixes, e.g. 500k.
+		#[clap(long, parse(try_from_str = ratelimit::parse_rate))]
+		max_rate: Option<u64>,
+
+		/// Try downloading from this mirror base URL before the CF CDN. Can be given multiple times.
+		#[clap(short, long)]
+		mirror: Vec<String>,
+
+		/// Move every mod to its newest file for the pack's Minecraft version and loader, instead of the files the pack pins.
+		#[clap(long)]
+		latest: bool,
+
+		/// With --latest, pick files for this Minecraft version instead of the pack's.
+		#[clap(long, requires = "latest")]
+		game_version: Option<String>,
+
+		/// With --latest, the least stable kind of file to pick: release, beta or alpha. More stable ones are always fine.
+		#[clap(long, default_value = "release", requires = "latest")]
+		release_type: FileReleaseType,
+
+		/// If a mod has no file of the --release-type, take the newest of the next most stable kind there is.
+		#[clap(long, requires = "latest")]
+		allow_fallback: bool,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Deletes the files an install put in a folder, going by the installed.json it left there. Anything added since is kept.
+	Uninstall {
+		/// The directory the pack was installed to
+		install_dir: PathBuf,
+
+		/// Only list what would be deleted.
+		#[clap(long)]
+		dry_run: bool,
+
+		/// Don't ask for confirmation before deleting.
+		#[clap(short, long)]
+		yes: bool
+	},
+	/// Packs an installed folder back up into a curseforge pack zip.
+	Export {
+		/// The pack zip, manifest.json or CurseForge app minecraftinstance.json describing the install's mods
+		manifest: PathBuf,
+		/// The installed folder to export
+		install_dir: PathBuf,
+
+		/// Where to write the pack zip.
+		#[clap(short, long)]
+		output: PathBuf,
+
+		/// Leave out files matching this pattern, e.g. "logs" or "*.log". Can be given multiple times.
+		#[clap(long)]
+		ignore: Vec<String>,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Converts a CurseForge app minecraftinstance.json into a pack manifest.
+	ImportInstance {
+		/// The instance's minecraftinstance.json
+		instance: PathBuf,
+
+		/// Where to write the manifest.
+		#[clap(short, long, default_value = "manifest.json")]
+		output: PathBuf
+	},
+	/// Reports the size of the on-disk mod and file metadata cache, and deletes it or just its older entries.
+	CleanCache {
+		/// Only remove entries last fetched more than this many days ago.
+		#[clap(long, value_name = "DAYS")]
+		older_than: Option<u32>,
+
+		/// Don't ask for confirmation before deleting.
+		#[clap(short, long)]
+		yes: bool
+	},
+	/// Checks that the key, network and install folder are all usable, and says what to do about anything that isn't.
+	Doctor {
+		/// The folder you want to install into.
+		#[clap(default_value = ".")]
+		install_dir: PathBuf,
+
+		/// Use a different file as the CF API key
+		#[clap(short = 'f', long)]
+		key_file: Option<PathBuf>,
+
+		/// Use a different CF API key, or - to read it from stdin. (Overrides key_file.)
+		#[clap(short, long)]
+		key: Option<String>
+	},
+	/// Prints a shell completion script to stdout.
+	Completions {
+		#[clap(arg_enum)]
+		shell: Shell
+	},
 	/// Grabs the internal curseforge key that allows downloading even mods with downloads disabled.
 	GrabKey {
-		/// Use a different version of the CF Overwolf extension.
+		/// Use a different version of the CF Overwolf extension, or "latest" to look for the newest one.
 		#[clap(short = 'v', long)]
 		cf_version: Option<String>,
 
 		/// Use an alternate URL to download the CF Overwolf extension. (Overrides cf_version)
 		#[clap(short = 'u', long)]
-		cf_url: Option<String>
+		cf_url: Option<String>,
+
+		/// A regex to find the key with, tried before the built in ones. The first capture group is the key.
+		#[clap(long, env = "UNDERLION_KEY_PATTERN")]
+		key_pattern: Option<String>,
+
+		/// Give up on downloading the extension after this many seconds. Failed downloads are retried twice.
+		#[clap(long, default_value = "300", parse(try_from_str = parse_nonzero))]
+		timeout: u32
 	},
 }
 
-fn path_or<'a>(p1: &'a Option<PathBuf>, default: &'a Path) -> &'a Path {
-	match p1.as_ref() {
-		Some(p) => p,
-		None => default
+// whatever did get installed is recorded, even if some of it failed
+fn write_sums_file(summary: &InstallSummary, path: &Option<PathBuf>) -> Result<(), AppError> {
+	if let Some(path) = path {
+		summary.write_sums(path)?;
+		output::success(format!("Wrote the SHA-1s of {} files to {:?}", summary.installed().count(), path));
 	}
+	Ok(())
 }
 
-fn get_key(input_key: Option<String>, input_file: &Option<PathBuf>) -> Result<String, String> {
-	match (input_key, input_file) {
-		(Some(k), _) => Ok(k),
-		(None, Some(f)) => {
-			try_load_file(f)
-		},
-		_ => {
-			if let Ok(s) = try_load_file(Path::new(DEFAULT_KEY_PATH)) {
-				Ok(s)
-			} else {
-				Err("No key or key file provided, and default key file does not exist!".into())
+// --game-dir on its own means the launcher's default folder, which has to exist already:
+// a missing one means the launcher isn't installed or keeps it somewhere else
+fn resolve_game_dir(game_dir: Option<Option<PathBuf>>) -> Result<Option<PathBuf>, AppError> {
+	match game_dir {
+		Some(Some(dir)) => Ok(Some(dir)),
+		Some(None) => {
+			let dir = instance::default_minecraft_dir().try_expect("Couldn't work out where .minecraft is on this system, pass --game-dir DIR")?;
+			if !dir.is_dir() {
+				return Err(AppError::Other(format!("{:?} doesn't exist, pass the game folder with --game-dir DIR", dir)));
 			}
-		}
+			output::info(format!("Installing into {:?}", dir));
+			Ok(Some(dir))
+		},
+		None => Ok(None)
 	}
 }
 
-fn try_load_file(file: &Path) -> Result<String, String> {
-	match fs::read_to_string(file) {
-		Ok(f) => Ok(f),
-		Err(e) => Err(format!("Error opening file {:?}: {}", file, e))
+fn path_or<'a>(p1: &'a Option<PathBuf>, default: &'a Path) -> &'a Path {
+	match p1.as_ref() {
+		Some(p) => p,
+		None => default
 	}
 }
 
-trait TryExpect<T> {
-	fn try_expect(self, msg: &str) -> Result<T, String>;
-}
 
-impl<T> TryExpect<T> for Option<T> {
-	fn try_expect(self, msg: &str) -> Result<T, String> {
-		match self {
-			Some(t) => Ok(t),
-			None => Err(msg.into())
-		}
-	}
-}
-
-fn try_mkdir(path: &Path) -> Result<(), String> {
-	if let Err(e) = fs::create_dir_all(path) {
-		Err(format!("Error creating directory {:?}: {}", path, e))
+fn parse_parallel(s: &str) -> Result<Parallelism, String> {
+	if s == "auto" {
+		Ok(Parallelism::Auto)
 	} else {
-		Ok(())
-	}
-}
-
-fn try_open(path: &Path) -> Result<File, String> {
-	match File::open(path) {
-		Ok(f) => Ok(f),
-		Err(e) => Err(format!("Error opening file {:?}: {}", path, e))
+		parse_nonzero(s).map(Parallelism::Threads)
 	}
 }
 
-fn try_open_write(path: &Path) -> Result<File, String> {
-	match File::create(path) {
-		Ok(f) => Ok(f),
-		Err(e) => Err(format!("Error opening file {:?}: {}", path, e))
+fn parse_nonzero(s: &str) -> Result<u32, String> {
+	match s.parse::<u32>() {
+		Ok(0) => Err("must be at least 1".into()),
+		Ok(n) => Ok(n),
+		Err(e) => Err(e.to_string())
 	}
 }
 
-fn try_open_zip(path: &Path) -> Result<ZipArchive<File>, String> {
-	let file = try_open(path)?;
-	match ZipArchive::new(file) {
-		Ok(z) => Ok(z),
-		Err(e) => Err(format!("Error opening pack zip {:?}: {}", path, e))
-	}
-}
-
-fn try_read_zip_entry<'a, T: Read + Seek>(zip: &'a mut ZipArchive<T>, loc: &str) -> Result<ZipFile<'a>, String> {
-	match zip.by_name(loc) {
-		Ok(f) => Ok(f),
-		Err(e) => Err(format!("Error reading zip entry {}: {}", loc, e))
+fn get_key(input_key: Option<String>, input_file: &Option<PathBuf>) -> Result<String, AppError> {
+	match (input_key, input_file) {
+		// keeps the key out of argv, where anyone can see it in the process list
+		(Some(k), _) if k == "-" => {
+			let mut line = String::new();
+			io::stdin().read_line(&mut line).context("Error reading the key from stdin")?;
+			let key = line.trim();
+			if key.is_empty() {
+				return Err(AppError::KeyNotFound("--key - was given, but no key was read from stdin".into()));
+			}
+			Ok(key.to_string())
+		},
+		(Some(k), _) => Ok(k),
+		(None, Some(f)) => {
+			try_load_file(f)
+		},
+		_ => {
+			// an empty variable is treated as unset, CI systems like to define them blank
+			if let Some(k) = KEY_ENV_VARS.iter().filter_map(|v| env::var(v).ok()).find(|k| !k.trim().is_empty()) {
+				Ok(k)
+			} else if let Ok(s) = try_load_file(Path::new(DEFAULT_KEY_PATH)) {
+				Ok(s)
+			} else {
+				Err(AppError::KeyNotFound("No key or key file provided, CF_API_KEY isn't set, and default key file does not exist!".into()))
+			}
+		}
 	}
 }
 
-fn try_read_manifest<T: Read>(t: &mut T) -> Result<Manifest, String> {
-	match serde_json::from_reader(t) {
-		Ok(m) => Ok(m),
-		Err(e) => Err(format!("Error parsing pack manifest: {}", e))
-	}
-}
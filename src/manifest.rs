@@ -1,10 +1,15 @@
-use serde::Deserialize;
+use std::{collections::HashMap, error::Error, fmt::{self, Display}};
 
-#[derive(Deserialize)]
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{api::ModLoaderType, error::AppError, output};
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct Manifest {
 	pub minecraft: MinecraftInfo,
 	#[serde(rename = "manifestType")]
 	pub manifest_type: String,
+	#[serde(default = "default_overrides", deserialize_with = "deserialize_overrides")]
 	pub overrides: String,
 	#[serde(rename = "manifestVersion")]
 	pub manifest_version: u32,
@@ -14,20 +19,189 @@ pub struct Manifest {
 	pub files: Vec<FileInfo>
 }
 
-#[derive(Deserialize)]
+pub const MANIFEST_TYPE: &str = "minecraftModpack";
+// the only version CF has ever written
+pub const MANIFEST_VERSION: u32 = 1;
+
+const DEFAULT_OVERRIDES: &str = "overrides";
+
+fn default_overrides() -> String {
+	DEFAULT_OVERRIDES.into()
+}
+
+// the folder name gets a / appended for prefix matching, so a stray slash or an empty name would match nothing (or everything)
+fn deserialize_overrides<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+	let overrides = Option::<String>::deserialize(deserializer)?.unwrap_or_default();
+	let overrides = overrides.trim().trim_matches('/');
+	Ok(if overrides.is_empty() { default_overrides() } else { overrides.to_string() })
+}
+
+// checked on the raw json so that some other tool's manifest.json gets a clear error
+// instead of a complaint about whichever field serde happened to miss first
+pub fn check_format(value: &serde_json::Value) -> Result<(), FormatError> {
+	match value.get("manifestType") {
+		Some(serde_json::Value::String(t)) if t == MANIFEST_TYPE => (),
+		t => return Err(FormatError::NotCurseForge(t.cloned()))
+	}
+	match value.get("manifestVersion").and_then(|v| v.as_u64()) {
+		Some(v) if v == u64::from(MANIFEST_VERSION) => Ok(()),
+		v => Err(FormatError::UnsupportedVersion(v))
+	}
+}
+
+#[derive(Debug)]
+pub enum FormatError {
+	// the manifestType it had instead, None if there wasn't one
+	NotCurseForge(Option<serde_json::Value>),
+	// None if it's missing or not a number
+	UnsupportedVersion(Option<u64>)
+}
+
+impl Display for FormatError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::NotCurseForge(Some(t)) => write!(f, "This doesn't look like a CurseForge modpack: manifestType is {}, expected \"{}\"", t, MANIFEST_TYPE),
+			Self::NotCurseForge(None) => write!(f, "This doesn't look like a CurseForge modpack: manifest.json has no manifestType"),
+			Self::UnsupportedVersion(Some(v)) => write!(f, "Unsupported CurseForge manifest version {}, only version {} is understood", v, MANIFEST_VERSION),
+			Self::UnsupportedVersion(None) => write!(f, "CurseForge manifest has no valid manifestVersion")
+		}
+	}
+}
+
+impl Error for FormatError {}
+
+impl Manifest {
+	// hand-edited manifests sometimes list a project twice, which would download it twice
+	pub fn dedup(&mut self, strict: bool) -> Result<(), AppError> {
+		let (files, duplicates) = dedup_files(std::mem::take(&mut self.files));
+		if strict && !duplicates.is_empty() {
+			let listed: Vec<String> = duplicates.iter().map(|d| d.to_string()).collect();
+			return Err(AppError::Other(format!("Pack manifest lists projects more than once: {}", listed.join("; "))));
+		}
+		for duplicate in &duplicates {
+			output::warn(format!("Pack manifest: {}, keeping file {}", duplicate, duplicate.kept));
+		}
+		self.files = files;
+		Ok(())
+	}
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct MinecraftInfo {
 	pub version: String,
 	#[serde(rename = "modLoaders")]
 	pub mod_loaders: Vec<LoaderInfo>
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Clone)]
 pub struct LoaderInfo {
 	pub id: String,
 	pub primary: bool
 }
 
-#[derive(Deserialize)]
+impl MinecraftInfo {
+	// the one marked primary, or the only loader if nothing is marked
+	pub fn primary_loader(&self) -> Result<&LoaderInfo, AppError> {
+		let primary: Vec<&LoaderInfo> = self.mod_loaders.iter().filter(|l| l.primary).collect();
+		match (primary.as_slice(), self.mod_loaders.as_slice()) {
+			([loader], _) => Ok(loader),
+			([], [loader]) => Ok(loader),
+			([], []) => Err(AppError::Other("Pack manifest lists no mod loader".into())),
+			([], _) => Err(AppError::Other(format!("Pack manifest lists mod loaders {} but none is marked primary", loader_ids(&self.mod_loaders)))),
+			(_, _) => Err(AppError::Other(format!("Pack manifest marks more than one mod loader as primary: {}", loader_ids(primary.iter().copied()))))
+		}
+	}
+
+	// problems are only warnings here since plenty of commands never look at the loader,
+	// the ones that do get the error from primary_loader()
+	pub fn validate_loaders(&self) -> Vec<String> {
+		let mut warnings = Vec::new();
+		if self.mod_loaders.is_empty() {
+			return warnings;
+		}
+		match self.primary_loader() {
+			Ok(loader) if !loader.primary => warnings.push(format!("Pack manifest doesn't mark {} as primary, using it anyway", loader.id)),
+			Ok(_) => (),
+			Err(e) => warnings.push(e.to_string())
+		}
+		warnings
+	}
+}
+
+fn loader_ids<'a>(loaders: impl IntoIterator<Item = &'a LoaderInfo>) -> String {
+	loaders.into_iter().map(|l| l.id.as_str()).collect::<Vec<_>>().join(", ")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoaderKind {
+	Forge,
+	NeoForge,
+	Fabric,
+	Quilt
+}
+
+impl LoaderKind {
+	// as it appears in manifest loader ids
+	pub fn name(self) -> &'static str {
+		match self {
+			Self::Forge => "forge",
+			Self::NeoForge => "neoforge",
+			Self::Fabric => "fabric",
+			Self::Quilt => "quilt"
+		}
+	}
+
+	pub fn mod_loader_type(self) -> ModLoaderType {
+		match self {
+			Self::Forge => ModLoaderType::Forge,
+			Self::NeoForge => ModLoaderType::NeoForge,
+			Self::Fabric => ModLoaderType::Fabric,
+			Self::Quilt => ModLoaderType::Quilt
+		}
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LoaderId {
+	pub kind: LoaderKind,
+	pub version: String
+}
+
+#[derive(Debug)]
+pub enum LoaderIdError {
+	Malformed(String),
+	Unsupported(String)
+}
+
+impl Display for LoaderIdError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Malformed(id) => write!(f, "Can't parse mod loader id {}", id),
+			Self::Unsupported(id) => write!(f, "Unsupported mod loader {} (only Forge, NeoForge, Fabric and Quilt are)", id)
+		}
+	}
+}
+
+impl Error for LoaderIdError {}
+
+impl LoaderInfo {
+	// `forge-47.2.0` -> Forge, `47.2.0`
+	pub fn parse(&self) -> Result<LoaderId, LoaderIdError> {
+		let (kind, version) = self.id.split_once('-')
+			.filter(|(kind, version)| !kind.is_empty() && !version.is_empty())
+			.ok_or_else(|| LoaderIdError::Malformed(self.id.clone()))?;
+		let kind = match kind {
+			"forge" => LoaderKind::Forge,
+			"neoforge" => LoaderKind::NeoForge,
+			"fabric" => LoaderKind::Fabric,
+			"quilt" => LoaderKind::Quilt,
+			_ => return Err(LoaderIdError::Unsupported(self.id.clone()))
+		};
+		Ok(LoaderId { kind, version: version.to_string() })
+	}
+}
+
+#[derive(Deserialize, Serialize, Clone)]
 pub struct FileInfo {
 	#[serde(rename = "projectID")]
 	pub project_id: i32,
@@ -36,3 +210,130 @@ pub struct FileInfo {
 	pub required: bool
 }
 
+// a project that was listed more than once
+pub struct Duplicate {
+	pub project_id: i32,
+	// every file id it was listed with, in order
+	pub file_ids: Vec<i32>,
+	pub kept: i32
+}
+
+impl Duplicate {
+	// false when every copy pointed at the same file
+	pub fn conflicts(&self) -> bool {
+		self.file_ids.iter().any(|&id| id != self.kept)
+	}
+}
+
+impl Display for Duplicate {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let ids: Vec<String> = self.file_ids.iter().map(|id| id.to_string()).collect();
+		write!(f, "project {} is listed {} times (files {})", self.project_id, self.file_ids.len(), ids.join(", "))
+	}
+}
+
+// one entry per project, in first-seen order. the highest file id wins since it's usually the newest,
+// and the entry stays required if any copy was
+pub fn dedup_files(files: Vec<FileInfo>) -> (Vec<FileInfo>, Vec<Duplicate>) {
+	let mut deduped: Vec<FileInfo> = Vec::new();
+	let mut seen: HashMap<i32, (usize, Vec<i32>)> = HashMap::new();
+	for file in files {
+		match seen.get_mut(&file.project_id) {
+			Some((idx, ids)) => {
+				let existing = &mut deduped[*idx];
+				existing.file_id = existing.file_id.max(file.file_id);
+				existing.required |= file.required;
+				ids.push(file.file_id);
+			},
+			None => {
+				seen.insert(file.project_id, (deduped.len(), vec![file.file_id]));
+				deduped.push(file);
+			}
+		}
+	}
+	let mut duplicates: Vec<Duplicate> = seen.into_iter()
+		.filter(|(_, (_, ids))| ids.len() > 1)
+		.map(|(project_id, (idx, file_ids))| Duplicate { project_id, file_ids, kept: deduped[idx].file_id })
+		.collect();
+	duplicates.sort_by_key(|d| d.project_id);
+	(deduped, duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn loader(id: &str) -> LoaderInfo {
+		LoaderInfo { id: id.into(), primary: true }
+	}
+
+	#[test]
+	fn loader_ids_parse() {
+		for (id, kind, version) in [
+			("forge-47.2.0", LoaderKind::Forge, "47.2.0"),
+			("neoforge-20.4.237", LoaderKind::NeoForge, "20.4.237"),
+			("fabric-0.15.0", LoaderKind::Fabric, "0.15.0"),
+			("quilt-0.23.1-beta.3", LoaderKind::Quilt, "0.23.1-beta.3")
+		] {
+			assert_eq!(loader(id).parse().unwrap(), LoaderId { kind, version: version.into() });
+		}
+	}
+
+	#[test]
+	fn bad_loader_ids() {
+		assert!(matches!(loader("liteloader-1.12.2").parse(), Err(LoaderIdError::Unsupported(id)) if id == "liteloader-1.12.2"));
+		assert!(matches!(loader("Forge-47.2.0").parse(), Err(LoaderIdError::Unsupported(_))));
+		for id in ["forge", "forge-", "-47.2.0", ""] {
+			assert!(matches!(loader(id).parse(), Err(LoaderIdError::Malformed(_))), "{}", id);
+		}
+	}
+
+	fn file(project_id: i32, file_id: i32, required: bool) -> FileInfo {
+		FileInfo { project_id, file_id, required }
+	}
+
+	#[test]
+	fn dedup_keeps_highest_file_id() {
+		let (files, duplicates) = dedup_files(vec![
+			file(10, 500, false),
+			file(20, 1, true),
+			file(10, 700, false),
+			file(10, 600, true),
+			file(30, 5, true),
+			file(30, 5, true)
+		]);
+		let kept: Vec<(i32, i32, bool)> = files.iter().map(|f| (f.project_id, f.file_id, f.required)).collect();
+		// first-seen order, and required if any copy was
+		assert_eq!(kept, [(10, 700, true), (20, 1, true), (30, 5, true)]);
+		assert_eq!(duplicates.len(), 2);
+		assert_eq!((duplicates[0].project_id, duplicates[0].kept, duplicates[0].file_ids.clone()), (10, 700, vec![500, 700, 600]));
+		assert!(duplicates[0].conflicts());
+		assert!(!duplicates[1].conflicts());
+	}
+
+	#[test]
+	fn strict_dedup_refuses_duplicates() {
+		let mut manifest: Manifest = serde_json::from_str(r#"{
+			"minecraft": {"version": "1.20.1", "modLoaders": []},
+			"manifestType": "minecraftModpack", "manifestVersion": 1,
+			"name": "Test", "version": "1.0", "author": "me",
+			"files": [{"projectID": 10, "fileID": 500, "required": true}, {"projectID": 10, "fileID": 700, "required": true}]
+		}"#).unwrap();
+		assert!(manifest.clone().dedup(true).is_err());
+		manifest.dedup(false).unwrap();
+		assert_eq!(manifest.files.len(), 1);
+		assert_eq!(manifest.files[0].file_id, 700);
+	}
+
+	#[test]
+	fn wrong_manifest_type_is_refused() {
+		let value = serde_json::json!({"manifestType": "modrinthModpack", "manifestVersion": 1});
+		assert!(matches!(check_format(&value), Err(FormatError::NotCurseForge(Some(t))) if t == "modrinthModpack"));
+		assert!(matches!(check_format(&serde_json::json!({"manifestVersion": 1})), Err(FormatError::NotCurseForge(None))));
+		let value = serde_json::json!({"manifestType": MANIFEST_TYPE, "manifestVersion": 2});
+		assert!(matches!(check_format(&value), Err(FormatError::UnsupportedVersion(Some(2)))));
+		let value = serde_json::json!({"manifestType": MANIFEST_TYPE, "manifestVersion": "1"});
+		assert!(matches!(check_format(&value), Err(FormatError::UnsupportedVersion(None))));
+		assert!(check_format(&serde_json::json!({"manifestType": MANIFEST_TYPE, "manifestVersion": 1})).is_ok());
+	}
+}
@@ -0,0 +1,168 @@
+use std::{collections::HashMap, fs::File, io::Write, path::{Path, PathBuf}};
+
+use clap::ArgEnum;
+use zip::{ZipArchive, ZipWriter, write::FileOptions, CompressionMethod};
+
+use crate::{atomic::{self, AtomicFile}, error::{AppError, ErrorContext}, manifest::{self, FileInfo, Manifest}, output, try_open, try_open_pack, try_read_manifest, MANIFEST_NAME};
+
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+	Skip,
+	Overwrite,
+	Error
+}
+
+struct OverrideSource {
+	rel: String,
+	input: usize,
+	entry: String
+}
+
+struct Input {
+	path: PathBuf,
+	manifest: Manifest,
+	zip: Option<ZipArchive<File>>
+}
+
+pub fn parse_pin(s: &str) -> Result<(i32, i32), String> {
+	let (project, file) = s.split_once(':').ok_or("expected PROJECT_ID:FILE_ID")?;
+	let project = project.parse().map_err(|_| format!("invalid project id {}", project))?;
+	let file = file.parse().map_err(|_| format!("invalid file id {}", file))?;
+	Ok((project, file))
+}
+
+pub fn merge(inputs: &[PathBuf], output_path: &Path, on_conflict: ConflictPolicy, pins: &[(i32, i32)]) -> Result<(), AppError> {
+	let mut loaded = Vec::new();
+	for path in inputs {
+		loaded.push(load_input(path)?);
+	}
+	let base = &loaded[0].manifest;
+	for input in &loaded[1..] {
+		if input.manifest.minecraft.version != base.minecraft.version {
+			output::warn(format!("{:?} targets Minecraft {}, but {:?} targets {}",
+				input.path, input.manifest.minecraft.version, loaded[0].path, base.minecraft.version));
+		}
+	}
+
+	let files = merge_files(&loaded, pins)?;
+	let overrides = merge_overrides(&mut loaded, on_conflict)?;
+
+	let mut merged = loaded[0].manifest.clone();
+	merged.files = files;
+	merged.overrides = "overrides".into();
+	let manifest_json = serde_json::to_string_pretty(&merged)
+		.map_err(|e| AppError::Other(format!("Error serializing merged manifest: {}", e)))?;
+
+	if output_path.extension().is_some_and(|e| e == "json") {
+		if !overrides.is_empty() {
+			output::warn(format!("Output is a bare manifest, dropping {} override file(s)", overrides.len()));
+		}
+		atomic::write(output_path, manifest_json)?;
+	} else {
+		let mut writer = ZipWriter::new(AtomicFile::create(output_path)?);
+		let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+		writer.start_file(MANIFEST_NAME, options).context("Error writing merged zip")?;
+		writer.write_all(manifest_json.as_bytes()).context("Error writing merged zip")?;
+		for source in overrides {
+			let zip = loaded[source.input].zip.as_mut().expect("overrides only come from zips");
+			let entry = zip.by_name(&source.entry).context(&format!("Error reading zip entry {}", source.entry))?;
+			writer.raw_copy_file_rename(entry, format!("overrides/{}", source.rel)).context("Error writing merged zip")?;
+		}
+		writer.finish().context("Error writing merged zip")?.commit()?;
+	}
+	output::success(format!("Merged {} packs into {:?} ({} files)", loaded.len(), output_path, merged.files.len()));
+	Ok(())
+}
+
+fn load_input(path: &Path) -> Result<Input, AppError> {
+	if path.extension().is_some_and(|e| e == "json") {
+		let manifest = try_read_manifest(&mut try_open(path)?)?;
+		Ok(Input { path: path.to_path_buf(), manifest, zip: None })
+	} else {
+		let (zip, manifest) = try_open_pack(path)?;
+		Ok(Input { path: path.to_path_buf(), manifest, zip: Some(zip) })
+	}
+}
+
+fn merge_files(inputs: &[Input], pins: &[(i32, i32)]) -> Result<Vec<FileInfo>, AppError> {
+	let all = inputs.iter().flat_map(|i| i.manifest.files.iter().cloned()).collect();
+	let (mut files, duplicates) = manifest::dedup_files(all);
+	// the same file turning up in several packs is the normal case, only differing files are worth a warning
+	for duplicate in duplicates.iter().filter(|d| d.conflicts()) {
+		output::warn(format!("Conflict: {}, keeping file {}", duplicate, duplicate.kept));
+	}
+	let by_project: HashMap<i32, usize> = files.iter().enumerate().map(|(idx, f)| (f.project_id, idx)).collect();
+	for (project_id, file_id) in pins {
+		match by_project.get(project_id) {
+			Some(&idx) => files[idx].file_id = *file_id,
+			None => return Err(AppError::Other(format!("Pinned project {} isn't in any of the packs", project_id)))
+		}
+	}
+	Ok(files)
+}
+
+// in first-seen order
+fn merge_overrides(inputs: &mut [Input], on_conflict: ConflictPolicy) -> Result<Vec<OverrideSource>, AppError> {
+	let mut overrides: Vec<OverrideSource> = Vec::new();
+	let mut by_path: HashMap<String, usize> = HashMap::new();
+	for (idx, input) in inputs.iter_mut().enumerate() {
+		let prefix = format!("{}/", input.manifest.overrides);
+		let zip = match input.zip.as_mut() {
+			Some(z) => z,
+			None => continue
+		};
+		let names: Vec<String> = zip.file_names()
+			.filter(|n| n.starts_with(&prefix) && !n.ends_with('/'))
+			.map(|n| n.to_string())
+			.collect();
+		for name in names {
+			let rel = name[prefix.len()..].to_string();
+			match by_path.get(&rel) {
+				Some(&existing) => match on_conflict {
+					ConflictPolicy::Skip => output::warn(format!("Override conflict: {} ({:?}), keeping earlier copy", rel, input.path)),
+					ConflictPolicy::Overwrite => {
+						output::warn(format!("Override conflict: {} ({:?}), overwriting earlier copy", rel, input.path));
+						overrides[existing].input = idx;
+						overrides[existing].entry = name;
+					},
+					ConflictPolicy::Error => return Err(AppError::Other(format!("Override conflict: {} exists in more than one pack", rel)))
+				},
+				None => {
+					by_path.insert(rel.clone(), overrides.len());
+					overrides.push(OverrideSource { rel, input: idx, entry: name });
+				}
+			}
+		}
+	}
+	Ok(overrides)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn input(files: &[(i32, i32)]) -> Input {
+		let files: Vec<serde_json::Value> = files.iter()
+			.map(|(project, file)| serde_json::json!({"projectID": project, "fileID": file, "required": true}))
+			.collect();
+		let manifest = serde_json::from_value(serde_json::json!({
+			"minecraft": {"version": "1.20.1", "modLoaders": []},
+			"manifestType": "minecraftModpack", "manifestVersion": 1,
+			"name": "Test", "version": "1.0", "author": "me",
+			"files": files
+		})).unwrap();
+		Input { path: PathBuf::from("pack.zip"), manifest, zip: None }
+	}
+
+	fn file_ids(files: &[FileInfo]) -> Vec<(i32, i32)> {
+		files.iter().map(|f| (f.project_id, f.file_id)).collect()
+	}
+
+	#[test]
+	fn higher_file_id_wins_unless_pinned() {
+		let inputs = [input(&[(10, 700), (20, 1)]), input(&[(10, 500), (30, 3)])];
+		assert_eq!(file_ids(&merge_files(&inputs, &[]).unwrap()), [(10, 700), (20, 1), (30, 3)]);
+		assert_eq!(file_ids(&merge_files(&inputs, &[(10, 500)]).unwrap()), [(10, 500), (20, 1), (30, 3)]);
+		assert!(merge_files(&inputs, &[(40, 1)]).is_err());
+	}
+}
@@ -0,0 +1,190 @@
+use std::{collections::HashMap, fs, io, path::{Path, PathBuf}};
+
+use crate::{api::{self, HashAlgo, Mod, CACHE_FILES, CACHE_MODS}, atomic::AtomicFile, cache::MetadataCache, error::{AppError, ErrorContext}, hash, install::{self, InstallOptions}, instance, lock::Lockfile, manifest::FileInfo, output, record::InstallRecord, space, summary::{InstallSummary, InstalledFile, ModResult, Outcome}, try_mkdir, try_open, try_open_pack};
+
+// what can be known about a manifest file without the API: a lockfile has everything,
+// otherwise whatever an earlier online install left in the metadata cache
+struct Expected {
+	info: FileInfo,
+	file_name: Option<String>,
+	sha1: Option<String>,
+	class_id: Option<i32>
+}
+
+// jars in the --from dir, found by name straight away and by sha1 only once a name lookup misses
+struct SourceDir {
+	by_name: HashMap<String, PathBuf>,
+	by_sha1: Option<HashMap<String, PathBuf>>
+}
+
+// installs a pack from a folder of already downloaded mods, never touching the network.
+// like install_pack, files missing from the folder are counted in the summary rather than failing the whole thing
+pub fn install_offline(pack_path: &Path, install_to: &Path, from: &Path, opts: &InstallOptions, cache: Option<&MetadataCache>) -> Result<InstallSummary, AppError> {
+	let (mut pack, mut manifest) = try_open_pack(pack_path)?;
+	manifest.dedup(opts.strict)?;
+	log::info!("Installing {} {} by {} (Minecraft {}, {} files) into {:?} from {:?}",
+		manifest.name, manifest.version, manifest.author, manifest.minecraft.version, manifest.files.len(), install_to, from);
+
+	opts.check_phases()?;
+	let expected = match &opts.locked {
+		_ if opts.overrides_only => Vec::new(),
+		Some(lock_path) => from_lockfile(&Lockfile::read(lock_path)?, cache),
+		None => from_cache(&manifest.files, cache)
+	};
+	let mut source = SourceDir::scan(from)?;
+	let matches: Vec<_> = expected.into_iter()
+		.map(|e| {
+			let found = source.find(&e);
+			(e, found)
+		})
+		.collect();
+
+	try_mkdir(install_to)?;
+	let game_dir = opts.instance_format.prepare(install_to, &manifest)?;
+	try_mkdir(&game_dir.join("mods"))?;
+	let fnames = opts.override_entries(&pack, &manifest);
+	if !opts.skip_space_check {
+		let mod_bytes: u64 = matches.iter()
+			.filter_map(|(_, m)| m.as_ref().ok())
+			.filter_map(|(path, _)| fs::metadata(path).ok())
+			.map(|m| m.len())
+			.sum();
+		space::check(&game_dir, mod_bytes + install::override_size(&mut pack, &fnames))?;
+	}
+
+	let total = matches.len();
+	let mut results = Vec::new();
+	for (file, found) in matches {
+		if opts.cancel.is_cancelled() {
+			break;
+		}
+		let (outcome, installed) = match found.and_then(|(path, verified)| copy_file(&file, &path, verified, &game_dir)) {
+			Ok((o, installed)) => (o, Some(installed)),
+			Err(e) => {
+				if opts.fail_fast {
+					return Err(e);
+				}
+				output::error(&e);
+				(Outcome::Failed(e), None)
+			}
+		};
+		results.push(ModResult { project_id: file.info.project_id, file_id: file.info.file_id, outcome, installed });
+	}
+	let (extracted, override_failures) = install::extract_overrides(&mut pack, fnames, &manifest.overrides, &game_dir, opts.fail_fast, &opts.cancel, None)?;
+
+	let summary = InstallSummary::from_results(results, total, extracted, override_failures);
+	InstallRecord::update(&game_dir, &manifest, &summary, opts.server)?;
+	Ok(summary)
+}
+
+fn from_lockfile(lock: &Lockfile, cache: Option<&MetadataCache>) -> Vec<Expected> {
+	lock.files.iter()
+		.map(|e| Expected {
+			info: FileInfo { project_id: e.project_id, file_id: e.file_id, required: true },
+			file_name: Some(e.file_name.clone()),
+			sha1: e.sha1.clone(),
+			class_id: cached_class(cache, e.project_id)
+		})
+		.collect()
+}
+
+fn from_cache(files: &[FileInfo], cache: Option<&MetadataCache>) -> Vec<Expected> {
+	files.iter()
+		.map(|f| {
+			let meta: Option<api::File> = cache.and_then(|c| c.get(CACHE_FILES, f.file_id));
+			Expected {
+				info: f.clone(),
+				sha1: meta.as_ref().and_then(|m| m.hashes.iter().find(|h| h.algo == HashAlgo::Sha1)).map(|h| h.value.to_lowercase()),
+				file_name: meta.map(|m| m.file_name),
+				class_id: cached_class(cache, f.project_id)
+			}
+		})
+		.collect()
+}
+
+fn cached_class(cache: Option<&MetadataCache>, project_id: i32) -> Option<i32> {
+	cache.and_then(|c| c.get::<Mod>(CACHE_MODS, project_id)).and_then(|m| m.class_id)
+}
+
+impl SourceDir {
+	fn scan(dir: &Path) -> Result<Self, AppError> {
+		let mut by_name = HashMap::new();
+		collect_files(dir, &mut by_name).context(&format!("Error reading {:?}", dir))?;
+		log::info!("Found {} files in {:?}", by_name.len(), dir);
+		Ok(Self { by_name, by_sha1: None })
+	}
+
+	// the bool is whether the file's hash was checked against the expected one
+	fn find(&mut self, file: &Expected) -> Result<(PathBuf, bool), AppError> {
+		let by_name = file.file_name.as_ref().and_then(|n| self.by_name.get(n));
+		match (by_name, &file.sha1) {
+			(Some(path), None) => return Ok((path.clone(), false)),
+			(Some(path), Some(expected)) => {
+				if sha1_of(path)?.eq_ignore_ascii_case(expected) {
+					return Ok((path.clone(), true));
+				}
+				output::warn(format!("{:?} doesn't match the expected sha1, looking for another copy", path));
+			},
+			_ => ()
+		}
+		if let Some(expected) = &file.sha1 {
+			if let Some(path) = self.hashes()?.get(&expected.to_lowercase()) {
+				return Ok((path.clone(), true));
+			}
+		}
+		Err(AppError::Other(match &file.file_name {
+			Some(name) => format!("{} (project {}, file {}) isn't in the --from directory", name, file.info.project_id, file.info.file_id),
+			None => format!("Project {} file {} has no cached metadata to find it by, pass --locked with a lockfile", file.info.project_id, file.info.file_id)
+		}))
+	}
+
+	fn hashes(&mut self) -> Result<&HashMap<String, PathBuf>, AppError> {
+		let hashes = match self.by_sha1.take() {
+			Some(h) => h,
+			None => {
+				let mut hashes = HashMap::new();
+				for path in self.by_name.values() {
+					hashes.insert(sha1_of(path)?, path.clone());
+				}
+				hashes
+			}
+		};
+		Ok(self.by_sha1.insert(hashes))
+	}
+}
+
+// the first file found wins when a name turns up in more than one subfolder
+fn collect_files(dir: &Path, out: &mut HashMap<String, PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let path = entry?.path();
+		if path.is_dir() {
+			collect_files(&path, out)?;
+		} else if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+			out.entry(name.to_string()).or_insert(path);
+		}
+	}
+	Ok(())
+}
+
+fn sha1_of(path: &Path) -> Result<String, AppError> {
+	let mut hasher = hash::Sha1Stream::new();
+	hasher.update_from(try_open(path)?).context(&format!("Error reading {:?}", path))?;
+	Ok(hasher.hex())
+}
+
+fn copy_file(file: &Expected, source: &Path, verified: bool, game_dir: &Path) -> Result<(Outcome, InstalledFile), AppError> {
+	let name = match &file.file_name {
+		Some(n) => Path::new(n),
+		None => Path::new(source.file_name().unwrap_or_default())
+	};
+	let dir = game_dir.join(instance::content_folder(file.class_id));
+	try_mkdir(&dir)?;
+	let target = install::contained_path(&dir, name)?;
+	let mut out = hash::HashWriter::new(AtomicFile::create(&target)?);
+	io::copy(&mut try_open(source)?, &mut out).context(&format!("Error copying {:?} to {:?}", source, target))?;
+	let (out, sha1) = out.finish();
+	out.commit()?;
+	output::info(format!("Copied {}", name.display()));
+	let outcome = if verified { Outcome::Verified } else { Outcome::Downloaded };
+	Ok((outcome, InstalledFile::new(game_dir, &target, sha1)))
+}
@@ -0,0 +1,151 @@
+use std::{env, fmt::Display, fs::File, io::{self, IsTerminal, Write}, path::Path, sync::{atomic::{AtomicBool, Ordering}, Mutex, OnceLock, RwLock}, time::SystemTime};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+static COLOR: OnceLock<bool> = OnceLock::new();
+// set when stdout belongs to something else (e.g. --events), messages then only go to the log
+// and errors to stderr
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// credentials that must never reach the console or the log file, whatever ends up formatting them
+static SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+const REDACTED: &str = "***";
+// anything shorter would start masking ordinary words
+const MIN_SECRET_LEN: usize = 8;
+
+pub fn add_secret(secret: &str) {
+	let secret = secret.trim();
+	if secret.len() < MIN_SECRET_LEN {
+		return;
+	}
+	if let Ok(mut secrets) = SECRETS.write() {
+		if !secrets.iter().any(|s| s == secret) {
+			secrets.push(secret.to_string());
+		}
+	}
+}
+
+pub fn redact(msg: impl Display) -> String {
+	let mut msg = msg.to_string();
+	if let Ok(secrets) = SECRETS.read() {
+		for secret in secrets.iter() {
+			if msg.contains(secret.as_str()) {
+				msg = msg.replace(secret.as_str(), REDACTED);
+			}
+		}
+	}
+	msg
+}
+
+pub fn set_quiet(quiet: bool) {
+	QUIET.store(quiet, Ordering::Relaxed);
+}
+
+fn quiet() -> bool {
+	QUIET.load(Ordering::Relaxed)
+}
+
+fn use_color() -> bool {
+	*COLOR.get_or_init(|| {
+		io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty())
+	})
+}
+
+fn paint(color: &str, msg: impl Display) -> String {
+	if use_color() {
+		format!("{}{}{}", color, msg, RESET)
+	} else {
+		msg.to_string()
+	}
+}
+
+pub fn info(msg: impl Display) {
+	let msg = redact(msg);
+	log::info!("{}", msg);
+	if !quiet() {
+		println!("{}", msg);
+	}
+}
+
+pub fn success(msg: impl Display) {
+	let msg = redact(msg);
+	log::info!("{}", msg);
+	if !quiet() {
+		println!("{}", paint(GREEN, msg));
+	}
+}
+
+pub fn warn(msg: impl Display) {
+	let msg = redact(msg);
+	log::warn!("{}", msg);
+	if !quiet() {
+		println!("{}", paint(YELLOW, msg));
+	}
+}
+
+pub fn error(msg: impl Display) {
+	let msg = redact(msg);
+	log::error!("{}", msg);
+	if quiet() {
+		eprintln!("{}", msg);
+	} else {
+		println!("{}", paint(RED, msg));
+	}
+}
+
+// anything but an explicit yes (including EOF) is a no
+pub fn confirm(prompt: impl Display) -> bool {
+	print!("{} [y/N] ", prompt);
+	let _ = io::stdout().flush();
+	let mut answer = String::new();
+	if io::stdin().read_line(&mut answer).is_err() {
+		return false;
+	}
+	matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// the console is driven directly by the functions above, the log only ever goes to a file
+struct FileLogger {
+	file: Mutex<File>
+}
+
+impl Log for FileLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		// rustls et al. are very chatty at debug
+		if metadata.target().starts_with(env!("CARGO_CRATE_NAME")) {
+			metadata.level() <= Level::Debug
+		} else {
+			metadata.level() <= Level::Warn
+		}
+	}
+
+	fn log(&self, record: &Record) {
+		if self.enabled(record.metadata()) {
+			if let Ok(mut file) = self.file.lock() {
+				let _ = writeln!(file, "{} {:<5} {}", humantime::format_rfc3339_seconds(SystemTime::now()), record.level(), redact(record.args()));
+			}
+		}
+	}
+
+	fn flush(&self) {
+		if let Ok(mut file) = self.file.lock() {
+			let _ = file.flush();
+		}
+	}
+}
+
+pub fn init_log_file(path: &Path) -> io::Result<()> {
+	let file = File::create(path)?;
+	let logger = FileLogger {
+		file: Mutex::new(file)
+	};
+	if log::set_boxed_logger(Box::new(logger)).is_ok() {
+		log::set_max_level(LevelFilter::Debug);
+	}
+	Ok(())
+}
@@ -0,0 +1,41 @@
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{output, progress::format_bytes};
+
+// decides whether to go ahead with a plan, usually by asking
+pub type ConfirmPlan = Arc<dyn Fn(&InstallPlan) -> bool + Send + Sync>;
+
+// what install_pack is about to do, worked out once everything has been resolved but before any downloads start
+pub struct InstallPlan {
+	pub pack_name: String,
+	pub pack_version: String,
+	pub minecraft_version: String,
+	pub game_dir: PathBuf,
+	pub mods: usize,
+	pub mod_bytes: u64,
+	// mods CF gives no size for, which aren't in mod_bytes
+	pub unsized_mods: usize,
+	pub overrides_folder: String,
+	pub overrides: usize,
+	pub override_bytes: u64,
+	// files CF won't give a download URL for, which are fetched straight from the CDN instead
+	pub fallback: Vec<String>
+}
+
+impl InstallPlan {
+	pub fn print(&self) {
+		output::info(format!("Installing {} {} (Minecraft {}) into {:?}", self.pack_name, self.pack_version, self.minecraft_version, self.game_dir));
+		let unsized_mods = if self.unsized_mods > 0 { format!(", plus {} mods of unknown size", self.unsized_mods) } else { String::new() };
+		output::info(format!("  {} mods, {} to download{}", self.mods, format_bytes(self.mod_bytes), unsized_mods));
+		if self.overrides > 0 {
+			output::info(format!("  {} files from {}/, {}", self.overrides, self.overrides_folder, format_bytes(self.override_bytes)));
+		}
+		if !self.fallback.is_empty() {
+			output::warn(format!("  {} mods don't allow third-party downloads and will be fetched from the CDN directly:", self.fallback.len()));
+			for name in &self.fallback {
+				output::warn(format!("    {}", name));
+			}
+		}
+		output::info(format!("  {} to write in total", format_bytes(self.mod_bytes + self.override_bytes)));
+	}
+}
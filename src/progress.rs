@@ -0,0 +1,86 @@
+use std::{sync::atomic::{AtomicU64, Ordering}, time::{Duration, Instant}};
+
+pub const REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+// byte counts shared by all download workers. `done` includes whatever was already on disk
+// from resumed downloads, `transferred` is only this run's traffic, which is what speed is based on
+pub struct Progress {
+	// None if nothing's size was known up front. files whose size only turns up once they're downloading are
+	// added as they start
+	total: Option<AtomicU64>,
+	done: AtomicU64,
+	transferred: AtomicU64,
+	start: Instant
+}
+
+impl Progress {
+	pub fn new(total: Option<u64>) -> Self {
+		Self {
+			total: total.map(AtomicU64::new),
+			done: AtomicU64::new(0),
+			transferred: AtomicU64::new(0),
+			start: Instant::now()
+		}
+	}
+
+	pub fn resumed(&self, bytes: u64) {
+		self.done.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn transferred(&self, bytes: u64) {
+		self.done.fetch_add(bytes, Ordering::Relaxed);
+		self.transferred.fetch_add(bytes, Ordering::Relaxed);
+	}
+
+	pub fn grow(&self, bytes: u64) {
+		if let Some(total) = &self.total {
+			total.fetch_add(bytes, Ordering::Relaxed);
+		}
+	}
+
+	pub fn done(&self) -> u64 {
+		self.done.load(Ordering::Relaxed)
+	}
+
+	pub fn total(&self) -> Option<u64> {
+		self.total.as_ref().map(|t| t.load(Ordering::Relaxed))
+	}
+
+	// only this run's traffic counts, resumed bytes came for free
+	pub fn bytes_per_sec(&self) -> u64 {
+		rate(self.transferred.load(Ordering::Relaxed), self.start.elapsed()) as u64
+	}
+}
+
+pub fn status_line(done: u64, total: Option<u64>, bytes_per_sec: u64) -> String {
+	match total {
+		Some(total) => {
+			let eta = match total.saturating_sub(done).checked_div(bytes_per_sec) {
+				Some(secs) => humantime::format_duration(Duration::from_secs(secs)).to_string(),
+				None => "unknown".into()
+			};
+			format!("{} / {} at {}/s, ETA {}", format_bytes(done), format_bytes(total), format_bytes(bytes_per_sec), eta)
+		},
+		None => format!("{} at {}/s", format_bytes(done), format_bytes(bytes_per_sec))
+	}
+}
+
+pub fn rate(bytes: u64, elapsed: Duration) -> f64 {
+	let secs = elapsed.as_secs_f64();
+	if secs > 0.0 { bytes as f64 / secs } else { 0.0 }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+	const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+	let mut value = bytes as f64;
+	let mut unit = 0;
+	while value >= 1024.0 && unit < UNITS.len() - 1 {
+		value /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{} {}", bytes, UNITS[0])
+	} else {
+		format!("{:.1} {}", value, UNITS[unit])
+	}
+}
@@ -0,0 +1,75 @@
+use std::env;
+
+use minreq::{Proxy, Request};
+
+#[derive(Clone, Default, Debug)]
+pub struct ProxyConfig {
+	explicit: Option<String>,
+	http: Option<String>,
+	https: Option<String>,
+	no_proxy: Vec<String>
+}
+
+impl ProxyConfig {
+	// --proxy wins over the environment, NO_PROXY applies to both
+	pub fn new(explicit: Option<String>) -> Self {
+		Self {
+			explicit,
+			http: env_var(&["HTTP_PROXY", "http_proxy"]),
+			https: env_var(&["HTTPS_PROXY", "https_proxy"]),
+			no_proxy: env_var(&["NO_PROXY", "no_proxy"])
+				.map(|s| s.split(',').map(|h| h.trim().to_lowercase()).filter(|h| !h.is_empty()).collect())
+				.unwrap_or_default()
+		}
+	}
+
+	pub fn for_url(&self, url: &str) -> Result<Option<Proxy>, minreq::Error> {
+		let (scheme, host) = split_url(url);
+		if self.bypass(&host) {
+			return Ok(None);
+		}
+		let proxy = match (&self.explicit, scheme.as_str()) {
+			(Some(p), _) => Some(p),
+			(None, "https") => self.https.as_ref().or(self.http.as_ref()),
+			(None, _) => self.http.as_ref()
+		};
+		match proxy {
+			Some(p) => Ok(Some(Proxy::new(strip_scheme(p))?)),
+			None => Ok(None)
+		}
+	}
+
+	pub fn apply(&self, request: Request, url: &str) -> Result<Request, minreq::Error> {
+		match self.for_url(url)? {
+			Some(proxy) => Ok(request.with_proxy(proxy)),
+			None => Ok(request)
+		}
+	}
+
+	fn bypass(&self, host: &str) -> bool {
+		self.no_proxy.iter().any(|entry| {
+			let entry = entry.trim_start_matches('.');
+			entry == "*" || host == entry || host.ends_with(&format!(".{}", entry))
+		})
+	}
+}
+
+fn env_var(names: &[&str]) -> Option<String> {
+	names.iter()
+		.filter_map(|n| env::var(n).ok())
+		.find(|v| !v.is_empty())
+}
+
+// minreq wants `[user:pass@]host:port`, people tend to hand us full URLs
+fn strip_scheme(proxy: &str) -> &str {
+	let proxy = proxy.split_once("://").map(|(_, rest)| rest).unwrap_or(proxy);
+	proxy.trim_end_matches('/')
+}
+
+fn split_url(url: &str) -> (String, String) {
+	let (scheme, rest) = url.split_once("://").unwrap_or(("http", url));
+	let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+	let host = authority.rsplit('@').next().unwrap_or(authority);
+	let host = host.split(':').next().unwrap_or(host);
+	(scheme.to_lowercase(), host.to_lowercase())
+}
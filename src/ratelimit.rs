@@ -0,0 +1,95 @@
+use std::{thread, time::{Duration, Instant}};
+
+use parking_lot::Mutex;
+
+// token bucket shared between threads. units are whatever the caller is pacing: bytes for
+// downloads, requests for API calls. it holds at most one second's worth of tokens, and
+// callers that overdraw go into debt and sleep it off outside the lock
+pub struct RateLimiter {
+	rate: f64,
+	bucket: Mutex<Bucket>
+}
+
+struct Bucket {
+	tokens: f64,
+	last: Instant
+}
+
+impl RateLimiter {
+	pub fn new(per_second: u64) -> Self {
+		let rate = per_second.max(1) as f64;
+		Self {
+			rate,
+			bucket: Mutex::new(Bucket { tokens: rate, last: Instant::now() })
+		}
+	}
+
+	pub fn acquire(&self, amount: u64) {
+		let wait = {
+			let mut bucket = self.bucket.lock();
+			let now = Instant::now();
+			let refill = now.duration_since(bucket.last).as_secs_f64() * self.rate;
+			bucket.tokens = (bucket.tokens + refill).min(self.rate) - amount as f64;
+			bucket.last = now;
+			if bucket.tokens < 0.0 {
+				Duration::from_secs_f64(-bucket.tokens / self.rate)
+			} else {
+				Duration::ZERO
+			}
+		};
+		if !wait.is_zero() {
+			thread::sleep(wait);
+		}
+	}
+}
+
+// bytes per second, with optional k/m/g suffixes (powers of 1024)
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+	let s = s.trim();
+	let (num, mult) = match s.chars().last().map(|c| c.to_ascii_lowercase()) {
+		Some('k') => (&s[..s.len() - 1], 1024),
+		Some('m') => (&s[..s.len() - 1], 1024 * 1024),
+		Some('g') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+		_ => (s, 1)
+	};
+	match num.parse::<u64>() {
+		Ok(0) => Err("must be greater than 0".into()),
+		Ok(n) => n.checked_mul(mult).ok_or_else(|| "too large".into()),
+		Err(e) => Err(e.to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+
+	use super::*;
+
+	// the bucket starts full, so n acquires at rate r can get through the first r free and then
+	// have to wait for the other n-r
+	#[test]
+	fn acquires_are_spaced_out() {
+		let limiter = RateLimiter::new(20);
+		let start = Instant::now();
+		for _ in 0..30 {
+			limiter.acquire(1);
+		}
+		assert!(start.elapsed() >= Duration::from_millis(500), "took {:?}", start.elapsed());
+	}
+
+	#[test]
+	fn threads_share_the_bucket() {
+		let limiter = Arc::new(RateLimiter::new(20));
+		let start = Instant::now();
+		let handles: Vec<_> = (0..3).map(|_| {
+			let limiter = limiter.clone();
+			thread::spawn(move || for _ in 0..10 {
+				limiter.acquire(1);
+			})
+		}).collect();
+		for handle in handles {
+			handle.join().unwrap();
+		}
+		assert!(start.elapsed() >= Duration::from_millis(500), "took {:?}", start.elapsed());
+	}
+}
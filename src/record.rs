@@ -0,0 +1,195 @@
+use std::{collections::BTreeSet, fs, path::{Component, Path, PathBuf}};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic, error::{AppError, ErrorContext}, manifest::Manifest, summary::InstallSummary, try_load_file};
+
+pub const RECORD_NAME: &str = "installed.json";
+
+// what installs have put in a game dir, so later commands can work from what's actually there and it can all
+// be taken out again without touching anything the player added. paths are relative to the game dir and '/' separated
+#[derive(Serialize, Deserialize, Default)]
+pub struct InstallRecord {
+	// the pack most recently installed there
+	pub pack: Option<RecordedPack>,
+	// whether that was a server install, so update picks the same overrides. records from before this was kept
+	// read as client installs
+	#[serde(default)]
+	pub server: bool,
+	pub mods: Vec<RecordedMod>,
+	pub overrides: Vec<RecordedFile>
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedPack {
+	pub name: String,
+	pub version: String,
+	#[serde(rename = "minecraftVersion")]
+	pub minecraft_version: String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedMod {
+	#[serde(rename = "projectId")]
+	pub project_id: i32,
+	#[serde(rename = "fileId")]
+	pub file_id: i32,
+	pub path: String,
+	pub sha1: String
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedFile {
+	pub path: String,
+	pub sha1: String
+}
+
+impl InstallRecord {
+	pub fn path(game_dir: &Path) -> PathBuf {
+		game_dir.join(RECORD_NAME)
+	}
+
+	// None if nothing was ever installed there
+	pub fn read(game_dir: &Path) -> Result<Option<Self>, AppError> {
+		let path = Self::path(game_dir);
+		if !path.is_file() {
+			return Ok(None);
+		}
+		let data = try_load_file(&path)?;
+		serde_json::from_str(&data).map(Some).map_err(|e| AppError::Other(format!("Error parsing install record {:?}: {}", path, e)))
+	}
+
+	pub fn write(&self, game_dir: &Path) -> Result<(), AppError> {
+		let json = serde_json::to_string_pretty(self)
+			.map_err(|e| AppError::Other(format!("Error serializing install record: {}", e)))?;
+		atomic::write(&Self::path(game_dir), json)
+	}
+
+	// merges into whatever's recorded already, so a resumed or repeated install still remembers the earlier
+	// runs' files. a path written again replaces its old entry
+	pub fn update(game_dir: &Path, manifest: &Manifest, summary: &InstallSummary, server: bool) -> Result<(), AppError> {
+		let mut record = Self::read(game_dir)?.unwrap_or_default();
+		record.merge(manifest, summary, server);
+		record.write(game_dir)
+	}
+
+	pub fn merge(&mut self, manifest: &Manifest, summary: &InstallSummary, server: bool) {
+		self.server = server;
+		self.pack = Some(RecordedPack {
+			name: manifest.name.clone(),
+			version: manifest.version.clone(),
+			minecraft_version: manifest.minecraft.version.clone()
+		});
+		for m in &summary.mods {
+			self.mods.retain(|r| r.path != m.file.path);
+			self.mods.push(RecordedMod { project_id: m.project_id, file_id: m.file_id, path: m.file.path.clone(), sha1: m.file.sha1.clone() });
+		}
+		for f in &summary.overrides {
+			self.overrides.retain(|r| r.path != f.path);
+			self.overrides.push(RecordedFile { path: f.path.clone(), sha1: f.sha1.clone() });
+		}
+		self.mods.sort_by(|a, b| a.path.cmp(&b.path));
+		self.overrides.sort_by(|a, b| a.path.cmp(&b.path));
+	}
+
+	// every file the record knows about, mods and overrides alike
+	pub fn paths(&self) -> BTreeSet<&str> {
+		self.mods.iter().map(|m| m.path.as_str()).chain(self.overrides.iter().map(|f| f.path.as_str())).collect()
+	}
+}
+
+#[derive(Default)]
+pub struct Uninstalled {
+	pub deleted: Vec<String>,
+	// recorded, but already gone
+	pub missing: Vec<String>,
+	// recorded, but pointing outside the game dir
+	pub refused: Vec<String>
+}
+
+// deletes every recorded file, any folders that leaves empty, and then the record itself. nothing outside
+// the game dir is touched, whatever the record says. with dry_run it only reports what would go
+pub fn uninstall(game_dir: &Path, dry_run: bool) -> Result<Uninstalled, AppError> {
+	let record = InstallRecord::read(game_dir)?
+		.ok_or_else(|| AppError::Other(format!("No {} in {:?}, so there's no record of what was installed there", RECORD_NAME, game_dir)))?;
+	let base = game_dir.canonicalize().context(&format!("Error resolving {:?}", game_dir))?;
+	let mut result = Uninstalled::default();
+	for rel in record.paths() {
+		let path = match contained(&base, rel) {
+			Some(p) => p,
+			None => {
+				result.refused.push(rel.to_string());
+				continue;
+			}
+		};
+		if fs::symlink_metadata(&path).is_err() {
+			result.missing.push(rel.to_string());
+			continue;
+		}
+		if !dry_run {
+			fs::remove_file(&path).context(&format!("Error deleting {:?}", path))?;
+			remove_empty_parents(&base, &path);
+		}
+		result.deleted.push(rel.to_string());
+	}
+	if !dry_run {
+		let path = InstallRecord::path(game_dir);
+		fs::remove_file(&path).context(&format!("Error deleting {:?}", path))?;
+	}
+	Ok(result)
+}
+
+// the record is just a json file, so its paths get the same checks as a zip's entries
+pub(crate) fn contained(base: &Path, rel: &str) -> Option<PathBuf> {
+	let rel = Path::new(rel);
+	if !rel.components().all(|c| matches!(c, Component::Normal(_))) {
+		return None;
+	}
+	let path = base.join(rel);
+	// a symlinked folder could lead anywhere, the file itself being a symlink is fine since only the link goes
+	match path.parent()?.canonicalize() {
+		Ok(parent) if !parent.starts_with(base) => None,
+		_ => Some(path)
+	}
+}
+
+fn remove_empty_parents(base: &Path, path: &Path) {
+	for dir in path.ancestors().skip(1).take_while(|d| *d != base) {
+		// fails on the first folder that still has something in it, which is where to stop
+		if fs::remove_dir(dir).is_err() {
+			break;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{env, process};
+
+	use super::*;
+
+	#[test]
+	fn contained_refuses_escapes() {
+		let dir = env::temp_dir().join(format!("underlion-record-{}", process::id()));
+		fs::create_dir_all(dir.join("mods")).unwrap();
+		let base = dir.canonicalize().unwrap();
+		assert_eq!(contained(&base, "mods/a.jar"), Some(base.join("mods/a.jar")));
+		assert_eq!(contained(&base, "../evil.jar"), None);
+		assert_eq!(contained(&base, "mods/../../evil.jar"), None);
+		assert_eq!(contained(&base, base.join("mods/a.jar").to_str().unwrap()), None);
+		#[cfg(unix)]
+		{
+			std::os::unix::fs::symlink(env::temp_dir(), base.join("link")).unwrap();
+			assert_eq!(contained(&base, "link/evil.jar"), None);
+		}
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn old_records_read_as_client_installs() {
+		let record: InstallRecord = serde_json::from_str(r#"{"pack": null, "mods": [], "overrides": []}"#).unwrap();
+		assert!(!record.server);
+		let json = serde_json::to_string(&InstallRecord { server: true, ..Default::default() }).unwrap();
+		assert!(serde_json::from_str::<InstallRecord>(&json).unwrap().server);
+	}
+}
@@ -0,0 +1,116 @@
+use std::{fs, path::Path};
+
+use crate::{atomic, error::{AppError, ErrorContext, TryExpect}, loader, manifest::{LoaderKind, Manifest}, proxy::ProxyConfig};
+
+const JVM_ARGS: &str = "-Xms1G -Xmx4G";
+
+struct Launch {
+	// relative path that only exists once the loader is installed
+	installed_marker: String,
+	install_cmd: Option<String>,
+	unix_cmd: String,
+	windows_cmd: String
+}
+
+pub fn write_server_scripts(dir: &Path, manifest: &Manifest, proxy: &ProxyConfig) -> Result<(), AppError> {
+	let mc = &manifest.minecraft.version;
+	let loader = loader::primary_loader_id(manifest)?;
+	let version = loader.version.as_str();
+
+	let launch = match loader.kind {
+		LoaderKind::Forge => {
+			loader::download_forge_installer(dir, mc, version, proxy)?;
+			let install_cmd = Some(format!("java -jar {} --installServer", loader::forge_installer_name(mc, version)));
+			if uses_forge_args_files(mc) {
+				let libs = format!("libraries/net/minecraftforge/forge/{}-{}", mc, version);
+				Launch {
+					installed_marker: format!("{}/unix_args.txt", libs),
+					install_cmd,
+					unix_cmd: format!("java $JVM_ARGS @{}/unix_args.txt nogui \"$@\"", libs),
+					windows_cmd: format!("java %JVM_ARGS% @{}/win_args.txt nogui %*", libs)
+				}
+			} else {
+				let jar = format!("forge-{}-{}.jar", mc, version);
+				Launch {
+					installed_marker: jar.clone(),
+					install_cmd,
+					unix_cmd: format!("java $JVM_ARGS -jar {} nogui \"$@\"", jar),
+					windows_cmd: format!("java %JVM_ARGS% -jar {} nogui %*", jar)
+				}
+			}
+		},
+		LoaderKind::NeoForge => {
+			loader::download_neoforge_installer(dir, version, proxy)?;
+			let libs = format!("libraries/net/neoforged/neoforge/{}", version);
+			Launch {
+				installed_marker: format!("{}/unix_args.txt", libs),
+				install_cmd: Some(format!("java -jar {} --installServer", loader::neoforge_installer_name(version))),
+				unix_cmd: format!("java $JVM_ARGS @{}/unix_args.txt nogui \"$@\"", libs),
+				windows_cmd: format!("java %JVM_ARGS% @{}/win_args.txt nogui %*", libs)
+			}
+		},
+		LoaderKind::Fabric => {
+			loader::download_fabric_server_launcher(dir, mc, version, proxy)?;
+			Launch {
+				installed_marker: "fabric-server-launch.jar".into(),
+				install_cmd: None,
+				unix_cmd: "java $JVM_ARGS -jar fabric-server-launch.jar nogui \"$@\"".into(),
+				windows_cmd: "java %JVM_ARGS% -jar fabric-server-launch.jar nogui %*".into()
+			}
+		},
+		LoaderKind::Quilt => {
+			let installer = loader::download_quilt_installer(dir, proxy)?;
+			let installer = installer.file_name().try_expect("Quilt installer path has no file name")?.to_string_lossy();
+			Launch {
+				installed_marker: "quilt-server-launch.jar".into(),
+				install_cmd: Some(format!("java -jar {} install server {} {} --install-dir=. --download-server", installer, mc, version)),
+				unix_cmd: "java $JVM_ARGS -jar quilt-server-launch.jar nogui \"$@\"".into(),
+				windows_cmd: "java %JVM_ARGS% -jar quilt-server-launch.jar nogui %*".into()
+			}
+		}
+	};
+
+	if cfg!(windows) {
+		write_script(dir, "run.bat", &windows_script(&launch))
+	} else {
+		write_script(dir, "run.sh", &unix_script(&launch))
+	}
+}
+
+// forge moved to @argfiles in 1.17
+fn uses_forge_args_files(mc_version: &str) -> bool {
+	mc_version.split('.')
+		.nth(1)
+		.and_then(|minor| minor.parse::<u32>().ok())
+		.is_some_and(|minor| minor >= 17)
+}
+
+fn unix_script(launch: &Launch) -> String {
+	let mut script = format!("#!/usr/bin/env sh\nJVM_ARGS=\"{}\"\ncd \"$(dirname \"$0\")\"\n", JVM_ARGS);
+	if let Some(install) = &launch.install_cmd {
+		script.push_str(&format!("if [ ! -f {} ]; then\n\t{} || exit 1\nfi\n", launch.installed_marker, install));
+	}
+	script.push_str(&format!("exec {}\n", launch.unix_cmd));
+	script
+}
+
+fn windows_script(launch: &Launch) -> String {
+	let mut script = format!("@echo off\r\nset JVM_ARGS={}\r\ncd /d \"%~dp0\"\r\n", JVM_ARGS);
+	if let Some(install) = &launch.install_cmd {
+		script.push_str(&format!("if not exist {} (\r\n\t{} || exit /b 1\r\n)\r\n", launch.installed_marker.replace('/', "\\"), install));
+	}
+	script.push_str(&format!("{}\r\n", launch.windows_cmd));
+	script
+}
+
+fn write_script(dir: &Path, name: &str, contents: &str) -> Result<(), AppError> {
+	let mut path = dir.to_path_buf();
+	path.push(name);
+	atomic::write(&path, contents)?;
+	#[cfg(unix)]
+	{
+		use std::os::unix::fs::PermissionsExt;
+		fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).context(&format!("Error making {:?} executable", path))?;
+	}
+	Ok(())
+}
@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use crate::{api::{ApiError, Curseforge, Mod, ModLoaderType, Pagination}, output};
+
+// CF's biggest page, and how far into a result set it will go: index + pageSize can't pass MAX_RESULTS
+pub const MAX_PAGE_SIZE: u32 = 50;
+pub const MAX_RESULTS: u32 = 10_000;
+
+#[derive(Clone, Default, Debug)]
+pub struct SearchQuery {
+	pub text: Option<String>,
+	// None searches every class, mods and modpacks alike
+	pub class_id: Option<i32>,
+	pub game_version: Option<String>,
+	pub loader: Option<ModLoaderType>
+}
+
+impl SearchQuery {
+	// the filter part of the query string, each parameter with its leading &
+	pub(crate) fn params(&self) -> String {
+		let mut params = String::new();
+		if let Some(text) = &self.text {
+			params.push_str(&format!("&searchFilter={}", encode(text)));
+		}
+		if let Some(c) = self.class_id {
+			params.push_str(&format!("&classId={}", c));
+		}
+		if let Some(v) = &self.game_version {
+			params.push_str(&format!("&gameVersion={}", encode(v)));
+		}
+		if let Some(l) = self.loader {
+			params.push_str(&format!("&modLoaderType={}", u8::from(l)));
+		}
+		params
+	}
+}
+
+// the mods matching a search, fetched a page at a time as the iterator gets to them. pages are ordinary
+// API requests, so they wait on the client's rate limiter and get retried like any other.
+// an error ends the iteration after it's been returned
+pub struct SearchResults<'a> {
+	cf: &'a Curseforge,
+	query: SearchQuery,
+	limit: Option<usize>,
+	returned: usize,
+	next_index: u32,
+	page: VecDeque<Mod>,
+	pagination: Option<Pagination>,
+	done: bool
+}
+
+impl<'a> SearchResults<'a> {
+	pub(crate) fn new(cf: &'a Curseforge, query: SearchQuery, limit: Option<usize>) -> Self {
+		Self { cf, query, limit, returned: 0, next_index: 0, page: VecDeque::new(), pagination: None, done: false }
+	}
+
+	// from the last page fetched, None before the first one
+	pub fn pagination(&self) -> Option<Pagination> {
+		self.pagination
+	}
+
+	fn page_size(&self) -> u32 {
+		// no point asking for a full page when the limit is only a few results away
+		let wanted = self.limit.map_or(MAX_PAGE_SIZE as usize, |l| l - self.returned);
+		(wanted.min(MAX_PAGE_SIZE as usize) as u32).min(MAX_RESULTS - self.next_index)
+	}
+
+	fn fetch_page(&mut self) -> Result<(), ApiError> {
+		let (mods, pagination) = self.cf.search_page(&self.query, self.next_index, self.page_size())?;
+		log::debug!("Search page at {}: {} of {} results", pagination.index, pagination.result_count, pagination.total_count);
+		self.next_index += pagination.result_count;
+		// CF stops short of the total if it's over MAX_RESULTS, and an empty page means there's nothing more either way
+		if pagination.result_count == 0 || u64::from(self.next_index) >= pagination.total_count || self.next_index >= MAX_RESULTS {
+			if pagination.total_count > u64::from(MAX_RESULTS) && self.next_index >= MAX_RESULTS {
+				output::warn(format!("CF only returns the first {} of {} results, narrow the search to see the rest", MAX_RESULTS, pagination.total_count));
+			}
+			self.done = true;
+		}
+		self.page.extend(mods);
+		self.pagination = Some(pagination);
+		Ok(())
+	}
+}
+
+impl Iterator for SearchResults<'_> {
+	type Item = Result<Mod, ApiError>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.limit.is_some_and(|l| self.returned >= l) {
+			return None;
+		}
+		if self.page.is_empty() {
+			if self.done {
+				return None;
+			}
+			if let Err(e) = self.fetch_page() {
+				self.done = true;
+				return Some(Err(e));
+			}
+		}
+		let m = self.page.pop_front()?;
+		self.returned += 1;
+		Some(Ok(m))
+	}
+}
+
+// percent-encodes everything but the characters that are always safe in a query string
+pub(crate) fn encode(s: &str) -> String {
+	s.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+			_ => format!("%{:02X}", b)
+		})
+		.collect()
+}
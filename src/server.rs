@@ -0,0 +1,50 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{api::{Curseforge, CLASS_RESOURCE_PACKS, CLASS_SHADERS}, error::{AppError, ErrorContext}, manifest::FileInfo, output};
+
+const CLIENT_ONLY_OVERRIDES: &[&str] = &["resourcepacks/", "shaderpacks/"];
+
+// swaps in server files where CF has them and drops projects that only make sense on a client
+pub fn select_server_files(cf: &Curseforge, files: Vec<FileInfo>) -> Result<Vec<FileInfo>, AppError> {
+	let project_ids: Vec<i32> = files.iter().map(|f| f.project_id).collect();
+	let file_ids: Vec<i32> = files.iter().map(|f| f.file_id).collect();
+	let client_only: HashSet<i32> = cf.get_mods(&project_ids)
+		.context("Error getting mod metadata")?
+		.into_iter()
+		.filter(|m| matches!(m.class_id, Some(CLASS_RESOURCE_PACKS) | Some(CLASS_SHADERS)))
+		.map(|m| m.id)
+		.collect();
+	let server_files: HashMap<i32, Option<i32>> = cf.get_files(&file_ids)
+		.context("Error getting file metadata")?
+		.into_iter()
+		.map(|f| (f.id, f.server_pack_file_id))
+		.collect();
+
+	let mut selected = Vec::new();
+	let mut no_server_file = 0;
+	for mut file in files {
+		if client_only.contains(&file.project_id) {
+			output::info(format!("Skipping client-only project {}", file.project_id));
+			continue;
+		}
+		match server_files.get(&file.file_id) {
+			Some(Some(server_id)) => {
+				log::info!("Using server file {} for project {} instead of {}", server_id, file.project_id, file.file_id);
+				file.file_id = *server_id;
+			},
+			_ => {
+				log::debug!("No server file for project {}, using file {}", file.project_id, file.file_id);
+				no_server_file += 1;
+			}
+		}
+		selected.push(file);
+	}
+	if no_server_file > 0 {
+		output::warn(format!("{} mod(s) have no dedicated server file, using their regular files", no_server_file));
+	}
+	Ok(selected)
+}
+
+pub fn is_client_only_override(relative_path: &str) -> bool {
+	CLIENT_ONLY_OVERRIDES.iter().any(|p| relative_path.starts_with(p))
+}
@@ -0,0 +1,15 @@
+use std::path::Path;
+
+use crate::{error::{AppError, ErrorContext}, progress::format_bytes};
+
+// the install dir already exists by the time this runs, so its volume can be queried directly
+pub fn check(dir: &Path, required: u64) -> Result<(), AppError> {
+	let available = fs2::available_space(dir).context(&format!("Error checking free space in {:?}", dir))?;
+	log::info!("Install needs {} bytes, {} available in {:?}", required, available, dir);
+	if required > available {
+		return Err(AppError::Other(format!(
+			"Not enough disk space in {:?}: the install needs about {} but only {} is free (use --skip-space-check to install anyway)",
+			dir, format_bytes(required), format_bytes(available))));
+	}
+	Ok(())
+}
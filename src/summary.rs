@@ -0,0 +1,121 @@
+use std::{io::Write, path::Path};
+
+use crate::{atomic::AtomicFile, error::{AppError, ErrorContext}, output};
+
+pub struct ModResult {
+	pub project_id: i32,
+	pub file_id: i32,
+	pub outcome: Outcome,
+	// None if it failed
+	pub installed: Option<InstalledFile>
+}
+
+// a file the install wrote, with its path relative to the game dir ('/' separated)
+#[derive(Clone, Debug)]
+pub struct InstalledFile {
+	pub path: String,
+	pub sha1: String
+}
+
+impl InstalledFile {
+	pub(crate) fn new(game_dir: &Path, path: &Path, sha1: String) -> Self {
+		let rel = path.strip_prefix(game_dir).unwrap_or(path);
+		let path = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+		Self { path, sha1 }
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct InstalledMod {
+	pub project_id: i32,
+	pub file_id: i32,
+	pub file: InstalledFile
+}
+
+pub enum Outcome {
+	Downloaded,
+	Verified,
+	Failed(AppError)
+}
+
+#[derive(Default)]
+pub struct InstallSummary {
+	pub downloaded: usize,
+	pub verified: usize,
+	pub cancelled: usize,
+	// mods in the pack this install left out on purpose: filtered, client-only on a server, or --overrides-only
+	pub skipped: usize,
+	pub failures: Vec<ModResult>,
+	pub override_failures: Vec<(String, AppError)>,
+	// what was actually written
+	pub mods: Vec<InstalledMod>,
+	pub overrides: Vec<InstalledFile>
+}
+
+impl InstallSummary {
+	pub fn from_results(results: Vec<ModResult>, total: usize, overrides: Vec<InstalledFile>, override_failures: Vec<(String, AppError)>) -> Self {
+		let mut summary = Self {
+			cancelled: total - results.len(),
+			override_failures,
+			overrides,
+			..Self::default()
+		};
+		for mut result in results {
+			if let Some(file) = result.installed.take() {
+				summary.mods.push(InstalledMod { project_id: result.project_id, file_id: result.file_id, file });
+			}
+			match result.outcome {
+				Outcome::Downloaded => summary.downloaded += 1,
+				Outcome::Verified => {
+					summary.downloaded += 1;
+					summary.verified += 1;
+				},
+				Outcome::Failed(_) => summary.failures.push(result)
+			}
+		}
+		summary
+	}
+
+	pub fn failed_count(&self) -> usize {
+		self.failures.len() + self.override_failures.len() + self.cancelled
+	}
+
+	// mods and overrides both
+	pub fn installed(&self) -> impl Iterator<Item = &InstalledFile> {
+		self.mods.iter().map(|m| &m.file).chain(&self.overrides)
+	}
+
+	// in sha1sum's format, so `sha1sum -c` can check it from the game dir
+	pub fn write_sums(&self, path: &Path) -> Result<(), AppError> {
+		let mut files: Vec<&InstalledFile> = self.installed().collect();
+		files.sort_by(|a, b| a.path.cmp(&b.path));
+		let mut out = AtomicFile::create(path)?;
+		for file in files {
+			writeln!(out, "{}  {}", file.sha1, file.path).context(&format!("Error writing {:?}", path))?;
+		}
+		out.commit()
+	}
+
+	pub fn print(&self) {
+		let mut tally = format!("{} downloaded, {} skipped, {} failed", self.downloaded, self.skipped, self.failed_count());
+		if self.cancelled > 0 {
+			tally.push_str(&format!(" ({} cancelled)", self.cancelled));
+		}
+		if self.verified > 0 {
+			tally.push_str(&format!(", {} verified", self.verified));
+		}
+		if self.failed_count() > 0 {
+			output::warn(tally);
+		} else {
+			output::success(tally);
+		}
+		for failure in &self.failures {
+			if let Outcome::Failed(e) = &failure.outcome {
+				output::error(format!("  Failed: project {} file {}: {}", failure.project_id, failure.file_id, e));
+			}
+		}
+		for (name, e) in &self.override_failures {
+			output::error(format!("  Failed override {}: {}", name, e));
+		}
+	}
+}
@@ -1,147 +1,271 @@
-use std::{sync::{Arc, atomic::{AtomicU32, Ordering}, Condvar, Mutex}, thread};
+use std::{sync::{Arc, atomic::{AtomicBool, AtomicU32, Ordering}}, thread, time::{Duration, Instant}};
 
-use crossbeam_channel::Sender;
+use crossbeam_channel::Receiver;
+use parking_lot::{Condvar, Mutex};
 
-pub struct ThreadPool<T: Send + 'static> {
-	threadcount: u32,
-	send: Sender<ThreadCommand<T>>,
-	dead: bool
+// a pool that lives for one batch, so workers can borrow from the caller instead of
+// everything having to be 'static and cloned into each thread
+pub struct ScopedPool {
+	threadcount: u32
 }
 
-impl<T: Send + 'static> ThreadPool<T> {
-	pub fn new<W, C, I>(threadcount: u32, worker: W, init: I) -> ThreadPool<T>  where 
-			W: Fn(T, &mut C) + Clone + Send + 'static,
-			I: Fn() -> C + Clone + Send + 'static {
-		let (send, recv) = crossbeam_channel::unbounded();
-
-		for _ in 0..threadcount {
-			let thread_recv = recv.clone();
-			let thread_fn = worker.clone();
-			let thread_init = init.clone();
-			thread::spawn(move || {
-				let mut context = thread_init();
-				while let Ok(ThreadCommand::Command(t, countdown)) = thread_recv.recv() {
-					thread_fn(t, &mut context);
-					if let Some(c) = countdown {
-						c.countdown();
-					}
-				}
-			});
-		}
-
-		ThreadPool {
-			send, threadcount,
-			dead: false
-		}
-	}
-
-	pub fn send(&self, task: T) -> Result<Wait, T> {
-		if !self.dead {
-			let latch = Arc::new(CountdownLatch::new(1));
-			let _ = self.send.send(ThreadCommand::Command(task, Some(latch.clone())));
-			Ok(Wait::Latch(latch))
-		} else {
-			Err(task)
-		}
+impl ScopedPool {
+	pub fn new(threadcount: u32) -> Self {
+		Self { threadcount }
 	}
 
-	pub fn send_all(&self, tasks: Vec<T>) -> Result<Wait, Vec<T>> {
-		if !self.dead {
-			let latch = Arc::new(CountdownLatch::new(tasks.len() as u32));
-			for task in tasks {
-				let _ = self.send.send(ThreadCommand::Command(task, Some(latch.clone())));
-			}
-			Ok(Wait::Latch(latch))
-		} else {
-			Err(tasks)
+	// blocks until every task has run or been skipped because of cancellation
+	pub fn run<T, R, W>(&self, tasks: Vec<T>, cancel: &Cancel, worker: W) -> Vec<R> where
+			T: Send,
+			R: Send,
+			W: Fn(T) -> R + Sync {
+		if self.threadcount <= 1 {
+			return tasks.into_iter()
+				.take_while(|_| !cancel.is_cancelled())
+				.map(worker)
+				.collect();
 		}
+		self.run_watched(tasks, cancel, worker, Wait::wait)
 	}
 
-	pub fn die(&mut self) {
-		self.dead = true;
-		for _ in 0..self.threadcount {
-			let _ = self.send.send(ThreadCommand::Die);
+	// like run, but `watch` is called on this thread with a Wait for the batch while the workers get on with it.
+	// the workers are still joined after watch returns, so to give up on the batch early it has to cancel it
+	pub fn run_watched<T, R, W, V>(&self, tasks: Vec<T>, cancel: &Cancel, worker: W, watch: V) -> Vec<R> where
+			T: Send,
+			R: Send,
+			W: Fn(T) -> R + Sync,
+			V: FnOnce(&Wait) {
+		let latch = Arc::new(CountdownLatch::new(tasks.len() as u32));
+		let (task_send, task_recv) = crossbeam_channel::unbounded();
+		for task in tasks {
+			let _ = task_send.send(task);
 		}
+		drop(task_send);
+		let (results_send, results) = crossbeam_channel::unbounded();
+		thread::scope(|scope| {
+			for _ in 0..self.threadcount.max(1) {
+				let task_recv = task_recv.clone();
+				let results_send = results_send.clone();
+				let worker = &worker;
+				let latch = &latch;
+				scope.spawn(move || {
+					let _drain = DrainOnPanic { tasks: &task_recv, latch };
+					while let Ok(task) = task_recv.recv() {
+						let _done = CountOnDrop(latch);
+						if !cancel.is_cancelled() {
+							let _ = results_send.send(worker(task));
+						}
+					}
+				});
+			}
+			watch(&Wait(latch.clone()));
+		});
+		drop(results_send);
+		results.try_iter().collect()
 	}
 }
 
-impl<T: Send + 'static> Drop for ThreadPool<T> {
+// counts a task off even if it panicked
+struct CountOnDrop<'a>(&'a CountdownLatch);
+
+impl Drop for CountOnDrop<'_> {
 	fn drop(&mut self) {
-		self.die()
+		self.0.countdown();
 	}
 }
 
-pub enum BranchedExecutor<T: Send + 'static> {
-	ThisThread(Box<dyn Fn(T)>),
-	Pooled(ThreadPool<T>)
+// a panicking worker takes the rest of the queue with it, so the watcher isn't left waiting for tasks nobody
+// will run. the panic itself still comes out of thread::scope
+struct DrainOnPanic<'a, T> {
+	tasks: &'a Receiver<T>,
+	latch: &'a CountdownLatch
 }
 
-impl<T: Send + 'static> BranchedExecutor<T> {
-	pub fn exec(&self, commands: Vec<T>) -> Result<Wait, Vec<T>> {
-		match self {
-			Self::ThisThread(f) => {
-				for cmd in commands {
-					f(cmd);
-				}
-				Ok(Wait::Noop)
-			},
-			Self::Pooled(pool) => {
-				pool.send_all(commands)
+impl<T> Drop for DrainOnPanic<'_, T> {
+	fn drop(&mut self) {
+		if thread::panicking() {
+			while self.tasks.try_recv().is_ok() {
+				self.latch.countdown();
 			}
 		}
 	}
 }
 
-#[derive(Clone)]
-pub enum Wait {
-	Latch(Arc<CountdownLatch>),
-	Noop
+#[derive(Clone, Default)]
+pub struct Cancel(Arc<AtomicBool>);
+
+impl Cancel {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn cancel(&self) {
+		self.0.store(true, Ordering::Relaxed);
+	}
+
+	pub fn is_cancelled(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
 }
 
+// a batch's progress, for whoever's watching it while the workers run
+#[derive(Clone)]
+pub struct Wait(Arc<CountdownLatch>);
+
 impl Wait {
 	pub fn wait(&self) {
-		match self {
-			Self::Latch(latch) => latch.wait(),
-			Self::Noop => ()
+		self.0.wait()
+	}
+
+	pub fn total(&self) -> u32 {
+		self.0.total()
+	}
+
+	pub fn completed(&self) -> u32 {
+		self.0.total() - self.0.remaining()
+	}
+
+	// true if everything finished before the timeout
+	pub fn wait_timeout(&self, dur: Duration) -> bool {
+		self.0.wait_timeout(dur)
+	}
+}
+
+// caps how many of something can happen at once, independent of how many threads want to
+pub struct Semaphore {
+	permits: Mutex<u32>,
+	var: Condvar
+}
+
+impl Semaphore {
+	pub fn new(permits: u32) -> Self {
+		Self {
+			permits: Mutex::new(permits),
+			var: Condvar::new()
 		}
 	}
+
+	pub fn acquire(&self) -> Permit<'_> {
+		let mut permits = self.permits.lock();
+		while *permits == 0 {
+			self.var.wait(&mut permits);
+		}
+		*permits -= 1;
+		Permit(self)
+	}
+}
+
+pub struct Permit<'a>(&'a Semaphore);
+
+impl Drop for Permit<'_> {
+	fn drop(&mut self) {
+		*self.0.permits.lock() += 1;
+		self.0.var.notify_one();
+	}
 }
 
 pub struct CountdownLatch {
 	status: Mutex<bool>,
 	var: Condvar,
-	count: AtomicU32 // don't use a Mutex<u32> so we can decrement without locking
+	count: AtomicU32, // don't use a Mutex<u32> so we can decrement without locking
+	total: u32
 }
 
 impl CountdownLatch {
 	pub fn new(count: u32) -> Self {
 		Self {
-			status: Mutex::new(false),
+			status: Mutex::new(count == 0),
 			var: Condvar::new(),
-			count: AtomicU32::new(count)
+			count: AtomicU32::new(count),
+			total: count
 		}
 	}
 
+	pub fn total(&self) -> u32 {
+		self.total
+	}
+
+	pub fn remaining(&self) -> u32 {
+		self.count.load(Ordering::Acquire)
+	}
+
+	// status only ever flips under the lock, so checking it under the lock can't miss the notify,
+	// and the loop takes care of spurious wakeups
 	pub fn wait(&self) {
-		if self.count.load(Ordering::Relaxed) > 0 {
-			let status_guard = self.status.lock().expect("Poisoned latch mutex!");
-			if !*status_guard {
-				let _ = self.var.wait(status_guard).expect("Poisoned latch mutex!");
+		let mut status_guard = self.status.lock();
+		while !*status_guard {
+			self.var.wait(&mut status_guard);
+		}
+	}
+
+	pub fn wait_timeout(&self, dur: Duration) -> bool {
+		// a timeout too far off to represent is as good as none
+		let deadline = match Instant::now().checked_add(dur) {
+			Some(d) => d,
+			None => {
+				self.wait();
+				return true;
+			}
+		};
+		let mut status_guard = self.status.lock();
+		while !*status_guard {
+			let left = deadline.saturating_duration_since(Instant::now());
+			if left.is_zero() || self.var.wait_for(&mut status_guard, left).timed_out() {
+				return *status_guard;
 			}
 		}
+		true
 	}
 
 	pub fn countdown(&self) {
-		let cur_count = self.count.fetch_sub(1, Ordering::Relaxed) - 1;
-		if cur_count <= 0 {
-			let mut status_guard = self.status.lock().expect("Poisoned latch mutex!");
+		let prev_count = self.count.fetch_sub(1, Ordering::AcqRel);
+		if prev_count == 1 {
+			let mut status_guard = self.status.lock();
 			*status_guard = true;
 			self.var.notify_all();
 		}
 	}
 }
 
-enum ThreadCommand<T: Send + 'static> {
-	Command(T, Option<Arc<CountdownLatch>>),
-	Die
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+	use std::panic::{self, AssertUnwindSafe};
+
+	use super::*;
+
+	// the panic still comes out of run, but only once the other workers have drained the queue, rather than
+	// the batch hanging on a task that never counted down
+	#[test]
+	fn panicking_task_doesnt_hang_the_batch() {
+		let pool = ScopedPool::new(4);
+		let result = panic::catch_unwind(AssertUnwindSafe(|| pool.run((0..64).collect(), &Cancel::new(), |i: u32| {
+			if i == 5 {
+				panic!("task {} failed", i);
+			}
+			thread::sleep(Duration::from_millis(1));
+			i
+		})));
+		assert!(result.is_err());
+	}
+
+	// lots of short rounds, so a countdown racing a waiter checking the status gets plenty of chances to lose the notify
+	#[test]
+	fn latch_waiters_see_every_countdown() {
+		for _ in 0..200 {
+			let latch = Arc::new(CountdownLatch::new(32));
+			thread::scope(|scope| {
+				let waiters: Vec<_> = (0..4).map(|_| scope.spawn(|| {
+					latch.wait();
+					latch.remaining()
+				})).collect();
+				for _ in 0..32 {
+					scope.spawn(|| latch.countdown());
+				}
+				for waiter in waiters {
+					assert_eq!(waiter.join().unwrap(), 0);
+				}
+			});
+			assert!(latch.wait_timeout(Duration::ZERO));
+		}
+	}
+}
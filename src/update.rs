@@ -0,0 +1,259 @@
+use std::{collections::{HashMap, HashSet}, fs, io::{Read, Seek}, path::{Path, PathBuf}, time::{SystemTime, UNIX_EPOCH}};
+
+use zip::ZipArchive;
+
+use crate::{api::{Curseforge, Mod}, atomic, edit::{self, FileChoice}, error::{AppError, ErrorContext, TryExpect}, hash, install::{self, DownloadSettings}, manifest::{FileInfo, Manifest}, output, progress::format_bytes, record::{self, InstallRecord, RecordedMod}, summary::InstallSummary, threadpool::Cancel, try_mkdir, try_read_zip_entry};
+
+// where update keeps what it replaces until it's done, under the game dir
+pub const BACKUP_DIR: &str = ".backup";
+
+// the difference between what installed.json says is in a game dir and what a new version of the pack wants there
+pub struct UpdatePlan {
+	pub unchanged: usize,
+	pub download: Vec<FileInfo>,
+	// the jars being replaced by a different file of the same project, and those of projects the pack dropped
+	pub superseded: Vec<RecordedMod>,
+	pub removed: Vec<RecordedMod>,
+	// zip entries that are new, or different from what was last extracted. an override the pack hasn't
+	// changed is left alone, even if it's been edited since
+	pub overrides: Vec<String>,
+	// by CF's file sizes, only known once size_downloads has asked it
+	pub download_bytes: Option<u64>,
+	pub override_bytes: u64
+}
+
+impl UpdatePlan {
+	pub fn is_empty(&self) -> bool {
+		self.download.is_empty() && self.superseded.is_empty() && self.removed.is_empty() && self.overrides.is_empty()
+	}
+
+	pub fn size_downloads(&mut self, cf: &Curseforge) -> Result<(), AppError> {
+		let file_ids: Vec<i32> = self.download.iter().map(|f| f.file_id).collect();
+		let files = cf.get_files(&file_ids).context("Error getting file metadata")?;
+		self.download_bytes = Some(files.iter().map(|f| f.file_length.max(0) as u64).sum());
+		Ok(())
+	}
+
+	pub fn print(&self) {
+		for file in &self.download {
+			output::info(format!("  + project {} file {}", file.project_id, file.file_id));
+		}
+		for m in self.superseded.iter().chain(&self.removed) {
+			output::info(format!("  - {}", m.path));
+		}
+		let downloads = match self.download_bytes {
+			Some(bytes) => format!("{} mods to download ({})", self.download.len(), format_bytes(bytes)),
+			None => format!("{} mods to download", self.download.len())
+		};
+		output::info(format!("{}, {} to delete, {} unchanged, {} overrides to extract ({})",
+			downloads, self.superseded.len() + self.removed.len(), self.unchanged, self.overrides.len(), format_bytes(self.override_bytes)));
+	}
+}
+
+pub fn plan<T: Read + Seek>(record: &InstallRecord, manifest: &Manifest, pack: &mut ZipArchive<T>, game_dir: &Path) -> Result<UpdatePlan, AppError> {
+	let mut recorded: HashMap<i32, Vec<&RecordedMod>> = HashMap::new();
+	for m in &record.mods {
+		recorded.entry(m.project_id).or_default().push(m);
+	}
+	let mut plan = UpdatePlan {
+		unchanged: 0,
+		download: Vec::new(),
+		superseded: Vec::new(),
+		removed: Vec::new(),
+		overrides: Vec::new(),
+		download_bytes: None,
+		override_bytes: 0
+	};
+	let wanted: HashSet<i32> = manifest.files.iter().map(|f| f.project_id).collect();
+	for file in &manifest.files {
+		let previous = recorded.get(&file.project_id).map(Vec::as_slice).unwrap_or_default();
+		// a jar that's gone missing since gets downloaded again, same file or not
+		let current = previous.iter().any(|m| m.file_id == file.file_id && game_dir.join(&m.path).is_file());
+		if current {
+			plan.unchanged += 1;
+		} else {
+			plan.download.push(file.clone());
+		}
+		plan.superseded.extend(previous.iter().filter(|m| m.file_id != file.file_id).map(|m| (*m).clone()));
+	}
+	plan.removed = record.mods.iter().filter(|m| !wanted.contains(&m.project_id)).cloned().collect();
+
+	let prefix = format!("{}/", manifest.overrides);
+	let extracted: HashMap<&str, &str> = record.overrides.iter().map(|f| (f.path.as_str(), f.sha1.as_str())).collect();
+	for fname in install::override_entries(pack, manifest, record.server) {
+		if fname.ends_with('/') {
+			continue;
+		}
+		let rel = fname.strip_prefix(&prefix).unwrap_or(&fname);
+		if let Some(previous) = extracted.get(rel) {
+			let mut hasher = hash::Sha1Stream::new();
+			hasher.update_from(try_read_zip_entry(pack, &fname)?).context(&format!("Error reading {}", fname))?;
+			if hasher.hex() == *previous && game_dir.join(rel).is_file() {
+				continue;
+			}
+		}
+		plan.overrides.push(fname);
+	}
+	plan.override_bytes = install::override_size(pack, &plan.overrides);
+	Ok(plan)
+}
+
+// moves every mod in the manifest to its newest file that fits `choice`, for updating to the latest of
+// everything rather than to what the pack pins. the manifest is only changed once every mod has a file,
+// so one without any leaves it as it was. returns how many mods got a different file
+pub fn pick_latest(cf: &Curseforge, manifest: &mut Manifest, choice: &FileChoice) -> Result<usize, AppError> {
+	let ids: Vec<i32> = manifest.files.iter().map(|f| f.project_id).collect();
+	let projects: HashMap<i32, Mod> = cf.get_mods(&ids).context("Error getting the pack's projects")?
+		.into_iter()
+		.map(|m| (m.id, m))
+		.collect();
+	let mut picked = Vec::with_capacity(manifest.files.len());
+	for file in &manifest.files {
+		let project = projects.get(&file.project_id).try_expect(&format!("CF didn't return project {}", file.project_id))?;
+		picked.push((project.name.clone(), edit::newest_file(cf, project, manifest, choice)?));
+	}
+	let mut changed = 0;
+	for (file, (name, file_id)) in manifest.files.iter_mut().zip(picked) {
+		if file.file_id != file_id {
+			output::info(format!("  {}: file {} -> {}", name, file.file_id, file_id));
+			file.file_id = file_id;
+			changed += 1;
+		}
+	}
+	Ok(changed)
+}
+
+#[derive(Clone, Default)]
+pub struct UpdateOptions {
+	pub download: DownloadSettings,
+	// leave the replaced files in the backup folder once the update has worked
+	pub keep_backup: bool
+}
+
+// the update happens in this order, so that it can be undone at any point before it's finished:
+// 1. everything about to be replaced or deleted is moved into a backup folder
+// 2. the new mods are downloaded and the overrides extracted
+// 3. installed.json is updated
+// 4. the backup is thrown away (or kept, with keep_backup)
+// if anything fails or is cancelled before 4, whatever was written is deleted, the backup is moved back, and
+// installed.json is left as it was. a failed summary is still Ok, it's just been rolled back
+pub fn apply<T: Read + Seek>(cf: &Curseforge, plan: UpdatePlan, manifest: &Manifest, pack: &mut ZipArchive<T>, game_dir: &Path, options: &UpdateOptions, cancel: &Cancel) -> Result<InstallSummary, AppError> {
+	let before = InstallRecord::read(game_dir)?.unwrap_or_default();
+	let mut backup = Backup::new(game_dir)?;
+	let prefix = format!("{}/", manifest.overrides);
+	let replaced = plan.superseded.iter().chain(&plan.removed).map(|m| m.path.as_str())
+		.chain(plan.overrides.iter().map(|f| f.strip_prefix(&prefix).unwrap_or(f)));
+	for rel in replaced {
+		if let Err(e) = backup.stash(rel) {
+			backup.restore()?;
+			return Err(e);
+		}
+	}
+	if !backup.stashed.is_empty() {
+		output::info(format!("Moved {} files to be replaced into {:?}", backup.stashed.len(), backup.dir));
+	}
+
+	let total = plan.download.len();
+	let summary = install::download_files(cf, plan.download, game_dir, HashMap::new(), &options.download, cancel)
+		.and_then(|results| {
+			let (extracted, override_failures) = install::extract_overrides(pack, plan.overrides, &manifest.overrides, game_dir, false, cancel, None)?;
+			Ok(InstallSummary::from_results(results, total, extracted, override_failures))
+		});
+	let summary = match summary {
+		Ok(s) if s.failed_count() == 0 => s,
+		Ok(s) => {
+			rollback(&backup, &s, game_dir)?;
+			return Ok(s);
+		},
+		Err(e) => {
+			backup.restore()?;
+			output::warn("Update failed, nothing was changed");
+			return Err(e);
+		}
+	};
+
+	let mut record = before;
+	let stashed: HashSet<&String> = backup.stashed.iter().collect();
+	record.mods.retain(|m| !stashed.contains(&m.path));
+	record.merge(manifest, &summary, record.server);
+	if let Err(e) = record.write(game_dir) {
+		rollback(&backup, &summary, game_dir)?;
+		return Err(e);
+	}
+	for m in plan.superseded.iter().chain(&plan.removed) {
+		output::info(format!("Removed {}", m.path));
+	}
+	if options.keep_backup {
+		output::info(format!("Kept the replaced files in {:?}", backup.dir));
+	} else {
+		backup.discard();
+	}
+	Ok(summary)
+}
+
+// puts the game dir back how it was before the update. installed.json is only written once everything else
+// has worked, so only files need undoing
+fn rollback(backup: &Backup, summary: &InstallSummary, game_dir: &Path) -> Result<(), AppError> {
+	output::warn("Update failed, rolling it back");
+	let base = game_dir.canonicalize().context(&format!("Error resolving {:?}", game_dir))?;
+	for file in summary.installed() {
+		// anything that replaced a stashed file is overwritten when that's restored
+		if backup.stashed.contains(&file.path) {
+			continue;
+		}
+		if let Some(path) = record::contained(&base, &file.path) {
+			fs::remove_file(&path).context(&format!("Error deleting {:?}", path))?;
+		}
+	}
+	backup.restore()?;
+	output::info("Rolled back, the install is as it was before the update");
+	Ok(())
+}
+
+// files moved out of the way while an update runs, kept under the game dir so moving them is just a rename
+struct Backup {
+	game_dir: PathBuf,
+	dir: PathBuf,
+	// relative to both game_dir and dir
+	stashed: Vec<String>
+}
+
+impl Backup {
+	fn new(game_dir: &Path) -> Result<Self, AppError> {
+		let stamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+		Ok(Self {
+			game_dir: game_dir.canonicalize().context(&format!("Error resolving {:?}", game_dir))?,
+			dir: game_dir.join(BACKUP_DIR).join(stamp.to_string()),
+			stashed: Vec::new()
+		})
+	}
+
+	fn stash(&mut self, rel: &str) -> Result<(), AppError> {
+		let from = record::contained(&self.game_dir, rel)
+			.ok_or_else(|| AppError::Other(format!("Refusing to touch {}, it's outside {:?}", rel, self.game_dir)))?;
+		if !from.is_file() {
+			return Ok(());
+		}
+		let to = self.dir.join(rel);
+		try_mkdir(to.parent().try_expect("Backup path has no parent directory")?)?;
+		fs::rename(&from, &to).context(&format!("Error moving {:?} to {:?}", from, to))?;
+		self.stashed.push(rel.to_string());
+		Ok(())
+	}
+
+	fn restore(&self) -> Result<(), AppError> {
+		for rel in &self.stashed {
+			let (from, to) = (self.dir.join(rel), self.game_dir.join(rel));
+			try_mkdir(to.parent().try_expect("Restored path has no parent directory")?)?;
+			atomic::replace(&from, &to).context(&format!("Error restoring {:?} from {:?}", to, from))?;
+		}
+		self.discard();
+		Ok(())
+	}
+
+	fn discard(&self) {
+		let _ = fs::remove_dir_all(&self.dir);
+		// only goes if there are no backups kept from earlier updates
+		let _ = fs::remove_dir(self.game_dir.join(BACKUP_DIR));
+	}
+}
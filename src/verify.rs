@@ -0,0 +1,184 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}};
+
+use serde::Serialize;
+
+use crate::{api::{self, ApiError, Curseforge, HashAlgo}, error::{AppError, ErrorContext}, hash, install::{self, Parallelism}, instance, manifest::{FileInfo, Manifest}, output, threadpool::{Cancel, ScopedPool}};
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+	Ok,
+	Missing,
+	Corrupt,
+	// CF wouldn't say what the file should be, so there was nothing to check it against
+	Unresolved
+}
+
+#[derive(Serialize)]
+pub struct FileReport {
+	#[serde(rename = "projectId")]
+	pub project_id: i32,
+	#[serde(rename = "fileId")]
+	pub file_id: i32,
+	#[serde(rename = "fileName")]
+	pub file_name: String,
+	pub sha1: Option<String>,
+	pub status: FileStatus,
+	// why an unresolved file couldn't be looked up
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>
+}
+
+#[derive(Serialize, Default)]
+pub struct VerifyReport {
+	pub ok: usize,
+	pub missing: usize,
+	pub corrupt: usize,
+	pub unresolved: usize,
+	pub files: Vec<FileReport>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub extras: Vec<String>
+}
+
+impl VerifyReport {
+	pub fn is_clean(&self) -> bool {
+		self.missing == 0 && self.corrupt == 0 && self.unresolved == 0
+	}
+
+	pub fn tally(&self) -> String {
+		let tally = format!("{} ok, {} missing, {} corrupt", self.ok, self.missing, self.corrupt);
+		match self.unresolved {
+			0 => tally,
+			n => format!("{}, {} couldn't be checked", tally, n)
+		}
+	}
+
+	pub fn print(&self) {
+		for file in &self.files {
+			match file.status {
+				FileStatus::Ok => {},
+				FileStatus::Missing => output::warn(format!("  Missing: {} (project {} file {})", file.file_name, file.project_id, file.file_id)),
+				FileStatus::Corrupt => output::error(format!("  Corrupt: {} (project {} file {})", file.file_name, file.project_id, file.file_id)),
+				FileStatus::Unresolved => output::error(format!("  Couldn't check: project {} file {} ({})", file.project_id, file.file_id, file.error.as_deref().unwrap_or("unknown error")))
+			}
+		}
+		for extra in &self.extras {
+			output::info(format!("  Extra: {}", extra));
+		}
+		let tally = self.tally();
+		if self.is_clean() {
+			output::success(tally);
+		} else {
+			output::warn(tally);
+		}
+	}
+}
+
+pub fn mods_dir(install_dir: &Path) -> PathBuf {
+	instance::detect_game_dir(install_dir).join("mods")
+}
+
+// the pack's files as CF describes them, and the ones it couldn't find. a file CF leaves out of the batch
+// still gets looked up on its own, and one that can't be is kept here rather than dropped, so it can't pass
+// for checked or for an extra
+pub struct PackFiles {
+	pub files: Vec<api::File>,
+	pub unresolved: Vec<(FileInfo, ApiError)>,
+	classes: HashMap<i32, i32>
+}
+
+pub fn resolve(cf: &Curseforge, manifest: &Manifest, parallel: Parallelism) -> Result<PackFiles, AppError> {
+	let classes = install::resolve_classes(cf, &manifest.files);
+	let threads = parallel.threads(manifest.files.len());
+	let (mut metadata, unresolved) = install::try_resolve_metadata(cf, &manifest.files, threads, &Cancel::new())?;
+	let files = manifest.files.iter().filter_map(|f| metadata.remove(&f.file_id)).collect();
+	Ok(PackFiles { files, unresolved, classes })
+}
+
+pub fn verify(cf: &Curseforge, manifest: &Manifest, install_dir: &Path, parallel: Parallelism) -> Result<VerifyReport, AppError> {
+	Ok(check(&resolve(cf, manifest, parallel)?, install_dir, parallel))
+}
+
+// each jar is hashed on its own pool thread, a big pack's worth of reading adds up otherwise
+pub fn check(pack: &PackFiles, install_dir: &Path, parallel: Parallelism) -> VerifyReport {
+	let game_dir = instance::detect_game_dir(install_dir);
+	let indexed: Vec<(usize, &api::File)> = pack.files.iter().enumerate().collect();
+	let mut checked = ScopedPool::new(parallel.threads(indexed.len())).run(indexed, &Cancel::new(), |(i, file)| {
+		let path = game_dir.join(instance::content_folder(pack.classes.get(&file.mod_id).copied())).join(&file.file_name);
+		(i, check_file(file, &path))
+	});
+	// the pool hands results back in whatever order they finished
+	checked.sort_by_key(|(i, _)| *i);
+
+	let mut report = VerifyReport::default();
+	for (_, file) in checked {
+		match file.status {
+			FileStatus::Ok => report.ok += 1,
+			FileStatus::Missing => report.missing += 1,
+			FileStatus::Corrupt => report.corrupt += 1,
+			FileStatus::Unresolved => report.unresolved += 1
+		}
+		report.files.push(file);
+	}
+	for (file, e) in &pack.unresolved {
+		report.unresolved += 1;
+		report.files.push(FileReport {
+			project_id: file.project_id,
+			file_id: file.file_id,
+			file_name: String::new(),
+			sha1: None,
+			status: FileStatus::Unresolved,
+			error: Some(e.to_string())
+		});
+	}
+	report
+}
+
+fn check_file(file: &api::File, path: &Path) -> FileReport {
+	let sha1 = file.hashes.iter().find(|h| h.algo == HashAlgo::Sha1).map(|h| h.value.to_lowercase());
+	// streamed, so each pool thread only holds a buffer's worth of its jar rather than all of it
+	let status = match fs::File::open(path) {
+		Err(_) => FileStatus::Missing,
+		Ok(jar) => {
+			let intact = match &sha1 {
+				Some(h) => {
+					let mut hasher = hash::Sha1Stream::new();
+					hasher.update_from(jar).is_ok() && hasher.hex().eq_ignore_ascii_case(h)
+				},
+				// no sha1 on record, the length is the best we can do
+				None => jar.metadata().is_ok_and(|m| m.len() as i64 == file.file_length)
+			};
+			if intact { FileStatus::Ok } else { FileStatus::Corrupt }
+		}
+	};
+	FileReport { project_id: file.mod_id, file_id: file.id, file_name: file.file_name.clone(), sha1, status, error: None }
+}
+
+// only looks at the top level of mods/, config folders and the like in there aren't ours to judge. the jar of a
+// file in pack.unresolved can't be told apart from an extra, so check that's empty before deleting any
+pub fn find_extras(install_dir: &Path, pack: &PackFiles) -> Result<Vec<String>, AppError> {
+	let mods_dir = mods_dir(install_dir);
+	let mut extras = Vec::new();
+	for entry in fs::read_dir(&mods_dir).context(&format!("Error listing {:?}", mods_dir))? {
+		let entry = entry.context(&format!("Error listing {:?}", mods_dir))?;
+		if !entry.file_type().context(&format!("Error reading {:?}", entry.path()))?.is_file() {
+			continue;
+		}
+		let name = entry.file_name().to_string_lossy().into_owned();
+		if !pack.files.iter().any(|f| f.file_name == name) {
+			extras.push(name);
+		}
+	}
+	extras.sort();
+	Ok(extras)
+}
+
+pub fn prune(install_dir: &Path, extras: &[String]) -> Result<(), AppError> {
+	let mods_dir = mods_dir(install_dir);
+	for name in extras {
+		let path = mods_dir.join(name);
+		fs::remove_file(&path).context(&format!("Error deleting {:?}", path))?;
+		output::info(format!("Deleted {}", name));
+	}
+	Ok(())
+}